@@ -0,0 +1,166 @@
+//! Client side of the GURT wire protocol: the HANDSHAKE, request encoding and response
+//! framing. Connections, TLS, retries and redirects are left to the caller.
+
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::handshake::PROTO_VERSION;
+use crate::limits::{enforce_max_message_size, LimitError};
+
+pub const DEFAULT_PORT: u16 = 4878;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientResponse {
+    pub code: u16,
+    /// Header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ClientResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WireError {
+    #[error("connection closed")]
+    Closed,
+    #[error("malformed message")]
+    Malformed,
+    #[error(transparent)]
+    TooLarge(#[from] LimitError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// `HANDSHAKE` request opening a connection to `host`.
+pub fn encode_handshake(host: &str, user_agent: Option<&str>) -> Vec<u8> {
+    let mut req = format!("HANDSHAKE / {}\r\nhost: {}\r\n", PROTO_VERSION, host);
+    if let Some(ua) = user_agent {
+        req.push_str(&format!("user-agent: {}\r\n", ua));
+    }
+    req.push_str("\r\n");
+    req.into_bytes()
+}
+
+/// Request head followed by `body`. A content-length is added for a non-empty body
+/// unless `headers` carries one.
+pub fn encode_request(
+    method: &str,
+    path: &str,
+    host: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Vec<u8> {
+    let mut req = format!(
+        "{} {} {}\r\nhost: {}\r\n",
+        method, path, PROTO_VERSION, host
+    );
+    for (name, value) in headers {
+        req.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let has_length = headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("content-length"));
+    if !body.is_empty() && !has_length {
+        req.push_str(&format!("content-length: {}\r\n", body.len()));
+    }
+    req.push_str("\r\n");
+    let mut bytes = req.into_bytes();
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Read one response, reading the head `chunk` bytes at a time. The body runs to its
+/// content-length; without one, to the end of the stream or until `read_idle` passes
+/// without data. 1xx, 204 and 304 responses have no body.
+///
+/// Also returns whether the response ended exactly where its framing said, i.e. the
+/// stream is positioned at the next response and may be reused.
+pub async fn read_response<S>(
+    stream: &mut S,
+    chunk: usize,
+    read_idle: Duration,
+) -> Result<(ClientResponse, bool), WireError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf: Vec<u8> = Vec::with_capacity(4096);
+    let mut tmp = vec![0u8; chunk.max(1)];
+    let header_end = loop {
+        let n = stream.read(&mut tmp).await?;
+        if n == 0 {
+            return Err(WireError::Closed);
+        }
+        buf.extend_from_slice(&tmp[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        enforce_max_message_size(buf.len())?;
+    };
+    let (head, rest) = buf.split_at(header_end + 4);
+    let head = std::str::from_utf8(head).map_err(|_| WireError::Malformed)?;
+    let mut lines = head.split("\r\n");
+    let mut status = lines.next().unwrap_or("").split_whitespace();
+    let _version = status.next();
+    let code = status
+        .next()
+        .and_then(|c| c.parse::<u16>().ok())
+        .ok_or(WireError::Malformed)?;
+    let mut headers = Vec::new();
+    let mut content_length: Option<usize> = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().to_string();
+            if name == "content-length" {
+                content_length = value.parse().ok();
+            }
+            headers.push((name, value));
+        }
+    }
+
+    let mut body = rest.to_vec();
+    let mut tmp = [0u8; 4096];
+    let framed = if (100..200).contains(&code) || code == 204 || code == 304 {
+        let framed = body.is_empty();
+        body.clear();
+        framed
+    } else if let Some(len) = content_length {
+        enforce_max_message_size(header_end + 4 + len)?;
+        while body.len() < len {
+            let n = stream.read(&mut tmp).await?;
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&tmp[..n]);
+        }
+        let framed = body.len() == len;
+        body.truncate(len);
+        framed
+    } else {
+        loop {
+            match tokio::time::timeout(read_idle, stream.read(&mut tmp)).await {
+                Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+                Ok(Ok(n)) => body.extend_from_slice(&tmp[..n]),
+            }
+            enforce_max_message_size(header_end + 4 + body.len())?;
+        }
+        false
+    };
+    Ok((
+        ClientResponse {
+            code,
+            headers,
+            body,
+        },
+        framed,
+    ))
+}
@@ -2,7 +2,7 @@ use anyhow::{anyhow, Result};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 const MAX_HANDSHAKE_BYTES: usize = 8 * 1024; // 8KB cap
-const PROTO_VERSION: &str = "GURT/1.0.0";
+pub const PROTO_VERSION: &str = "GURT/1.0.0";
 
 pub async fn read_and_respond_handshake<S>(stream: &mut S) -> Result<()>
 where
@@ -29,8 +29,7 @@ where
     // Track where to resume scanning for CRLFCRLF to avoid O(n^2) rescans
     let mut search_from: usize = 0;
     let finder = Finder::new(b"\r\n\r\n");
-    let mut header_end: Option<usize> = None;
-    loop {
+    let header_end = loop {
         let n = stream
             .read(&mut tmp)
             .await
@@ -43,16 +42,14 @@ where
         if buf.len() > MAX_MESSAGE_BYTES {
             return Err(StatusCode::RequestEntityTooLarge);
         }
-    // Only scan newly appended region (with overlap for boundary cases)
-    // Note: tempting to "simplify" by rescanning the whole buffer each time — don't; it turns into O(n^2) under slow IO.
+        // Only scan newly appended region (with overlap for boundary cases)
+        // Note: tempting to "simplify" by rescanning the whole buffer each time — don't; it turns into O(n^2) under slow IO.
         let start = search_from.saturating_sub(3);
         if let Some(rel) = finder.find(&buf[start..]) {
-            header_end = Some(start + rel);
-            break;
+            break start + rel;
         }
         search_from = before_len + n;
-    }
-    let header_end = header_end.ok_or(StatusCode::BadRequest)?;
+    };
     let (head, rest) = buf.split_at(header_end + 4);
     let head_str = std::str::from_utf8(head).map_err(|_| StatusCode::BadRequest)?;
     let mut lines = head_str.split("\r\n");
@@ -92,7 +89,7 @@ where
             return Err(StatusCode::RequestEntityTooLarge);
         }
         if !rest.is_empty() {
-            body.extend_from_slice(&rest);
+            body.extend_from_slice(rest);
         }
         while body.len() < content_length {
             let mut chunk = [0u8; 4096];
@@ -133,6 +130,7 @@ impl Response {
 pub fn make_response(code: StatusCode, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
     let reason = match code {
         StatusCode::Ok => "OK",
+        StatusCode::NotModified => "NOT_MODIFIED",
         StatusCode::BadRequest => "BAD_REQUEST",
         StatusCode::TooManyRequests => "TOO_MANY_REQUESTS",
         StatusCode::RequestEntityTooLarge => "TOO_LARGE",
@@ -1,3 +1,4 @@
+pub mod client;
 pub mod gurt;
 pub mod handshake;
 pub mod http_like;
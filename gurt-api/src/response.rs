@@ -6,6 +6,8 @@ pub struct SearchResultItem {
     pub title: String,
     pub url: String,
     pub score: f32,
+    /// HTML-safe excerpt of the page; matched query terms are wrapped in `<b>`.
+    pub snippet: String,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
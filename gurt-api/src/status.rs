@@ -1,6 +1,7 @@
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatusCode {
     Ok,
+    NotModified,
     BadRequest,
     TooManyRequests,
     RequestEntityTooLarge,
@@ -11,10 +12,23 @@ impl StatusCode {
     pub fn as_u16(self) -> u16 {
         match self {
             StatusCode::Ok => 200,
+            StatusCode::NotModified => 304,
             StatusCode::BadRequest => 400,
             StatusCode::TooManyRequests => 429,
             StatusCode::RequestEntityTooLarge => 413,
             StatusCode::InternalServerError => 500,
         }
     }
+
+    pub fn from_u16(code: u16) -> Option<Self> {
+        match code {
+            200 => Some(StatusCode::Ok),
+            304 => Some(StatusCode::NotModified),
+            400 => Some(StatusCode::BadRequest),
+            429 => Some(StatusCode::TooManyRequests),
+            413 => Some(StatusCode::RequestEntityTooLarge),
+            500 => Some(StatusCode::InternalServerError),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,4 @@
+ALTER TABLE crawl_queue
+    ADD COLUMN depth INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN last_error TEXT,
+    ADD CONSTRAINT crawl_queue_depth_check CHECK (depth >= 0);
@@ -0,0 +1,5 @@
+ALTER TABLE domains
+    ADD COLUMN cname_chain TEXT[],
+    ADD COLUMN cname_depth SMALLINT,
+    ADD COLUMN dns_checked_at TIMESTAMPTZ,
+    ADD CONSTRAINT domains_cname_depth_check CHECK (cname_depth IS NULL OR cname_depth >= 0);
@@ -0,0 +1,13 @@
+ALTER TABLE recrawl_queue
+    ADD COLUMN reason TEXT,
+    ADD COLUMN max_attempts INTEGER NOT NULL DEFAULT 3,
+    ADD COLUMN last_error TEXT,
+    ADD CONSTRAINT recrawl_queue_max_attempts_check CHECK (max_attempts > 0);
+
+ALTER TABLE urls
+    ADD COLUMN render_outcome TEXT,
+    ADD COLUMN render_attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN rendered_at TIMESTAMPTZ,
+    ADD CONSTRAINT urls_render_outcome_check
+        CHECK (render_outcome IS NULL OR render_outcome IN ('deferred', 'rendered', 'gave_up')),
+    ADD CONSTRAINT urls_render_attempts_check CHECK (render_attempts >= 0);
@@ -0,0 +1,16 @@
+-- recrawl_queue holds two kinds of rows per URL: the periodic recrawl ('recrawl') and
+-- a pending deferred re-render ('render').
+ALTER TABLE recrawl_queue
+    ADD COLUMN kind TEXT NOT NULL DEFAULT 'recrawl',
+    ADD COLUMN depth INTEGER NOT NULL DEFAULT 0,
+    ADD CONSTRAINT recrawl_queue_kind_check CHECK (kind IN ('recrawl', 'render')),
+    ADD CONSTRAINT recrawl_queue_depth_check CHECK (depth >= 0);
+
+UPDATE recrawl_queue SET kind = 'render' WHERE reason IS NOT NULL;
+
+ALTER TABLE recrawl_queue
+    DROP CONSTRAINT recrawl_queue_url_id_key,
+    ADD CONSTRAINT recrawl_queue_url_id_kind_key UNIQUE (url_id, kind);
+
+CREATE INDEX idx_recrawl_queue_kind_ready ON recrawl_queue (kind, available_at)
+    WHERE locked_by IS NULL;
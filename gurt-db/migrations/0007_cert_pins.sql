@@ -0,0 +1,12 @@
+-- Leaf certificate fingerprints pinned on first use (GURT_TLS_MODE=tofu). Keyed by host
+-- name rather than domains.id: DNS servers and redirect targets are pinned too.
+CREATE TABLE cert_pins (
+    host TEXT PRIMARY KEY,
+    sha256 TEXT NOT NULL,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    mismatch_sha256 TEXT,
+    mismatch_count INTEGER NOT NULL DEFAULT 0,
+    last_mismatch_at TIMESTAMPTZ,
+    CHECK (mismatch_count >= 0)
+);
@@ -5,12 +5,19 @@ use anyhow::Result;
 use gurt_query::ParsedQuery;
 
 /// Minimal document representation for indexing.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct IndexDocument {
     pub url: String,
     pub domain: String,
     pub title: String,
+    /// Visible body text (markup already stripped).
     pub content: String,
+    /// Heading texts, one per line.
+    pub headings: String,
+    /// Meta description.
+    pub description: String,
+    /// Image alt texts.
+    pub alt_text: String,
     pub fetch_time: i64,
     pub language: String,    // e.g., "en"
     pub render_mode: String, // "static" | "rendered"
@@ -24,23 +31,41 @@ pub struct SearchHit {
     pub domain: String,
     pub fetch_time: i64,
     pub score: f32,
+    /// Best-matching passage of the content, as plain text.
+    pub snippet: String,
+    /// Byte ranges within `snippet` that matched query terms.
+    pub snippet_highlights: Vec<std::ops::Range<usize>>,
+}
+
+/// One page of search hits plus the total number of matching documents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResults {
+    pub total: u64,
+    pub hits: Vec<SearchHit>,
 }
 
 /// Pluggable index/search engine abstraction.
 pub trait IndexEngine: Send + Sync {
     fn engine_name(&self) -> &'static str;
 
-    /// Add/replace a document in the index.
+    /// Add/replace a document in the index. Any existing document with the
+    /// same `url` is removed first, so recrawls do not produce duplicates.
     fn add(&self, doc: IndexDocument) -> Result<()>;
 
+    /// Remove the document stored under `url` (e.g. the page now returns 404).
+    fn delete_url(&self, url: &str) -> Result<()>;
+
+    /// Remove every document belonging to `domain` (e.g. the domain was blocked).
+    fn delete_domain(&self, domain: &str) -> Result<()>;
+
     /// Commit pending changes to make them durable.
     fn commit(&self) -> Result<()>;
 
     /// Refresh searchers to see new segments (near-real-time).
     fn refresh(&self) -> Result<()>;
 
-    /// Execute a search with pagination.
-    fn search(&self, query: &ParsedQuery, page: usize, size: usize) -> Result<Vec<SearchHit>>;
+    /// Execute a search with pagination. `total` counts every match, not just this page.
+    fn search(&self, query: &ParsedQuery, page: usize, size: usize) -> Result<SearchResults>;
 }
 
 type EngineFactory = fn() -> Box<dyn IndexEngine>;
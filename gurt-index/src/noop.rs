@@ -1,4 +1,4 @@
-use crate::{IndexDocument, IndexEngine, SearchHit};
+use crate::{IndexDocument, IndexEngine, SearchResults};
 use anyhow::Result;
 use gurt_query::ParsedQuery;
 
@@ -18,6 +18,14 @@ impl IndexEngine for NoopIndexEngine {
         Ok(())
     }
 
+    fn delete_url(&self, _url: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_domain(&self, _domain: &str) -> Result<()> {
+        Ok(())
+    }
+
     fn commit(&self) -> Result<()> {
         Ok(())
     }
@@ -26,8 +34,8 @@ impl IndexEngine for NoopIndexEngine {
         Ok(())
     }
 
-    fn search(&self, _query: &ParsedQuery, _page: usize, _size: usize) -> Result<Vec<SearchHit>> {
-        Ok(Vec::new())
+    fn search(&self, _query: &ParsedQuery, _page: usize, _size: usize) -> Result<SearchResults> {
+        Ok(SearchResults::default())
     }
 }
 
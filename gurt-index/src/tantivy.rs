@@ -2,17 +2,20 @@ use std::path::Path;
 use std::sync::Mutex;
 
 use anyhow::{Context, Result};
-use gurt_query::ParsedQuery;
-use tantivy::collector::TopDocs;
+use gurt_query::{ParsedQuery, QueryFilters};
+use tantivy::collector::{Count, TopDocs};
 use tantivy::doc;
-use tantivy::query::{BooleanQuery, Occur, Query, TermQuery};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, Occur, Query, RegexQuery, TermQuery,
+};
 use tantivy::schema::{
     Field, IndexRecordOption, Schema, SchemaBuilder, TextFieldIndexing, TextOptions, FAST, INDEXED,
     STORED, STRING,
 };
-use tantivy::{Document as _, Index, IndexReader, IndexWriter, Term};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Document as _, Index, IndexReader, IndexWriter, Score, Term};
 
-use crate::{IndexDocument, IndexEngine, SearchHit};
+use crate::{IndexDocument, IndexEngine, SearchHit, SearchResults};
 
 /// Field handles for fast access at query time.
 #[derive(Debug, Clone)]
@@ -21,9 +24,44 @@ pub struct TantivyFields {
     pub domain: Field,
     pub title: Field,
     pub content: Field,
+    pub headings: Field,
+    pub description: Field,
+    pub alt_text: Field,
     pub fetch_time: Field,
     pub language: Field,
     pub render_mode: Field,
+    /// Lowercased file extension of the URL path (`html` when the path has none).
+    pub filetype: Field,
+}
+
+/// Files Tantivy keeps besides its segments.
+const INDEX_META_FILES: [&str; 4] = [
+    "meta.json",
+    ".managed.json",
+    ".tantivy-meta.lock",
+    ".tantivy-writer.lock",
+];
+
+/// Delete the files of the Tantivy index in `dir`: its metadata and lock files, and the
+/// segment files, named `<segment uuid>.<ext>` (32 hex digits before the first dot).
+fn remove_index_files(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let segment = name
+            .split_once('.')
+            .is_some_and(|(id, _)| id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()));
+        if segment || INDEX_META_FILES.contains(&name) {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
 }
 
 /// Default Tantivy-based index engine.
@@ -33,38 +71,46 @@ pub struct TantivyIndexEngine {
     index: Index,
     reader: IndexReader,
     writer: Mutex<IndexWriter>,
+    rebuilt: bool,
 }
 
 impl TantivyIndexEngine {
     /// Build the Schema per requirements: url, domain, title, content,
-    /// fetch_time, language, render_mode.
+    /// headings, description, alt_text, fetch_time, language, render_mode, filetype.
     pub fn build_schema() -> (Schema, TantivyFields) {
         // Indexing options for text fields: positions+freqs for BM25.
         let text_indexing = TextFieldIndexing::default()
             .set_index_option(IndexRecordOption::WithFreqsAndPositions)
             .set_tokenizer("en_stops");
 
-        let text_with_positions = TextOptions::default()
-            .set_indexing_options(text_indexing)
-            .set_stored();
+        let text_unstored = TextOptions::default().set_indexing_options(text_indexing);
+        let text_with_positions = text_unstored.clone().set_stored();
 
         let mut sb = SchemaBuilder::default();
         let url = sb.add_text_field("url", STRING | STORED);
         let domain = sb.add_text_field("domain", STRING | STORED);
         let title = sb.add_text_field("title", text_with_positions.clone());
-        let content = sb.add_text_field("content", text_with_positions);
+        let content = sb.add_text_field("content", text_with_positions.clone());
+        let headings = sb.add_text_field("headings", text_unstored.clone());
+        let description = sb.add_text_field("description", text_with_positions);
+        let alt_text = sb.add_text_field("alt_text", text_unstored);
         let fetch_time = sb.add_i64_field("fetch_time", INDEXED | FAST | STORED);
         let language = sb.add_text_field("language", STRING | STORED);
         let render_mode = sb.add_text_field("render_mode", STRING | STORED);
+        let filetype = sb.add_text_field("filetype", STRING);
         let schema = sb.build();
         let fields = TantivyFields {
             url,
             domain,
             title,
             content,
+            headings,
+            description,
+            alt_text,
             fetch_time,
             language,
             render_mode,
+            filetype,
         };
         (schema, fields)
     }
@@ -82,19 +128,32 @@ impl TantivyIndexEngine {
             index,
             reader,
             writer: Mutex::new(writer),
+            rebuilt: false,
         }
     }
 
-    /// Open an existing index at `dir`, or create one if missing.
+    /// Open an existing index at `dir`, or create one if missing. An index built with
+    /// another schema (e.g. before a field was added) is deleted and recreated empty;
+    /// [`rebuilt`](Self::rebuilt) then tells the caller to index its documents again.
+    /// Only Tantivy's own files are removed; anything else in `dir` is left alone.
     pub fn open_or_create_in_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
         let dir = dir.as_ref();
         let (schema, fields) = Self::build_schema();
+        let mut rebuilt = false;
+        if dir.join("meta.json").exists() {
+            let existing = Index::open_in_dir(dir).context("open tantivy index")?;
+            if existing.schema() != schema {
+                drop(existing);
+                remove_index_files(dir)
+                    .with_context(|| format!("removing outdated index {}", dir.display()))?;
+                rebuilt = true;
+            }
+        }
         if !dir.exists() {
             std::fs::create_dir_all(dir)
                 .with_context(|| format!("creating index dir {}", dir.display()))?;
         }
-        let meta = dir.join("meta.json");
-        let index = if meta.exists() {
+        let index = if dir.join("meta.json").exists() {
             Index::open_in_dir(dir).context("open tantivy index")?
         } else {
             Index::create_in_dir(dir, schema.clone()).context("create tantivy index")?
@@ -108,9 +167,20 @@ impl TantivyIndexEngine {
             index,
             reader,
             writer: Mutex::new(writer),
+            rebuilt,
         })
     }
 
+    /// Whether opening replaced an index of an older schema with an empty one.
+    pub fn rebuilt(&self) -> bool {
+        self.rebuilt
+    }
+
+    /// Underlying Tantivy index handle.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
     /// Number of documents visible to the current searcher.
     pub fn num_docs(&self) -> u64 {
         self.reader.searcher().num_docs()
@@ -123,17 +193,40 @@ impl IndexEngine for TantivyIndexEngine {
     }
 
     fn add(&self, doc: IndexDocument) -> Result<()> {
+        let filetype = filetype_from_url(&doc.url);
+        let url_term = Term::from_field_text(self.fields.url, &doc.url);
         let tdoc = doc!(
             self.fields.url => doc.url,
-            self.fields.domain => doc.domain,
+            self.fields.domain => doc.domain.to_ascii_lowercase(),
             self.fields.title => doc.title,
             self.fields.content => doc.content,
+            self.fields.headings => doc.headings,
+            self.fields.description => doc.description,
+            self.fields.alt_text => doc.alt_text,
             self.fields.fetch_time => doc.fetch_time,
             self.fields.language => doc.language,
-            self.fields.render_mode => doc.render_mode
+            self.fields.render_mode => doc.render_mode,
+            self.fields.filetype => filetype
         );
-        let mut writer = self.writer.lock().expect("writer lock");
-        let _ = writer.add_document(tdoc);
+        // Upsert: the delete is ordered before the add, so only the new copy survives commit.
+        let writer = self.writer.lock().expect("writer lock");
+        writer.delete_term(url_term);
+        writer.add_document(tdoc).context("add document")?;
+        Ok(())
+    }
+
+    fn delete_url(&self, url: &str) -> Result<()> {
+        let writer = self.writer.lock().expect("writer lock");
+        writer.delete_term(Term::from_field_text(self.fields.url, url));
+        Ok(())
+    }
+
+    fn delete_domain(&self, domain: &str) -> Result<()> {
+        let writer = self.writer.lock().expect("writer lock");
+        writer.delete_term(Term::from_field_text(
+            self.fields.domain,
+            &domain.to_ascii_lowercase(),
+        ));
         Ok(())
     }
 
@@ -148,43 +241,43 @@ impl IndexEngine for TantivyIndexEngine {
         Ok(())
     }
 
-    fn search(&self, query: &ParsedQuery, page: usize, size: usize) -> Result<Vec<SearchHit>> {
-        // Build a BM25-backed boolean query from analyzed terms over title + content.
+    fn search(&self, query: &ParsedQuery, page: usize, size: usize) -> Result<SearchResults> {
+        // Build a BM25-backed boolean query from analyzed terms over the weighted text
+        // fields, then AND the site/filetype filters on top without letting them affect scores.
         let page = page.max(1);
         let size = size.max(1);
         let offset = (page - 1) * size;
 
         let tokens = analyze_terms(&query.terms);
-        if tokens.is_empty() {
-            return Ok(Vec::new());
+        let filters = self.filter_queries(&query.filters)?;
+        if tokens.is_empty() && filters.is_empty() {
+            return Ok(SearchResults::default());
         }
 
-        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        let mut text_clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
         for t in tokens {
-            let term_title = Term::from_field_text(self.fields.title, &t);
-            let term_content = Term::from_field_text(self.fields.content, &t);
-            clauses.push((
-                Occur::Should,
-                Box::new(TermQuery::new(
-                    term_title,
-                    IndexRecordOption::WithFreqsAndPositions,
-                )),
-            ));
-            clauses.push((
-                Occur::Should,
-                Box::new(TermQuery::new(
-                    term_content,
-                    IndexRecordOption::WithFreqsAndPositions,
-                )),
-            ));
+            for (field, boost) in self.weighted_text_fields() {
+                let term = Term::from_field_text(field, &t);
+                let q = TermQuery::new(term, IndexRecordOption::WithFreqsAndPositions);
+                text_clauses.push((Occur::Should, Box::new(BoostQuery::new(Box::new(q), boost))));
+            }
+        }
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        if text_clauses.is_empty() {
+            // Filter-only query (e.g. `site:example.real`): list everything the filters admit.
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        } else {
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(text_clauses))));
         }
-        if clauses.is_empty() {
-            return Ok(Vec::new());
+        for filter in filters {
+            clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(filter, 0.0))));
         }
         let bool_query = BooleanQuery::new(clauses);
         let searcher = self.reader.searcher();
-        let top_docs =
-            searcher.search(&bool_query, &TopDocs::with_limit(size).and_offset(offset))?;
+        let (top_docs, total) = searcher.search(
+            &bool_query,
+            &(TopDocs::with_limit(size).and_offset(offset), Count),
+        )?;
 
         fn first_str(v: &serde_json::Value) -> Option<String> {
             match v {
@@ -213,27 +306,152 @@ impl IndexEngine for TantivyIndexEngine {
             }
         }
 
+        let mut snippets = SnippetGenerator::create(&searcher, &bool_query, self.fields.content)
+            .context("snippet generator")?;
+        snippets.set_max_num_chars(SNIPPET_MAX_CHARS);
+
         let mut out = Vec::with_capacity(top_docs.len());
         for (score, addr) in top_docs {
             let doc = searcher.doc::<tantivy::TantivyDocument>(addr)?;
+            let snippet = snippets.snippet_from_doc(&doc);
             let json = doc.to_json(&self.schema);
             let v: serde_json::Value = serde_json::from_str(&json).unwrap_or(serde_json::json!({}));
             let title = v.get("title").and_then(first_str).unwrap_or_default();
             let url = v.get("url").and_then(first_str).unwrap_or_default();
             let domain = v.get("domain").and_then(first_str).unwrap_or_default();
             let fetch_time = v.get("fetch_time").and_then(first_i64).unwrap_or(0);
+            let (snippet, snippet_highlights) = if snippet.is_empty() {
+                // No term hit in content (title-only match or filter-only query).
+                let content = v
+                    .get("content")
+                    .and_then(first_str)
+                    .filter(|c| !c.trim().is_empty())
+                    .or_else(|| v.get("description").and_then(first_str))
+                    .unwrap_or_default();
+                (leading_excerpt(&content, SNIPPET_MAX_CHARS), Vec::new())
+            } else {
+                (
+                    snippet.fragment().to_string(),
+                    snippet.highlighted().to_vec(),
+                )
+            };
             out.push(SearchHit {
                 title,
                 url,
                 domain,
                 fetch_time,
                 score,
+                snippet,
+                snippet_highlights,
             });
         }
+        Ok(SearchResults {
+            total: total as u64,
+            hits: out,
+        })
+    }
+}
+
+impl TantivyIndexEngine {
+    /// Text fields queried for every term, with their BM25 boosts. A match in the
+    /// title or a heading says more about a page than one somewhere in its body.
+    fn weighted_text_fields(&self) -> [(Field, Score); 5] {
+        [
+            (self.fields.title, 2.0),
+            (self.fields.headings, 1.5),
+            (self.fields.description, 1.2),
+            (self.fields.content, 1.0),
+            (self.fields.alt_text, 0.5),
+        ]
+    }
+
+    /// Translate `site:` / `filetype:` filters into required (non-scoring) queries.
+    /// - `site` matches the `domain` field exactly, or any subdomain when
+    ///   `site_subdomains` is set.
+    /// - `filetype` matches the extension derived from the URL path at index time.
+    fn filter_queries(&self, filters: &QueryFilters) -> Result<Vec<Box<dyn Query>>> {
+        let mut out: Vec<Box<dyn Query>> = Vec::new();
+        if let Some(site) = filters.site.as_deref() {
+            let site = site.trim_end_matches('.').to_ascii_lowercase();
+            if filters.site_subdomains {
+                let pattern = format!("(.+\\.)?{}", regex_escape(&site));
+                let q = RegexQuery::from_pattern(&pattern, self.fields.domain)
+                    .context("build site regex")?;
+                out.push(Box::new(q));
+            } else {
+                out.push(Box::new(TermQuery::new(
+                    Term::from_field_text(self.fields.domain, &site),
+                    IndexRecordOption::Basic,
+                )));
+            }
+        }
+        if let Some(ft) = filters.filetype.as_deref() {
+            out.push(Box::new(TermQuery::new(
+                Term::from_field_text(self.fields.filetype, &normalize_filetype(ft)),
+                IndexRecordOption::Basic,
+            )));
+        }
         Ok(out)
     }
 }
 
+const SNIPPET_MAX_CHARS: usize = 180;
+
+/// First `max_chars` bytes of `text`, cut back to a word boundary.
+fn leading_excerpt(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    if text.len() <= max_chars {
+        return text.to_string();
+    }
+    let mut end = max_chars;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = text[..end].rfind(char::is_whitespace).unwrap_or(end);
+    text[..cut].trim_end().to_string()
+}
+
+/// Derive the filetype of a URL from the extension of its last path segment.
+/// Paths without an extension (`/`, `/about`) are pages and count as `html`.
+pub fn filetype_from_url(url: &str) -> String {
+    let after_scheme = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+    let path = match after_scheme.find('/') {
+        Some(i) => &after_scheme[i..],
+        None => "/",
+    };
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let last = path.rsplit('/').next().unwrap_or("");
+    match last.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && !ext.is_empty()
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            normalize_filetype(ext)
+        }
+        _ => "html".to_string(),
+    }
+}
+
+fn normalize_filetype(ext: &str) -> String {
+    let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+    match ext.as_str() {
+        "htm" => "html".to_string(),
+        _ => ext,
+    }
+}
+
+fn regex_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
 fn analyze_terms(raw_terms: &[String]) -> Vec<String> {
     let mut out = Vec::new();
     for term in raw_terms {
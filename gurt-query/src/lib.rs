@@ -1,6 +1,8 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct QueryFilters {
     pub site: Option<String>,
+    /// When set, `site` also matches any subdomain of the given domain.
+    pub site_subdomains: bool,
     pub filetype: Option<String>,
 }
 
@@ -10,23 +12,17 @@ pub struct ParsedQuery {
     pub filters: QueryFilters,
 }
 
-impl Default for QueryFilters {
-    fn default() -> Self {
-        Self {
-            site: None,
-            filetype: None,
-        }
-    }
-}
-
 /// Parse a raw query string into free-text terms and supported filters.
 /// Supported filters: `site:<domain>`, `filetype:<ext>` (case-insensitive keys).
 /// - Domains and filetypes are lowercased and stripped of surrounding quotes.
+/// - `site:*.<domain>` (or `site:.<domain>`) also matches subdomains of `<domain>`.
+/// - A leading dot on filetypes is ignored (`filetype:.pdf` == `filetype:pdf`).
 /// - Unknown tokens are treated as free-text terms.
 /// - Multiple occurrences: the last one wins.
 pub fn parse_query(input: &str) -> ParsedQuery {
     let mut terms: Vec<String> = Vec::new();
     let mut site: Option<String> = None;
+    let mut site_subdomains = false;
     let mut filetype: Option<String> = None;
 
     for raw in input.split_whitespace() {
@@ -34,13 +30,18 @@ pub fn parse_query(input: &str) -> ParsedQuery {
             match k.to_ascii_lowercase().as_str() {
                 "site" => {
                     let v = strip_quotes(v).to_ascii_lowercase();
+                    let (v, subdomains) = match v.strip_prefix("*.").or(v.strip_prefix('.')) {
+                        Some(rest) => (rest.to_string(), true),
+                        None => (v, false),
+                    };
                     if !v.is_empty() {
                         site = Some(v);
+                        site_subdomains = subdomains;
                     }
                     continue;
                 }
                 "filetype" => {
-                    let v = strip_quotes(v).to_ascii_lowercase();
+                    let v = strip_quotes(v).trim_start_matches('.').to_ascii_lowercase();
                     if !v.is_empty() {
                         filetype = Some(v);
                     }
@@ -57,7 +58,11 @@ pub fn parse_query(input: &str) -> ParsedQuery {
 
     ParsedQuery {
         terms,
-        filters: QueryFilters { site, filetype },
+        filters: QueryFilters {
+            site,
+            site_subdomains,
+            filetype,
+        },
     }
 }
 
@@ -26,7 +26,7 @@ fn main() -> io::Result<()> {
             "tantivy" => {
                 let path = dir_opt
                     .map(std::path::PathBuf::from)
-                    .unwrap_or_else(|| tempdir());
+                    .unwrap_or_else(tempdir);
                 let eng =
                     TantivyIndexEngine::open_or_create_in_dir(&path).expect("open/create tantivy");
                 (Box::new(eng), Some(path))
@@ -56,6 +56,7 @@ fn main() -> io::Result<()> {
             fetch_time: 1_700_000_000 + i as i64,
             language: "en".into(),
             render_mode: "static".into(),
+            ..Default::default()
         });
     }
 
@@ -1,11 +1,27 @@
-use anyhow::Result;
+//! The GURT client used by the crawler, the indexer and the GURT DNS resolver.
+//!
+//! A request opens a stream through the [`ConnectorFn`], sends the plaintext HANDSHAKE,
+//! upgrades the stream (TLS in production, nothing in tests) and then exchanges the
+//! request and response framed by [`gurt_api::client`]. With a keep-alive pool the
+//! upgraded stream is reused for later requests to the same host.
+
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::time::timeout;
 
-use gurt_api::limits::{enforce_max_message_size, MAX_MESSAGE_BYTES};
+use gurt_api::client::{encode_handshake, encode_request, read_response, WireError, DEFAULT_PORT};
+
+pub use gurt_api::client::ClientResponse;
+
+use super::keepalive::ConnPool;
+use super::redirect::{RedirectChain, RedirectError, RedirectPolicy};
+use super::resolver::{ResolveError, Resolver};
+use super::tls::insecure_tls_upgrade;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ClientError {
@@ -13,34 +29,116 @@ pub enum ClientError {
     Connection,
     Timeout,
     Io,
+    /// The server's certificate was not trusted (or did not match its pin).
+    Certificate,
+    Redirect(RedirectError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid message"),
+            Self::Connection => write!(f, "connection failed"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Io => write!(f, "i/o error"),
+            Self::Certificate => write!(f, "certificate rejected"),
+            Self::Redirect(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<WireError> for ClientError {
+    fn from(err: WireError) -> Self {
+        match err {
+            WireError::Closed => Self::Connection,
+            WireError::Malformed => Self::InvalidMessage,
+            WireError::TooLarge(_) | WireError::Io(_) => Self::Io,
+        }
+    }
 }
 
 pub trait IoStream: AsyncRead + AsyncWrite + Unpin + Send {}
 impl<T: AsyncRead + AsyncWrite + Unpin + Send> IoStream for T {}
 pub type DynStream = Pin<Box<dyn IoStream>>;
 
-pub type ConnectorFn = dyn Fn(
-        &str,
-        u16,
-    ) -> Pin<Box<dyn std::future::Future<Output = Result<DynStream, ClientError>> + Send>>
-    + Send
-    + Sync;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Opens a stream to `host:port` on which the HANDSHAKE is sent.
+pub type ConnectorFn = dyn Fn(&str, u16) -> BoxFuture<Result<DynStream, ClientError>> + Send + Sync;
+
+/// Upgrades a stream to `host` after its HANDSHAKE was accepted.
+pub type UpgradeFn =
+    dyn Fn(&str, DynStream) -> BoxFuture<Result<DynStream, ClientError>> + Send + Sync;
+
+/// One request: method, gurt:// URL, extra headers and body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ClientRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn post(url: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            method: "POST".to_string(),
+            body: body.into(),
+            ..Self::get(url)
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
 
 #[derive(Clone)]
 pub struct GurtClient {
     connector: Arc<ConnectorFn>,
+    upgrade: Option<Arc<UpgradeFn>>,
+    pool: Option<Arc<ConnPool<DynStream>>>,
+    /// Sent in the HANDSHAKE and with every request.
+    pub user_agent: Option<String>,
+    /// Bound on opening a connection.
     pub req_timeout: Duration,
+    /// Bound on the HANDSHAKE plus the upgrade.
+    pub handshake_timeout: Duration,
+    /// Bound on sending a request and reading its response on an open stream.
+    pub fetch_timeout: Duration,
+    /// A body without content-length ends after this long without data.
+    pub read_idle: Duration,
     pub retry_backoff: Duration,
     pub header_read_chunk: usize,
+    pub redirects: RedirectPolicy,
 }
 
 impl GurtClient {
     pub fn new_with_connector(connector: Arc<ConnectorFn>) -> Self {
         Self {
             connector,
+            upgrade: None,
+            pool: None,
+            user_agent: None,
             req_timeout: Duration::from_secs(2),
+            handshake_timeout: Duration::from_secs(2),
+            fetch_timeout: Duration::from_secs(30),
+            read_idle: Duration::from_millis(500),
             retry_backoff: Duration::from_millis(10),
             header_read_chunk: 2048,
+            redirects: RedirectPolicy::default(),
         }
     }
 
@@ -50,50 +148,75 @@ impl GurtClient {
         Self::new_with_connector(connector)
     }
 
-    /// Build a client with a rustls-based TLS connector. For development only (no cert verification).
-    #[cfg(feature = "tls_client")]
+    /// Build a client over TCP with a rustls upgrade that accepts any certificate. For
+    /// development only; see [`super::tls`] for the verifying upgrades.
     pub fn new_rustls_insecure() -> Self {
-        use rustls::pki_types::ServerName;
-        use rustls::ClientConfig;
-        use std::sync::Arc as StdArc;
-        use tokio_rustls::TlsConnector;
-
-        let mut cfg = ClientConfig::builder()
-            .with_safe_defaults()
-            .with_custom_certificate_verifier(StdArc::new(NoVerifier))
-            .with_no_client_auth();
-        cfg.alpn_protocols = vec![b"GURT/1.0".to_vec()];
-        let cfg = StdArc::new(cfg);
-        let tls = TlsConnector::from(cfg);
-        let connector_arc: Arc<ConnectorFn> = Arc::new(move |host: &str, port: u16| {
-            let host_owned = host.to_string();
-            let tls = tls.clone();
-            Box::pin(async move {
-                let tcp = tokio::net::TcpStream::connect((host_owned.as_str(), port))
-                    .await
-                    .map_err(|_| ClientError::Connection)?;
-                let server_name = ServerName::try_from(host_owned.as_str())
-                    .map_err(|_| ClientError::InvalidMessage)?;
-                let stream = tls
-                    .connect(server_name, tcp)
-                    .await
-                    .map_err(|_| ClientError::Connection)?;
-                Ok(Box::pin(stream) as DynStream)
-            })
-        });
-        Self::new_with_connector(connector_arc)
+        Self::new_with_connector(tcp_connector(None)).with_upgrade(insecure_tls_upgrade())
+    }
+
+    /// Upgrade every stream after its HANDSHAKE, e.g. with [`super::tls::upgrade_from_env`].
+    pub fn with_upgrade(mut self, upgrade: Arc<UpgradeFn>) -> Self {
+        self.upgrade = Some(upgrade);
+        self
+    }
+
+    /// Keep upgraded streams in `pool` between requests.
+    pub fn with_keepalive(mut self, pool: Arc<ConnPool<DynStream>>) -> Self {
+        self.pool = Some(pool);
+        self
     }
 
+    /// Fetch `url`, following redirects per `self.redirects`; each request of the chain
+    /// is retried up to `retries` times on connection errors.
     pub async fn fetch_with_retries(
         &self,
         url: &str,
         retries: usize,
+    ) -> Result<ClientResponse, ClientError> {
+        self.execute(ClientRequest::get(url), retries).await
+    }
+
+    /// Send `req`, following redirects per `self.redirects` and retrying each request of
+    /// the chain up to `retries` times on connection errors. 301 and 302 turn a request
+    /// other than GET or HEAD into a GET without body; 307 and 308 repeat it as is.
+    pub async fn execute(
+        &self,
+        mut req: ClientRequest,
+        retries: usize,
+    ) -> Result<ClientResponse, ClientError> {
+        let mut chain = RedirectChain::new(&req.url, self.redirects);
+        loop {
+            let resp = self.send_retrying(&req, retries).await?;
+            if !self.redirects.follows(resp.code) {
+                return Ok(resp);
+            }
+            req.url = chain
+                .follow(resp.header("location"))
+                .map_err(ClientError::Redirect)?
+                .to_string();
+            if matches!(resp.code, 301 | 302) && req.method != "GET" && req.method != "HEAD" {
+                req.method = "GET".to_string();
+                req.body.clear();
+                req.headers.retain(|(k, _)| {
+                    !k.eq_ignore_ascii_case("content-type")
+                        && !k.eq_ignore_ascii_case("content-length")
+                });
+            }
+        }
+    }
+
+    async fn send_retrying(
+        &self,
+        req: &ClientRequest,
+        retries: usize,
     ) -> Result<ClientResponse, ClientError> {
         let mut last_err = None;
         for attempt in 0..=retries {
-            match self.fetch_once(url).await {
+            match self.send(req).await {
                 Ok(resp) => return Ok(resp),
-                Err(e @ ClientError::InvalidMessage) => return Err(e),
+                Err(e @ ClientError::InvalidMessage)
+                | Err(e @ ClientError::Certificate)
+                | Err(e @ ClientError::Redirect(_)) => return Err(e),
                 Err(e @ ClientError::Connection)
                 | Err(e @ ClientError::Timeout)
                 | Err(e @ ClientError::Io) => {
@@ -108,192 +231,166 @@ impl GurtClient {
         Err(last_err.unwrap_or(ClientError::Connection))
     }
 
-    async fn fetch_once(&self, url: &str) -> Result<ClientResponse, ClientError> {
-        // Parse gurt:// URL
-        let parsed = url::Url::parse(url).map_err(|_| ClientError::InvalidMessage)?;
+    /// Send one request and read its response; no retries, redirects are returned as
+    /// they are. A pooled stream the server closed in the meantime is replaced by a
+    /// fresh one.
+    pub async fn send(&self, req: &ClientRequest) -> Result<ClientResponse, ClientError> {
+        let parsed = url::Url::parse(&req.url).map_err(|_| ClientError::InvalidMessage)?;
         if parsed.scheme() != "gurt" {
             return Err(ClientError::InvalidMessage);
         }
         let host = parsed.host_str().ok_or(ClientError::InvalidMessage)?;
-        let port = parsed.port().unwrap_or(4878);
-        let path = format!(
-            "{}{}",
-            parsed.path(),
-            parsed
-                .query()
-                .map(|q| format!("?{}", q))
-                .unwrap_or_default()
-        );
-
-        // Connect
-        let fut = (self.connector)(host, port);
-        let mut stream = timeout(self.req_timeout, fut)
-            .await
-            .map_err(|_| ClientError::Timeout)??;
+        let port = parsed.port().unwrap_or(DEFAULT_PORT);
+        let bytes = self.encode(req, &parsed, host, port);
 
-        // Handshake: send a simple token, expect GURT 101 response headers
-        let hs_req = b"HANDSHAKE / GURT/1.0\r\n\r\n";
-        let _ = timeout(self.req_timeout, stream.write_all(hs_req))
-            .await
-            .map_err(|_| ClientError::Timeout)
-            .map_err(|_| ClientError::Timeout)?;
-        let _ = timeout(self.req_timeout, stream.flush())
-            .await
-            .map_err(|_| ClientError::Timeout);
-        let hs = read_response_like(&mut stream, self.header_read_chunk).await?;
-        if hs.code != 101 {
-            return Err(ClientError::InvalidMessage);
+        let pool = self.pool.as_ref().filter(|p| p.enabled());
+        if let Some(mut stream) = pool.and_then(|p| p.take(host, port)) {
+            if let Ok((resp, reusable)) = self.exchange(&mut stream, &bytes).await {
+                if let Some(pool) = pool.filter(|_| reusable) {
+                    pool.put(host, port, stream);
+                }
+                return Ok(resp);
+            }
+        }
+        let mut stream = self.open(host, port).await?;
+        let (resp, reusable) = self.exchange(&mut stream, &bytes).await?;
+        if let Some(pool) = pool.filter(|_| reusable) {
+            pool.put(host, port, stream);
         }
-
-        // Request
-        let req = format!("GET {} GURT/1.0\r\nhost: {}\r\n\r\n", path, host);
-        let _ = timeout(self.req_timeout, stream.write_all(req.as_bytes()))
-            .await
-            .map_err(|_| ClientError::Timeout)?;
-        let _ = timeout(self.req_timeout, stream.flush())
-            .await
-            .map_err(|_| ClientError::Timeout);
-
-        // Response
-        let resp = read_response_like(&mut stream, self.header_read_chunk).await?;
         Ok(resp)
     }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ClientResponse {
-    pub code: u16,
-    pub headers: Vec<(String, String)>,
-    pub body: Vec<u8>,
-}
 
-async fn read_response_like(
-    stream: &mut DynStream,
-    chunk: usize,
-) -> Result<ClientResponse, ClientError> {
-    // Read header up to CRLFCRLF; enforce total cap
-    let mut buf: Vec<u8> = Vec::with_capacity(4096);
-    let mut tmp = vec![0u8; chunk.max(1)];
-    let mut header_end: Option<usize> = None;
-    loop {
-        let n = stream.read(&mut tmp).await.map_err(|_| ClientError::Io)?;
-        if n == 0 {
-            return Err(ClientError::Connection);
+    fn encode(&self, req: &ClientRequest, url: &url::Url, host: &str, port: u16) -> Vec<u8> {
+        let mut path = url.path().to_string();
+        if path.is_empty() {
+            path.push('/');
         }
-        buf.extend_from_slice(&tmp[..n]);
-        if buf.len() > MAX_MESSAGE_BYTES {
-            return Err(ClientError::Io);
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
         }
-        if let Some(pos) = find_crlfcrlf(&buf) {
-            header_end = Some(pos);
-            break;
+        let host_header = if port != DEFAULT_PORT {
+            format!("{}:{}", host, port)
+        } else {
+            host.to_string()
+        };
+        let mut headers = Vec::with_capacity(req.headers.len() + 2);
+        if let Some(ua) = &self.user_agent {
+            headers.push(("user-agent".to_string(), ua.clone()));
         }
+        let keep_alive = self.pool.as_ref().is_some_and(|p| p.enabled());
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        headers.push(("connection".to_string(), connection.to_string()));
+        headers.extend(req.headers.iter().cloned());
+        encode_request(&req.method, &path, &host_header, &headers, &req.body)
     }
-    let header_end = header_end.ok_or(ClientError::InvalidMessage)?;
-    let (head, rest) = buf.split_at(header_end + 4);
-    let head_str = std::str::from_utf8(head).map_err(|_| ClientError::InvalidMessage)?;
-    let mut lines = head_str.split("\r\n");
-    let status = lines.next().unwrap_or("");
-    let mut sp = status.split_whitespace();
-    let _proto = sp.next().unwrap_or("");
-    let code = sp
-        .next()
-        .and_then(|c| c.parse::<u16>().ok())
-        .ok_or(ClientError::InvalidMessage)?;
-    let mut headers: Vec<(String, String)> = Vec::new();
-    let mut content_length: usize = 0;
-    for line in lines {
-        if line.is_empty() {
-            continue;
-        }
-        if let Some((name, value)) = line.split_once(':') {
-            let name = name.trim().to_ascii_lowercase();
-            let value = value.trim().to_string();
-            if name == "content-length" {
-                if let Ok(n) = value.parse::<usize>() {
-                    content_length = n;
-                }
+
+    /// Connect, HANDSHAKE and upgrade.
+    async fn open(&self, host: &str, port: u16) -> Result<DynStream, ClientError> {
+        let mut stream = timeout(self.req_timeout, (self.connector)(host, port))
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        let handshake = async {
+            let hs = encode_handshake(host, self.user_agent.as_deref());
+            stream.write_all(&hs).await.map_err(|_| ClientError::Io)?;
+            stream.flush().await.map_err(|_| ClientError::Io)?;
+            let (resp, _) =
+                read_response(&mut stream, self.header_read_chunk, self.read_idle).await?;
+            if resp.code != 101 {
+                return Err(ClientError::InvalidMessage);
             }
-            headers.push((name, value));
-        }
-    }
-    // Read body to content-length if present
-    let mut body = Vec::new();
-    if content_length > 0 {
-        enforce_max_message_size(header_end + 4 + content_length).map_err(|_| ClientError::Io)?;
-        if !rest.is_empty() {
-            body.extend_from_slice(rest);
-        }
-        while body.len() < content_length {
-            let mut chunk = [0u8; 4096];
-            let n = stream.read(&mut chunk).await.map_err(|_| ClientError::Io)?;
-            if n == 0 {
-                break;
+            match &self.upgrade {
+                Some(upgrade) => upgrade(host, stream).await,
+                None => Ok(stream),
             }
-            body.extend_from_slice(&chunk[..n]);
-            let total = header_end + 4 + body.len();
-            if total > MAX_MESSAGE_BYTES {
-                return Err(ClientError::Io);
+        };
+        timeout(self.handshake_timeout, handshake)
+            .await
+            .map_err(|_| ClientError::Timeout)?
+    }
+
+    /// Write a request and read its response. The stream can be reused when both sides
+    /// kept it open and the response was framed by its length.
+    async fn exchange(
+        &self,
+        stream: &mut DynStream,
+        request: &[u8],
+    ) -> Result<(ClientResponse, bool), ClientError> {
+        let fut = async {
+            // A peer may answer (and close) before taking the whole request, e.g. with an
+            // early error; a failed write only counts if there is no response to read.
+            let written = match stream.write_all(request).await {
+                Ok(()) => stream.flush().await.is_ok(),
+                Err(_) => false,
+            };
+            match read_response(stream, self.header_read_chunk, self.read_idle).await {
+                Ok(resp) => Ok(resp),
+                Err(_) if !written => Err(ClientError::Io),
+                Err(e) => Err(e.into()),
             }
-        }
-        body.truncate(content_length);
+        };
+        let (resp, framed) = timeout(self.fetch_timeout, fut)
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        let closing = resp
+            .header("connection")
+            .is_some_and(|v| v.eq_ignore_ascii_case("close"));
+        let keep_alive = self.pool.as_ref().is_some_and(|p| p.enabled());
+        Ok((resp, keep_alive && framed && !closing))
     }
-    Ok(ClientResponse {
-        code,
-        headers,
-        body,
-    })
 }
 
-fn find_crlfcrlf(buf: &[u8]) -> Option<usize> {
-    // naive search
-    buf.windows(4).position(|w| w == b"\r\n\r\n")
+/// Bound on each connect to an address other than the last one a name resolved to, so
+/// an unreachable address leaves time for the next.
+const FAILOVER_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connector opening TCP connections. IP literals and `localhost` are connected as
+/// they are. Other names go through `resolver`, trying its addresses in order; a name
+/// it does not know fails, and a resolver failure falls back to the OS resolver.
+pub fn tcp_connector(resolver: Option<Arc<dyn Resolver>>) -> Arc<ConnectorFn> {
+    Arc::new(move |host: &str, port: u16| {
+        let host = host.to_string();
+        let resolver = resolver.clone();
+        Box::pin(async move {
+            let addrs = if let Ok(ip) = host.parse::<IpAddr>() {
+                vec![ip]
+            } else if host.eq_ignore_ascii_case("localhost") {
+                Vec::new()
+            } else if let Some(resolver) = &resolver {
+                match resolver.resolve(&host).await {
+                    Ok(addrs) => addrs,
+                    Err(ResolveError::NotFound) => return Err(ClientError::Connection),
+                    Err(ResolveError::Failed) => Vec::new(),
+                }
+            } else {
+                Vec::new()
+            };
+            let tcp = if addrs.is_empty() {
+                tokio::net::TcpStream::connect((host.as_str(), port))
+                    .await
+                    .map_err(|_| ClientError::Connection)?
+            } else {
+                connect_any(&addrs, port).await?
+            };
+            tcp.set_nodelay(true).ok();
+            Ok(Box::pin(tcp) as DynStream)
+        })
+    })
 }
 
-// Development-only certificate verifier (accepts any cert). Do not use in production.
-#[cfg(feature = "tls_client")]
-#[derive(Debug)]
-struct NoVerifier;
-#[cfg(feature = "tls_client")]
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        use rustls::SignatureScheme::*;
-        vec![
-            ECDSA_NISTP256_SHA256,
-            ECDSA_NISTP384_SHA384,
-            ED25519,
-            RSA_PKCS1_SHA256,
-            RSA_PKCS1_SHA384,
-            RSA_PKCS1_SHA512,
-            RSA_PSS_SHA256,
-            RSA_PSS_SHA384,
-            RSA_PSS_SHA512,
-        ]
+async fn connect_any(addrs: &[IpAddr], port: u16) -> Result<tokio::net::TcpStream, ClientError> {
+    for (i, ip) in addrs.iter().enumerate() {
+        let connect = tokio::net::TcpStream::connect((*ip, port));
+        let res = if i + 1 < addrs.len() {
+            timeout(FAILOVER_CONNECT_TIMEOUT, connect)
+                .await
+                .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+        } else {
+            connect.await
+        };
+        if let Ok(tcp) = res {
+            return Ok(tcp);
+        }
     }
+    Err(ClientError::Connection)
 }
@@ -0,0 +1,640 @@
+//! Mutable DOM tree used to run page scripts during render-once.
+//!
+//! The tree is built from [`crate::crawler::html::tokenize`] with the same forgiveness:
+//! stray end tags are ignored and unclosed elements are closed at the end of their
+//! parent. Nodes live in an arena and are addressed by [`NodeId`]; detached nodes stay in
+//! the arena until the tree is dropped.
+
+use crate::crawler::html::{tokenize, Token};
+
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeData {
+    Document,
+    Element {
+        tag: String,
+        attrs: Vec<(String, String)>,
+    },
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    data: NodeData,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// Elements that never have children or an end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose text is serialized verbatim.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+#[derive(Debug, Clone)]
+pub struct Dom {
+    nodes: Vec<Node>,
+}
+
+impl Dom {
+    /// The document node.
+    pub const ROOT: NodeId = 0;
+
+    pub fn parse(html: &str) -> Self {
+        let mut dom = Self {
+            nodes: vec![Node {
+                data: NodeData::Document,
+                parent: None,
+                children: Vec::new(),
+            }],
+        };
+        dom.parse_into(Self::ROOT, html);
+        dom
+    }
+
+    fn parse_into(&mut self, parent: NodeId, html: &str) {
+        let mut stack = vec![parent];
+        for token in tokenize(html) {
+            let top = *stack.last().unwrap_or(&parent);
+            match token {
+                Token::StartTag {
+                    name,
+                    attrs,
+                    self_closing,
+                } => {
+                    let void = self_closing || VOID_ELEMENTS.contains(&name.as_str());
+                    let id = self.push(NodeData::Element { tag: name, attrs });
+                    self.attach(top, id, None);
+                    if !void {
+                        stack.push(id);
+                    }
+                }
+                Token::EndTag { name } => {
+                    // Close the innermost matching element; the parse root is never closed.
+                    if let Some(pos) = stack
+                        .iter()
+                        .rposition(|&n| n != parent && self.tag(n) == Some(name.as_str()))
+                    {
+                        stack.truncate(pos);
+                    }
+                }
+                Token::Text(text) => {
+                    let id = self.push(NodeData::Text(text));
+                    self.attach(top, id, None);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node {
+            data,
+            parent: None,
+            children: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Insert a detached `child` into `parent` at `index` (appended when None).
+    fn attach(&mut self, parent: NodeId, child: NodeId, index: Option<usize>) {
+        self.nodes[child].parent = Some(parent);
+        let children = &mut self.nodes[parent].children;
+        match index {
+            Some(i) if i <= children.len() => children.insert(i, child),
+            _ => children.push(child),
+        }
+    }
+
+    pub fn create_element(&mut self, tag: &str) -> NodeId {
+        self.push(NodeData::Element {
+            tag: tag.trim().to_ascii_lowercase(),
+            attrs: Vec::new(),
+        })
+    }
+
+    pub fn create_text(&mut self, text: &str) -> NodeId {
+        self.push(NodeData::Text(text.to_string()))
+    }
+
+    pub fn data(&self, id: NodeId) -> &NodeData {
+        &self.nodes[id].data
+    }
+
+    pub fn is_element(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id].data, NodeData::Element { .. })
+    }
+
+    pub fn tag(&self, id: NodeId) -> Option<&str> {
+        match &self.nodes[id].data {
+            NodeData::Element { tag, .. } => Some(tag),
+            _ => None,
+        }
+    }
+
+    pub fn attr(&self, id: NodeId, name: &str) -> Option<&str> {
+        match &self.nodes[id].data {
+            NodeData::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn set_attr(&mut self, id: NodeId, name: &str, value: &str) {
+        if let NodeData::Element { attrs, .. } = &mut self.nodes[id].data {
+            let name = name.trim().to_ascii_lowercase();
+            match attrs.iter_mut().find(|(k, _)| *k == name) {
+                Some((_, v)) => *v = value.to_string(),
+                None => attrs.push((name, value.to_string())),
+            }
+        }
+    }
+
+    pub fn remove_attr(&mut self, id: NodeId, name: &str) {
+        if let NodeData::Element { attrs, .. } = &mut self.nodes[id].data {
+            attrs.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    pub fn element_children(&self, id: NodeId) -> Vec<NodeId> {
+        self.nodes[id]
+            .children
+            .iter()
+            .copied()
+            .filter(|&c| self.is_element(c))
+            .collect()
+    }
+
+    /// Next (`forward`) or previous element sibling.
+    pub fn element_sibling(&self, id: NodeId, forward: bool) -> Option<NodeId> {
+        let siblings = self.element_children(self.parent(id)?);
+        let pos = siblings.iter().position(|&s| s == id)?;
+        if forward {
+            siblings.get(pos + 1).copied()
+        } else {
+            pos.checked_sub(1).map(|p| siblings[p])
+        }
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors.
+    pub fn contains(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut cur = Some(id);
+        while let Some(n) = cur {
+            if n == ancestor {
+                return true;
+            }
+            cur = self.nodes[n].parent;
+        }
+        false
+    }
+
+    /// Remove `id` from its parent; it can be re-inserted later.
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&c| c != id);
+        }
+    }
+
+    /// Move `child` to the end of `parent`. Refused (false) if it would create a cycle.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) -> bool {
+        self.insert_at(parent, child, None)
+    }
+
+    /// Move `child` into `reference`'s parent, before (or after) `reference`.
+    pub fn insert_beside(&mut self, reference: NodeId, child: NodeId, after: bool) -> bool {
+        let Some(parent) = self.parent(reference) else {
+            return false;
+        };
+        if child == reference || self.contains(child, parent) {
+            return false;
+        }
+        self.detach(child);
+        let Some(pos) = self.nodes[parent]
+            .children
+            .iter()
+            .position(|&c| c == reference)
+        else {
+            return false;
+        };
+        self.attach(parent, child, Some(pos + usize::from(after)));
+        true
+    }
+
+    fn insert_at(&mut self, parent: NodeId, child: NodeId, index: Option<usize>) -> bool {
+        if child == Self::ROOT || self.contains(child, parent) {
+            return false;
+        }
+        self.detach(child);
+        self.attach(parent, child, index);
+        true
+    }
+
+    /// Put `new` where `old` is and detach `old`.
+    pub fn replace(&mut self, old: NodeId, new: NodeId) -> bool {
+        if old == new {
+            return true;
+        }
+        if !self.insert_beside(old, new, false) {
+            return false;
+        }
+        self.detach(old);
+        true
+    }
+
+    /// Detached copy of `id` (with its subtree when `deep`).
+    pub fn clone_node(&mut self, id: NodeId, deep: bool) -> NodeId {
+        let copy = self.push(self.nodes[id].data.clone());
+        if deep {
+            for child in self.nodes[id].children.clone() {
+                let c = self.clone_node(child, true);
+                self.attach(copy, c, None);
+            }
+        }
+        copy
+    }
+
+    fn clear_children(&mut self, id: NodeId) {
+        for child in std::mem::take(&mut self.nodes[id].children) {
+            self.nodes[child].parent = None;
+        }
+    }
+
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        self.collect_text(id, &mut out);
+        out
+    }
+
+    fn collect_text(&self, id: NodeId, out: &mut String) {
+        match &self.nodes[id].data {
+            NodeData::Text(t) => out.push_str(t),
+            _ => {
+                for &c in &self.nodes[id].children {
+                    self.collect_text(c, out);
+                }
+            }
+        }
+    }
+
+    pub fn set_text_content(&mut self, id: NodeId, text: &str) {
+        if let NodeData::Text(t) = &mut self.nodes[id].data {
+            *t = text.to_string();
+            return;
+        }
+        self.clear_children(id);
+        if !text.is_empty() {
+            let t = self.create_text(text);
+            self.attach(id, t, None);
+        }
+    }
+
+    pub fn inner_html(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        let raw = self.tag(id).is_some_and(|t| RAW_TEXT_ELEMENTS.contains(&t));
+        for &c in &self.nodes[id].children {
+            self.serialize(c, raw, false, &mut out);
+        }
+        out
+    }
+
+    pub fn set_inner_html(&mut self, id: NodeId, html: &str) {
+        if !self.is_element(id) && id != Self::ROOT {
+            return;
+        }
+        self.clear_children(id);
+        self.parse_into(id, html);
+    }
+
+    /// The whole document as HTML.
+    pub fn to_html(&self) -> String {
+        self.inner_html(Self::ROOT)
+    }
+
+    /// The document as HTML without `<script>` elements, i.e. what gets indexed.
+    pub fn to_static_html(&self) -> String {
+        let mut out = String::new();
+        for &c in &self.nodes[Self::ROOT].children {
+            self.serialize(c, false, true, &mut out);
+        }
+        out
+    }
+
+    fn serialize(&self, id: NodeId, raw_text: bool, skip_scripts: bool, out: &mut String) {
+        match &self.nodes[id].data {
+            NodeData::Document => {
+                for &c in &self.nodes[id].children {
+                    self.serialize(c, false, skip_scripts, out);
+                }
+            }
+            NodeData::Text(t) if raw_text => out.push_str(t),
+            NodeData::Text(t) => escape_into(t, false, out),
+            NodeData::Element { tag, attrs } => {
+                if skip_scripts && tag == "script" {
+                    return;
+                }
+                out.push('<');
+                out.push_str(tag);
+                for (k, v) in attrs {
+                    out.push(' ');
+                    out.push_str(k);
+                    if !v.is_empty() {
+                        out.push_str("=\"");
+                        escape_into(v, true, out);
+                        out.push('"');
+                    }
+                }
+                out.push('>');
+                if VOID_ELEMENTS.contains(&tag.as_str()) {
+                    return;
+                }
+                let raw = RAW_TEXT_ELEMENTS.contains(&tag.as_str());
+                for &c in &self.nodes[id].children {
+                    self.serialize(c, raw, skip_scripts, out);
+                }
+                out.push_str("</");
+                out.push_str(tag);
+                out.push('>');
+            }
+        }
+    }
+
+    /// `<body>`, or the document node for fragments without one.
+    pub fn body(&self) -> NodeId {
+        self.descendants(Self::ROOT)
+            .into_iter()
+            .find(|&n| self.tag(n) == Some("body"))
+            .unwrap_or(Self::ROOT)
+    }
+
+    /// Elements below `scope` in document order.
+    fn descendants(&self, scope: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut stack: Vec<NodeId> = self.nodes[scope].children.iter().rev().copied().collect();
+        while let Some(n) = stack.pop() {
+            if self.is_element(n) {
+                out.push(n);
+            }
+            stack.extend(self.nodes[n].children.iter().rev());
+        }
+        out
+    }
+
+    /// First element below `scope` matching `selector`; see [`Dom::select_all`].
+    pub fn select(&self, scope: NodeId, selector: &str) -> Option<NodeId> {
+        let sel = Selector::parse(selector)?;
+        self.descendants(scope)
+            .into_iter()
+            .find(|&n| sel.matches(self, n))
+    }
+
+    /// Elements below `scope` matching a selector list such as `div.card > a[href], #nav`.
+    /// Supported: type, `*`, `#id`, `.class`, `[attr]`, `[attr=value]`, and the
+    /// descendant and child combinators. Unsupported selectors match nothing.
+    pub fn select_all(&self, scope: NodeId, selector: &str) -> Vec<NodeId> {
+        let Some(sel) = Selector::parse(selector) else {
+            return Vec::new();
+        };
+        self.descendants(scope)
+            .into_iter()
+            .filter(|&n| sel.matches(self, n))
+            .collect()
+    }
+}
+
+fn escape_into(s: &str, attr: bool, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn matches(&self, dom: &Dom, n: NodeId) -> bool {
+        let Some(tag) = dom.tag(n) else {
+            return false;
+        };
+        if self.tag.as_deref().is_some_and(|t| t != tag) {
+            return false;
+        }
+        if self.id.is_some() && dom.attr(n, "id") != self.id.as_deref() {
+            return false;
+        }
+        let class = dom.attr(n, "class").unwrap_or("");
+        if !self
+            .classes
+            .iter()
+            .all(|c| class.split_whitespace().any(|x| x == c))
+        {
+            return false;
+        }
+        self.attrs.iter().all(|(k, v)| match (dom.attr(n, k), v) {
+            (Some(actual), Some(want)) => actual == want,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+    }
+}
+
+/// Parsed selector list; each entry is a chain of compounds where `true` marks a child
+/// (`>`) relation to the previous compound.
+struct Selector(Vec<Vec<(bool, Compound)>>);
+
+impl Selector {
+    fn parse(input: &str) -> Option<Self> {
+        let mut list = Vec::new();
+        for part in input.split(',') {
+            let mut chain: Vec<(bool, Compound)> = Vec::new();
+            let mut child = false;
+            let spaced = part.replace('>', " > ");
+            for word in spaced.split_whitespace() {
+                if word == ">" {
+                    if chain.is_empty() || child {
+                        return None;
+                    }
+                    child = true;
+                    continue;
+                }
+                chain.push((child, parse_compound(word)?));
+                child = false;
+            }
+            if chain.is_empty() || child {
+                return None;
+            }
+            list.push(chain);
+        }
+        Some(Self(list))
+    }
+
+    fn matches(&self, dom: &Dom, n: NodeId) -> bool {
+        self.0.iter().any(|chain| matches_chain(dom, n, chain))
+    }
+}
+
+fn matches_chain(dom: &Dom, n: NodeId, chain: &[(bool, Compound)]) -> bool {
+    let Some(((child, last), rest)) = chain.split_last() else {
+        return true;
+    };
+    if !last.matches(dom, n) {
+        return false;
+    }
+    if rest.is_empty() {
+        return true;
+    }
+    let mut cur = dom.parent(n);
+    while let Some(p) = cur {
+        if matches_chain(dom, p, rest) {
+            return true;
+        }
+        if *child {
+            return false;
+        }
+        cur = dom.parent(p);
+    }
+    false
+}
+
+fn parse_compound(s: &str) -> Option<Compound> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut out = Compound::default();
+    let mut rest = s;
+    let tag_len = rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len());
+    if tag_len > 0 {
+        out.tag = Some(rest[..tag_len].to_ascii_lowercase());
+        rest = &rest[tag_len..];
+    } else if let Some(r) = rest.strip_prefix('*') {
+        rest = r;
+    }
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '#' | '.' => {
+                let body = &rest[1..];
+                let len = body.find(|c: char| !is_ident(c)).unwrap_or(body.len());
+                if len == 0 {
+                    return None;
+                }
+                if c == '#' {
+                    out.id = Some(body[..len].to_string());
+                } else {
+                    out.classes.push(body[..len].to_string());
+                }
+                rest = &body[len..];
+            }
+            '[' => {
+                let end = rest.find(']')?;
+                let inner = &rest[1..end];
+                let (name, value) = match inner.split_once('=') {
+                    Some((k, v)) => (k, Some(v.trim().trim_matches(['"', '\'']).to_string())),
+                    None => (inner, None),
+                };
+                let name = name.trim();
+                if name.is_empty() || !name.chars().all(is_ident) {
+                    return None;
+                }
+                out.attrs.push((name.to_ascii_lowercase(), value));
+                rest = &rest[end + 1..];
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_serializes_round_trip() {
+        let html = r#"<html><body><p class="a b">x &amp; y<br>z</p><script>if (a<b) {}</script></body></html>"#;
+        let dom = Dom::parse(html);
+        assert_eq!(dom.to_html(), html);
+        assert_eq!(
+            dom.to_static_html(),
+            r#"<html><body><p class="a b">x &amp; y<br>z</p></body></html>"#
+        );
+        assert_eq!(dom.tag(dom.body()), Some("body"));
+    }
+
+    #[test]
+    fn forgives_stray_and_missing_end_tags() {
+        let dom = Dom::parse("<div><span>a</div></b><p>b");
+        assert_eq!(dom.to_html(), "<div><span>a</span></div><p>b</p>");
+        assert_eq!(dom.body(), Dom::ROOT);
+    }
+
+    #[test]
+    fn selectors() {
+        let dom = Dom::parse(
+            r#"<div id="nav"><a class="x y" href="/1">1</a><p><a href="/2">2</a></p></div><a data-k="v">3</a>"#,
+        );
+        let texts = |sel: &str| -> Vec<String> {
+            dom.select_all(Dom::ROOT, sel)
+                .into_iter()
+                .map(|n| dom.text_content(n))
+                .collect()
+        };
+        assert_eq!(texts("a"), vec!["1", "2", "3"]);
+        assert_eq!(texts("#nav > a"), vec!["1"]);
+        assert_eq!(texts("div a[href]"), vec!["1", "2"]);
+        assert_eq!(texts("a.y.x, a[data-k='v']"), vec!["1", "3"]);
+        assert_eq!(texts("*#nav p > a"), vec!["2"]);
+        assert!(texts("a:hover").is_empty());
+        assert!(texts("> a").is_empty());
+        assert_eq!(
+            dom.select(Dom::ROOT, "p").map(|n| dom.tag(n)),
+            Some(Some("p"))
+        );
+    }
+
+    #[test]
+    fn mutations() {
+        let mut dom = Dom::parse(r#"<ul id="l"><li>a</li><li>c</li></ul>"#);
+        let list = dom.select(Dom::ROOT, "#l").unwrap();
+        let items = dom.element_children(list);
+        let b = dom.create_element("LI");
+        dom.set_text_content(b, "b<");
+        assert!(dom.insert_beside(items[0], b, true));
+        assert_eq!(dom.inner_html(list), "<li>a</li><li>b&lt;</li><li>c</li>");
+        assert_eq!(dom.element_sibling(b, true), Some(items[1]));
+        assert_eq!(dom.element_sibling(b, false), Some(items[0]));
+
+        let copy = dom.clone_node(list, true);
+        assert!(!dom.append_child(b, list), "cycle refused");
+        assert!(dom.replace(items[1], copy));
+        dom.set_attr(copy, "id", "copy");
+        assert_eq!(
+            dom.to_html(),
+            r#"<ul id="l"><li>a</li><li>b&lt;</li><ul id="copy"><li>a</li><li>b&lt;</li><li>c</li></ul></ul>"#
+        );
+        dom.set_inner_html(list, "<b>new</b>");
+        assert_eq!(dom.text_content(Dom::ROOT), "new");
+        dom.detach(list);
+        assert_eq!(dom.to_html(), "");
+    }
+}
@@ -0,0 +1,230 @@
+//! HTML-to-text extraction run before indexing.
+//!
+//! Splits a page into the fields the index weights separately: title, headings, meta
+//! description, image alt text and the remaining visible body text. Script, style and
+//! template contents never reach the index.
+
+use crate::crawler::html::{tokenize, Token};
+
+/// Text fields pulled out of an HTML page. All fields are whitespace-collapsed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractedPage {
+    pub title: String,
+    /// `<h1>`..`<h6>` texts, one per line.
+    pub headings: String,
+    /// `<meta name="description">` (or `og:description`) content.
+    pub description: String,
+    /// `alt` attributes of images, space separated.
+    pub alt_text: String,
+    /// Visible body text, including heading text.
+    pub text: String,
+}
+
+/// Elements whose contents are never visible text.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style", "template", "noscript"];
+
+/// Elements that break the flow of text; words on either side must not be glued.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "option",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+fn is_heading(name: &str) -> bool {
+    matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Extract indexable text fields from an HTML document.
+pub fn extract_page(html: &str) -> ExtractedPage {
+    let mut page = ExtractedPage::default();
+    let mut text = String::new();
+    let mut title = String::new();
+    let mut heading = String::new();
+    let mut headings: Vec<String> = Vec::new();
+    let mut alts: Vec<String> = Vec::new();
+    let mut og_description: Option<String> = None;
+
+    let mut hidden_depth = 0usize;
+    let mut in_head = false;
+    let mut in_title = false;
+    let mut heading_depth = 0usize;
+
+    for token in tokenize(html) {
+        match &token {
+            Token::StartTag {
+                name, self_closing, ..
+            } => {
+                let name = name.as_str();
+                if HIDDEN_ELEMENTS.contains(&name) {
+                    if !self_closing {
+                        hidden_depth += 1;
+                    }
+                    continue;
+                }
+                match name {
+                    "head" => in_head = true,
+                    "body" => in_head = false,
+                    "title" if !self_closing => in_title = true,
+                    "meta" => match (token.attr("name"), token.attr("property")) {
+                        (Some(n), _) if n.eq_ignore_ascii_case("description") => {
+                            page.description = collapse(token.attr("content").unwrap_or(""));
+                        }
+                        (_, Some(p)) if p.eq_ignore_ascii_case("og:description") => {
+                            og_description = token.attr("content").map(collapse);
+                        }
+                        _ => {}
+                    },
+                    "img" | "area" => {
+                        if let Some(alt) = token.attr("alt").map(collapse) {
+                            if !alt.is_empty() {
+                                alts.push(alt);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                if is_heading(name) && !self_closing {
+                    heading_depth += 1;
+                }
+                if BLOCK_ELEMENTS.contains(&name) {
+                    text.push(' ');
+                }
+            }
+            Token::EndTag { name } => {
+                let name = name.as_str();
+                if HIDDEN_ELEMENTS.contains(&name) {
+                    hidden_depth = hidden_depth.saturating_sub(1);
+                    continue;
+                }
+                match name {
+                    "head" => in_head = false,
+                    "title" if in_title => {
+                        in_title = false;
+                        if page.title.is_empty() {
+                            page.title = collapse(&title);
+                        }
+                        title.clear();
+                    }
+                    _ => {}
+                }
+                if is_heading(name) && heading_depth > 0 {
+                    heading_depth -= 1;
+                    if heading_depth == 0 {
+                        let h = collapse(&heading);
+                        if !h.is_empty() {
+                            headings.push(h);
+                        }
+                        heading.clear();
+                    }
+                }
+                if BLOCK_ELEMENTS.contains(&name) {
+                    text.push(' ');
+                }
+            }
+            Token::Text(t) => {
+                if hidden_depth > 0 {
+                    continue;
+                }
+                if in_title {
+                    title.push_str(t);
+                    continue;
+                }
+                if in_head {
+                    continue;
+                }
+                if heading_depth > 0 {
+                    heading.push_str(t);
+                }
+                text.push_str(t);
+            }
+        }
+    }
+
+    if in_title && page.title.is_empty() {
+        // Unterminated <title>: take what we have rather than dropping it.
+        page.title = collapse(&title);
+    }
+    if page.description.is_empty() {
+        page.description = og_description.unwrap_or_default();
+    }
+    page.headings = headings.join("\n");
+    page.alt_text = alts.join(" ");
+    page.text = collapse(&text);
+    page
+}
+
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_fields_and_drops_markup() {
+        let html = r#"<!DOCTYPE html>
+<html><head>
+  <title> Rust  &amp; Friends </title>
+  <meta name="Description" content="All about rust">
+  <style>.title { color: red }</style>
+</head>
+<body style="bg-[#000]">
+  <h1>Welcome</h1><p>Hello <b>wor</b>ld</p>
+  <img src="/a.png" alt="A crab">
+  <script type="text/lua">gurt.select('#x')</script>
+  <div class="text-sm">Bye</div>
+</body></html>"#;
+        let page = extract_page(html);
+        assert_eq!(page.title, "Rust & Friends");
+        assert_eq!(page.description, "All about rust");
+        assert_eq!(page.headings, "Welcome");
+        assert_eq!(page.alt_text, "A crab");
+        assert_eq!(page.text, "Welcome Hello world Bye");
+    }
+
+    #[test]
+    fn plain_text_passes_through() {
+        let page = extract_page("just   some\ntext");
+        assert_eq!(page.text, "just some text");
+        assert!(page.title.is_empty());
+    }
+
+    #[test]
+    fn og_description_is_a_fallback() {
+        let page = extract_page(r#"<meta property="og:description" content="From OG">"#);
+        assert_eq!(page.description, "From OG");
+    }
+}
@@ -0,0 +1,190 @@
+//! Crawl frontier policy for one domain.
+//!
+//! Seeds (root + sitemap) start at depth 0; outlinks of a page at depth `d` enter at
+//! `d + 1`. URLs are canonicalized on the way in. The queue itself lives in Postgres
+//! (`storage::queue`), which also enforces the page budget and never queues a URL twice;
+//! this module decides which links are followed and reports links to other domains
+//! according to [`LinkPolicy`].
+
+use std::collections::HashSet;
+
+use crate::link::canonicalize_url;
+
+pub const DEFAULT_MAX_DEPTH: usize = 3;
+pub const DEFAULT_MAX_PAGES: usize = 64;
+
+/// What to do with links that leave the domain being crawled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPolicy {
+    /// Ignore links to other domains.
+    SameDomain,
+    /// Report other domains so they can be queued for their own crawl.
+    CrossDomain,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrontierConfig {
+    /// Maximum link distance from a seed URL.
+    pub max_depth: usize,
+    /// Maximum number of URLs queued per domain.
+    pub max_pages: usize,
+    pub policy: LinkPolicy,
+}
+
+impl Default for FrontierConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_pages: DEFAULT_MAX_PAGES,
+            policy: LinkPolicy::CrossDomain,
+        }
+    }
+}
+
+impl FrontierConfig {
+    /// Read overrides from `GURT_CRAWL_MAX_DEPTH`, `GURT_CRAWL_MAX_PAGES` and
+    /// `GURT_CRAWL_FOLLOW_CROSS_DOMAIN` (`0` keeps the crawl on one domain).
+    pub fn from_env() -> Self {
+        let mut cfg = Self::default();
+        if let Some(v) = env_usize("GURT_CRAWL_MAX_DEPTH") {
+            cfg.max_depth = v;
+        }
+        if let Some(v) = env_usize("GURT_CRAWL_MAX_PAGES") {
+            cfg.max_pages = v.max(1);
+        }
+        if let Ok(v) = std::env::var("GURT_CRAWL_FOLLOW_CROSS_DOMAIN") {
+            if matches!(v.trim(), "0" | "false" | "no" | "off") {
+                cfg.policy = LinkPolicy::SameDomain;
+            }
+        }
+        cfg
+    }
+}
+
+fn env_usize(key: &str) -> Option<usize> {
+    std::env::var(key)
+        .ok()
+        .and_then(|s| s.trim().parse::<usize>().ok())
+}
+
+/// Links of one page, split by where they go.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outlinks {
+    /// Canonical same-domain URLs to queue at `depth + 1` (empty once `max_depth` is
+    /// reached), deduplicated within the page.
+    pub same_domain: Vec<String>,
+    /// Other domains linked from the page (empty under [`LinkPolicy::SameDomain`]).
+    pub foreign_domains: Vec<String>,
+}
+
+pub struct Frontier {
+    domain: String,
+    cfg: FrontierConfig,
+}
+
+impl Frontier {
+    pub fn new(domain: &str, cfg: FrontierConfig) -> Self {
+        Self {
+            domain: domain.to_ascii_lowercase(),
+            cfg,
+        }
+    }
+
+    pub fn config(&self) -> &FrontierConfig {
+        &self.cfg
+    }
+
+    /// Canonical form of a depth-0 URL, or None if it is off-domain or not a gurt URL.
+    pub fn seed(&self, url: &str) -> Option<String> {
+        canonicalize_url(url).filter(|u| host_of(u).as_deref() == Some(self.domain.as_str()))
+    }
+
+    /// Split the outlinks of a page fetched at `depth`.
+    pub fn outlinks<I, S>(&self, depth: usize, links: I) -> Outlinks
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut out = Outlinks::default();
+        let mut seen = HashSet::new();
+        let mut foreign_seen = HashSet::new();
+        for link in links {
+            let Some(url) = canonicalize_url(link.as_ref()) else {
+                continue;
+            };
+            let Some(host) = host_of(&url) else {
+                continue;
+            };
+            if host == self.domain {
+                if depth < self.cfg.max_depth && seen.insert(url.clone()) {
+                    out.same_domain.push(url);
+                }
+            } else if self.cfg.policy == LinkPolicy::CrossDomain
+                && foreign_seen.insert(host.clone())
+            {
+                out.foreign_domains.push(host);
+            }
+        }
+        out
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    if parsed.scheme() != "gurt" {
+        return None;
+    }
+    parsed.host_str().map(|h| h.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(max_depth: usize, policy: LinkPolicy) -> FrontierConfig {
+        FrontierConfig {
+            max_depth,
+            max_pages: 10,
+            policy,
+        }
+    }
+
+    #[test]
+    fn seeds_are_canonical_and_on_domain() {
+        let f = Frontier::new("a.real", cfg(3, LinkPolicy::SameDomain));
+        assert_eq!(
+            f.seed("gurt://A.real:4878/x/#top").as_deref(),
+            Some("gurt://a.real/x")
+        );
+        assert!(f.seed("gurt://b.real/").is_none());
+        assert!(f.seed("https://a.real/").is_none());
+    }
+
+    #[test]
+    fn outlinks_are_deduped_and_depth_limited() {
+        let f = Frontier::new("a.real", cfg(1, LinkPolicy::SameDomain));
+        let links = [
+            "gurt://a.real/x",
+            "gurt://a.real/y",
+            "gurt://a.real/x#frag",
+            "gurt://b.real/",
+        ];
+        let out = f.outlinks(0, links);
+        assert_eq!(out.same_domain, vec!["gurt://a.real/x", "gurt://a.real/y"]);
+        assert!(out.foreign_domains.is_empty());
+        // depth 1 == max_depth: its links are not followed
+        assert!(f.outlinks(1, links).same_domain.is_empty());
+    }
+
+    #[test]
+    fn cross_domain_policy_reports_domains_once_per_page() {
+        let f = Frontier::new("a.real", cfg(2, LinkPolicy::CrossDomain));
+        let out = f.outlinks(0, ["gurt://B.real/x", "gurt://b.real/y", "gurt://c.real/"]);
+        assert_eq!(out.foreign_domains, vec!["b.real", "c.real"]);
+        // Reported even past max_depth: discovery is independent of this crawl's depth.
+        assert_eq!(
+            f.outlinks(2, ["gurt://d.real/"]).foreign_domains,
+            vec!["d.real"]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Minimal, forgiving HTML tokenizer.
+//!
+//! This is not a spec-compliant HTML5 parser; it only needs to be good enough to pull
+//! text, attributes and links out of the pages GURT sites serve. Malformed markup never
+//! fails: unknown constructs degrade to text.
+
+/// A lexical HTML token. Tag and attribute names are lowercased; text and attribute
+/// values have character references decoded, except raw-text element bodies
+/// (`<script>`, `<style>`) which are passed through verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    StartTag {
+        name: String,
+        attrs: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    EndTag {
+        name: String,
+    },
+    Text(String),
+}
+
+impl Token {
+    /// Attribute value by (lowercase) name, for start tags.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        match self {
+            Token::StartTag { attrs, .. } => attrs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// Elements whose content is raw text up to the matching end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Split `html` into tokens. Comments, doctypes and processing instructions are dropped.
+pub fn tokenize(html: &str) -> Vec<Token> {
+    let bytes = html.as_bytes();
+    let len = bytes.len();
+    let mut out = Vec::new();
+    let mut text_start = 0usize;
+    let mut i = 0usize;
+
+    let flush_text = |out: &mut Vec<Token>, from: usize, to: usize| {
+        if from < to {
+            let text = decode_entities(&html[from..to]);
+            if !text.is_empty() {
+                out.push(Token::Text(text));
+            }
+        }
+    };
+
+    while i < len {
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+        let rest = &html[i..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            flush_text(&mut out, text_start, i);
+            i = match comment.find("-->") {
+                Some(p) => i + 4 + p + 3,
+                None => len,
+            };
+            text_start = i;
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            flush_text(&mut out, text_start, i);
+            i = match rest.find('>') {
+                Some(p) => i + p + 1,
+                None => len,
+            };
+            text_start = i;
+            continue;
+        }
+        let next = bytes.get(i + 1).copied().unwrap_or(0);
+        if next == b'/' && bytes.get(i + 2).is_some_and(|b| b.is_ascii_alphabetic()) {
+            flush_text(&mut out, text_start, i);
+            let end = find_tag_end(bytes, i + 2);
+            let name = read_name(&html[i + 2..end]);
+            out.push(Token::EndTag { name });
+            i = (end + 1).min(len);
+            text_start = i;
+            continue;
+        }
+        if next.is_ascii_alphabetic() {
+            flush_text(&mut out, text_start, i);
+            let end = find_tag_end(bytes, i + 1);
+            let inner = &html[i + 1..end];
+            let name = read_name(inner);
+            let self_closing = inner.trim_end().ends_with('/');
+            let attrs = parse_attrs(&inner[name.len()..]);
+            i = (end + 1).min(len);
+            let raw = !self_closing && RAW_TEXT_ELEMENTS.contains(&name.as_str());
+            out.push(Token::StartTag {
+                name: name.clone(),
+                attrs,
+                self_closing,
+            });
+            if raw {
+                let close = format!("</{}", name);
+                let body_end = find_ascii_ci(&html[i..], &close)
+                    .map(|p| i + p)
+                    .unwrap_or(len);
+                if body_end > i {
+                    out.push(Token::Text(html[i..body_end].to_string()));
+                }
+                i = body_end;
+            }
+            text_start = i;
+            continue;
+        }
+        // A bare '<' (e.g. "a < b") is just text.
+        i += 1;
+    }
+    flush_text(&mut out, text_start, len);
+    out
+}
+
+/// Index of the closing '>' of a tag starting at `from`, honoring quoted attribute values.
+fn find_tag_end(bytes: &[u8], from: usize) -> usize {
+    let mut quote: Option<u8> = None;
+    let mut j = from;
+    while j < bytes.len() {
+        let b = bytes[j];
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => return j,
+            None => {}
+        }
+        j += 1;
+    }
+    bytes.len()
+}
+
+fn read_name(s: &str) -> String {
+    s.chars()
+        .take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn parse_attrs(s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    loop {
+        while chars
+            .peek()
+            .is_some_and(|(_, c)| c.is_whitespace() || *c == '/')
+        {
+            chars.next();
+        }
+        let Some(&(start, _)) = chars.peek() else {
+            break;
+        };
+        let mut end = s.len();
+        while let Some(&(j, c)) = chars.peek() {
+            if c.is_whitespace() || c == '=' || c == '/' {
+                end = j;
+                break;
+            }
+            chars.next();
+        }
+        let name = s[start..end].to_ascii_lowercase();
+        while chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.peek().is_some_and(|(_, c)| *c == '=') {
+            chars.next();
+            while chars.peek().is_some_and(|(_, c)| c.is_whitespace()) {
+                chars.next();
+            }
+            match chars.peek().map(|(_, c)| *c) {
+                Some(q @ ('"' | '\'')) => {
+                    chars.next();
+                    for (_, c) in chars.by_ref() {
+                        if c == q {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                Some(_) => {
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        value.push(c);
+                        chars.next();
+                    }
+                }
+                None => {}
+            }
+        }
+        if !name.is_empty() {
+            attrs.push((name, decode_entities(&value)));
+        }
+    }
+    attrs
+}
+
+fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    let h = haystack.as_bytes();
+    let n = needle.as_bytes();
+    if n.is_empty() || h.len() < n.len() {
+        return None;
+    }
+    (0..=h.len() - n.len()).find(|&i| h[i..i + n.len()].eq_ignore_ascii_case(n))
+}
+
+/// Decode the character references that show up in practice: the XML five, `&nbsp;`
+/// and numeric references. Unknown or malformed references are left untouched.
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest
+            .find(';')
+            .filter(|&semi| semi <= 10)
+            .and_then(|semi| decode_reference(&rest[1..semi]).map(|c| (c, semi)));
+        match decoded {
+            Some((c, semi)) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Character of an entity or numeric character reference, given without `&` and `;`.
+pub(crate) fn decode_reference(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse::<u32>().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenizes_tags_attrs_and_text() {
+        let toks = tokenize(r#"<A HREF='/x?a=1&amp;b=2' data-on>Hi &lt;3</a><br/>"#);
+        assert_eq!(
+            toks,
+            vec![
+                Token::StartTag {
+                    name: "a".into(),
+                    attrs: vec![
+                        ("href".into(), "/x?a=1&b=2".into()),
+                        ("data-on".into(), String::new())
+                    ],
+                    self_closing: false,
+                },
+                Token::Text("Hi <3".into()),
+                Token::EndTag { name: "a".into() },
+                Token::StartTag {
+                    name: "br".into(),
+                    attrs: vec![],
+                    self_closing: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_text_elements_and_comments() {
+        let toks = tokenize("<!DOCTYPE html><!-- c --><script>if (a<b) {}</SCRIPT>x");
+        assert_eq!(toks.len(), 4);
+        assert_eq!(toks[1], Token::Text("if (a<b) {}".into()));
+        assert_eq!(
+            toks[2],
+            Token::EndTag {
+                name: "script".into()
+            }
+        );
+        assert_eq!(toks[3], Token::Text("x".into()));
+    }
+
+    #[test]
+    fn quoted_gt_does_not_end_tag() {
+        let toks = tokenize(r#"<img alt="a > b" src=x.png>"#);
+        assert_eq!(toks[0].attr("alt"), Some("a > b"));
+        assert_eq!(toks[0].attr("src"), Some("x.png"));
+    }
+
+    #[test]
+    fn decodes_numeric_and_leaves_unknown_entities() {
+        assert_eq!(
+            decode_entities("&#65;&#x42;&bogus; & done"),
+            "AB&bogus; & done"
+        );
+    }
+}
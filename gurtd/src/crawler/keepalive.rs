@@ -0,0 +1,112 @@
+//! Keep-alive connections for [`GurtClient`](super::client::GurtClient).
+//!
+//! A fresh fetch pays for a TCP connect, the plaintext HANDSHAKE and a TLS handshake
+//! before its request. After a response framed by content-length the upgraded stream is
+//! parked here instead of closed, and the next request to the same host and port takes
+//! it. Parked streams expire after an idle TTL (below the server's 30s keep-alive) and
+//! each host keeps at most `max_per_host` of them.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_IDLE_TTL_MS: u64 = 20_000;
+const DEFAULT_MAX_PER_HOST: usize = 4;
+
+/// Parked streams per (host, port), oldest first, with the time they were parked.
+type IdleMap<S> = HashMap<(String, u16), Vec<(S, Instant)>>;
+
+pub struct ConnPool<S> {
+    idle_ttl: Duration,
+    max_per_host: usize,
+    idle: Mutex<IdleMap<S>>,
+}
+
+impl<S> ConnPool<S> {
+    pub fn new(idle_ttl: Duration, max_per_host: usize) -> Self {
+        Self {
+            idle_ttl,
+            max_per_host,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// False when connections are never kept (TTL or per-host limit of 0).
+    pub fn enabled(&self) -> bool {
+        !self.idle_ttl.is_zero() && self.max_per_host > 0
+    }
+
+    /// The most recently parked live stream to `host:port`; expired ones are dropped.
+    pub fn take(&self, host: &str, port: u16) -> Option<S> {
+        let mut idle = self.idle.lock().unwrap();
+        let key = (host.to_ascii_lowercase(), port);
+        let streams = idle.get_mut(&key)?;
+        streams.retain(|(_, since)| since.elapsed() < self.idle_ttl);
+        let stream = streams.pop().map(|(s, _)| s);
+        if streams.is_empty() {
+            idle.remove(&key);
+        }
+        stream
+    }
+
+    /// Park a stream whose last response was fully read. Returns false (and drops the
+    /// stream) when the host already has `max_per_host` idle connections.
+    pub fn put(&self, host: &str, port: u16, stream: S) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let streams = idle.entry((host.to_ascii_lowercase(), port)).or_default();
+        streams.retain(|(_, since)| since.elapsed() < self.idle_ttl);
+        if streams.len() >= self.max_per_host {
+            return false;
+        }
+        streams.push((stream, Instant::now()));
+        true
+    }
+
+    /// - GURT_KEEPALIVE_IDLE_MS (default 20000; 0 closes every connection after its response)
+    /// - GURT_KEEPALIVE_MAX_PER_HOST (default 4)
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("GURT_KEEPALIVE_IDLE_MS")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .map(|v| v.min(29_000))
+            .unwrap_or(DEFAULT_IDLE_TTL_MS);
+        let max_per_host = std::env::var("GURT_KEEPALIVE_MAX_PER_HOST")
+            .ok()
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_PER_HOST);
+        Self::new(Duration::from_millis(ttl), max_per_host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_newest_stream_per_host() {
+        let pool = ConnPool::new(Duration::from_secs(10), 2);
+        assert!(pool.put("A.real", 4878, 1));
+        assert!(pool.put("a.real", 4878, 2));
+        assert!(!pool.put("a.real", 4878, 3), "per-host limit");
+        assert!(pool.put("a.real", 9000, 4), "other port, other slot");
+        assert_eq!(pool.take("a.real", 4878), Some(2));
+        assert_eq!(pool.take("a.real", 4878), Some(1));
+        assert_eq!(pool.take("a.real", 4878), None);
+        assert_eq!(pool.take("b.real", 4878), None);
+    }
+
+    #[test]
+    fn idle_streams_expire() {
+        let pool = ConnPool::new(Duration::from_millis(20), 4);
+        assert!(pool.put("a.real", 4878, 1));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(pool.take("a.real", 4878), None);
+
+        let off = ConnPool::new(Duration::ZERO, 4);
+        assert!(!off.enabled());
+        assert!(!off.put("a.real", 4878, 1));
+    }
+}
@@ -0,0 +1,191 @@
+//! Page-level robots directives: `<meta name="robots">`, the meta tag named after our
+//! crawler and the `x-robots-tag` response header.
+//!
+//! Directives only ever restrict: `index`/`follow`/`all` are accepted but cannot undo a
+//! restriction given elsewhere for the same page.
+
+use crate::crawler::html::{tokenize, Token};
+
+/// What a page allows us to do with it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RobotsDirectives {
+    /// Keep the page out of the index (and remove it if it is there).
+    pub noindex: bool,
+    /// Do not queue the page's outlinks or record them in the link graph.
+    pub nofollow: bool,
+    /// Do not serve a cached copy. gurtd serves none, so this is informational only.
+    pub noarchive: bool,
+}
+
+impl RobotsDirectives {
+    /// Directives of a page from its response headers and, for HTML, its body.
+    pub fn for_page<'a>(
+        headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+        html: Option<&str>,
+        user_agent: &str,
+    ) -> Self {
+        let mut out = Self::default();
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("x-robots-tag") {
+                out.merge(Self::from_header(value, user_agent));
+            }
+        }
+        if let Some(html) = html {
+            out.merge(Self::from_html(html, user_agent));
+        }
+        out
+    }
+
+    /// Directives of `<meta name="robots">` and `<meta name="<agent token>">` tags.
+    pub fn from_html(html: &str, user_agent: &str) -> Self {
+        let token = agent_token(user_agent);
+        let mut out = Self::default();
+        for t in tokenize(html) {
+            if !matches!(&t, Token::StartTag { name, .. } if name == "meta") {
+                continue;
+            }
+            let (Some(name), Some(content)) = (t.attr("name"), t.attr("content")) else {
+                continue;
+            };
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(&token) {
+                out.merge(Self::parse_list(content));
+            }
+        }
+        out
+    }
+
+    /// Directives of one `x-robots-tag` header value. A value may be scoped to a crawler
+    /// with a `agent: ` prefix (`gurtd: noindex, nofollow`); scopes naming another agent
+    /// are ignored.
+    pub fn from_header(value: &str, user_agent: &str) -> Self {
+        let token = agent_token(user_agent);
+        let mut out = Self::default();
+        let mut applies = true;
+        for part in value.split(',') {
+            let mut part = part.trim();
+            if let Some((scope, rest)) = part.split_once(':') {
+                let scope = scope.trim();
+                // `unavailable_after: <date>` and friends carry a value, not a scope.
+                if !is_valued_directive(scope) {
+                    applies = scope.eq_ignore_ascii_case(&token);
+                    part = rest.trim();
+                }
+            }
+            if applies {
+                out.apply(part);
+            }
+        }
+        out
+    }
+
+    /// A comma (or whitespace) separated directive list such as `noindex, nofollow`.
+    pub fn parse_list(list: &str) -> Self {
+        let mut out = Self::default();
+        for d in list.split([',', ' ', '\t']) {
+            out.apply(d);
+        }
+        out
+    }
+
+    fn apply(&mut self, directive: &str) {
+        match directive.trim().to_ascii_lowercase().as_str() {
+            "noindex" => self.noindex = true,
+            "nofollow" => self.nofollow = true,
+            "noarchive" => self.noarchive = true,
+            "none" => {
+                self.noindex = true;
+                self.nofollow = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.noindex |= other.noindex;
+        self.nofollow |= other.nofollow;
+        self.noarchive |= other.noarchive;
+    }
+}
+
+fn is_valued_directive(name: &str) -> bool {
+    [
+        "unavailable_after",
+        "max-snippet",
+        "max-image-preview",
+        "max-video-preview",
+    ]
+    .iter()
+    .any(|d| name.eq_ignore_ascii_case(d))
+}
+
+/// Product token of a user-agent (`gurtd/0.1` -> `gurtd`), lowercased.
+fn agent_token(user_agent: &str) -> String {
+    user_agent
+        .split(['/', ' '])
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UA: &str = "gurtd/0.1";
+
+    #[test]
+    fn meta_robots_and_agent_meta() {
+        let html = r#"<head><meta name="ROBOTS" content="noindex"><meta name="gurtd" content="nofollow,noarchive"></head>"#;
+        assert_eq!(
+            RobotsDirectives::from_html(html, UA),
+            RobotsDirectives {
+                noindex: true,
+                nofollow: true,
+                noarchive: true,
+            }
+        );
+        let other =
+            r#"<meta name="otherbot" content="none"><meta name="robots" content="index, follow">"#;
+        assert_eq!(
+            RobotsDirectives::from_html(other, UA),
+            RobotsDirectives::default()
+        );
+        let none = r#"<meta name="robots" content="none">"#;
+        let d = RobotsDirectives::from_html(none, UA);
+        assert!(d.noindex && d.nofollow && !d.noarchive);
+    }
+
+    #[test]
+    fn header_values_with_agent_scopes() {
+        let d = RobotsDirectives::from_header("noindex, unavailable_after: 2030-01-01", UA);
+        assert!(d.noindex && !d.nofollow);
+        let d = RobotsDirectives::from_header("otherbot: noindex, nofollow", UA);
+        assert_eq!(d, RobotsDirectives::default());
+        let d = RobotsDirectives::from_header("otherbot: noindex, gurtd: nofollow", UA);
+        assert!(!d.noindex && d.nofollow);
+    }
+
+    #[test]
+    fn headers_and_meta_combine() {
+        let headers = [
+            ("content-type", "text/html"),
+            ("x-robots-tag", "noarchive"),
+            ("X-Robots-Tag", "gurtd: nofollow"),
+        ];
+        let d = RobotsDirectives::for_page(
+            headers.iter().copied(),
+            Some(r#"<meta name="robots" content="noindex">"#),
+            UA,
+        );
+        assert_eq!(
+            d,
+            RobotsDirectives {
+                noindex: true,
+                nofollow: true,
+                noarchive: true,
+            }
+        );
+    }
+}
@@ -1,6 +1,17 @@
 pub mod client;
+pub mod dom;
+pub mod extract;
+pub mod frontier;
+pub mod html;
+pub mod keepalive;
+pub mod meta_robots;
 pub mod pipeline;
+pub mod recrawl;
+pub mod redirect;
 pub mod render;
+pub mod resolver;
 pub mod robots;
 pub mod scheduler;
+pub mod script;
 pub mod sitemap;
+pub mod tls;
@@ -2,7 +2,8 @@ use anyhow::Result;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::crawler::render::{render_once, DynamicReason, RenderConfig};
+use crate::crawler::extract::extract_page;
+use crate::crawler::render::{render_once, DynamicReason, RenderConfig, DEFAULT_INSTRUCTION_LIMIT};
 use crate::index::{IndexDocument, IndexEngine};
 
 /// Item representing a dynamic page that timed out during render and should be re-crawled/rendered.
@@ -38,11 +39,17 @@ impl DynamicReCrawlQueue {
     pub async fn len(&self) -> usize {
         self.inner.lock().await.len()
     }
+
+    pub async fn is_empty(&self) -> bool {
+        self.inner.lock().await.is_empty()
+    }
 }
 
-/// Process a fetched HTML document through the selective render-once pipeline
-/// and add it to the index. If the dynamic render path times out, enqueue the
-/// URL for re-crawl/re-render.
+/// Process a fetched HTML document through the selective render-once pipeline,
+/// extract its text fields and add it to the index. `title` is used only when the
+/// page has no `<title>`. If the dynamic render path times out, enqueue the URL
+/// for re-crawl/re-render.
+#[allow(clippy::too_many_arguments)]
 pub async fn process_fetched_document(
     engine: &dyn IndexEngine,
     requeue: &DynamicReCrawlQueue,
@@ -71,6 +78,7 @@ pub async fn process_fetched_document(
 
 /// Same as `process_fetched_document` but allows overriding a simulated render cost
 /// (for tests). If `simulated_cost` > `render_budget`, the pipeline will mark timeout.
+#[allow(clippy::too_many_arguments)]
 pub async fn process_fetched_document_with_cost(
     engine: &dyn IndexEngine,
     requeue: &DynamicReCrawlQueue,
@@ -86,6 +94,8 @@ pub async fn process_fetched_document_with_cost(
     let cfg = RenderConfig {
         time_budget: render_budget,
         simulated_cost,
+        page_url: Some(url.to_string()),
+        instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
     };
     let outcome = render_once(html, &cfg).await;
 
@@ -103,11 +113,20 @@ pub async fn process_fetched_document_with_cost(
         );
     }
 
+    let page = extract_page(&outcome.content);
+    let title = if page.title.is_empty() {
+        title.to_string()
+    } else {
+        page.title
+    };
     let doc = IndexDocument {
         url: url.to_string(),
         domain: domain.to_string(),
-        title: title.to_string(),
-        content: outcome.content,
+        title,
+        content: page.text,
+        headings: page.headings,
+        description: page.description,
+        alt_text: page.alt_text,
         fetch_time,
         language: language.to_string(),
         render_mode: outcome.render_mode,
@@ -119,7 +138,7 @@ pub async fn process_fetched_document_with_cost(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::index::SearchHit;
+    use crate::index::SearchResults;
     use crate::query::ParsedQuery;
     use std::sync::Mutex as StdMutex;
 
@@ -135,14 +154,20 @@ mod tests {
             *self.last.lock().unwrap() = Some(doc);
             Ok(())
         }
+        fn delete_url(&self, _url: &str) -> Result<()> {
+            Ok(())
+        }
+        fn delete_domain(&self, _domain: &str) -> Result<()> {
+            Ok(())
+        }
         fn commit(&self) -> Result<()> {
             Ok(())
         }
         fn refresh(&self) -> Result<()> {
             Ok(())
         }
-        fn search(&self, _q: &ParsedQuery, _p: usize, _s: usize) -> Result<Vec<SearchHit>> {
-            Ok(vec![])
+        fn search(&self, _q: &ParsedQuery, _p: usize, _s: usize) -> Result<SearchResults> {
+            Ok(SearchResults::default())
         }
     }
 
@@ -168,6 +193,7 @@ mod tests {
         let doc = eng.last.lock().unwrap().clone().unwrap();
         assert_eq!(doc.render_mode, "rendered");
         assert!(!doc.content.contains("<script"));
+        assert_eq!(doc.content, "ok");
         assert_eq!(queue.len().await, 0);
     }
 
@@ -196,7 +222,7 @@ mod tests {
         .unwrap();
         let doc = eng.last.lock().unwrap().clone().unwrap();
         assert_eq!(doc.render_mode, "static");
-        assert_eq!(doc.content, html);
+        assert_eq!(doc.content, "call network.fetch(\"/api\")");
         assert_eq!(queue.len().await, 1);
         let drained = queue.drain().await;
         assert_eq!(drained[0].url, "gurt://ex/b");
@@ -226,7 +252,8 @@ mod tests {
         .unwrap();
         let doc = eng.last.lock().unwrap().clone().unwrap();
         assert_eq!(doc.render_mode, "static");
-        assert_eq!(doc.content, html);
+        assert_eq!(doc.content, "static");
+        assert_eq!(doc.title, "t");
         assert_eq!(queue.len().await, 0);
     }
 }
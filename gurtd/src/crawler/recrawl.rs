@@ -0,0 +1,240 @@
+//! Adaptive recrawl intervals.
+//!
+//! Each crawled URL has a recrawl interval. It starts from sitemap hints (`<changefreq>`,
+//! else the age of `<lastmod>`), the domain's `crawl_interval_seconds`, or the default,
+//! and is re-estimated after every successful fetch from the URL's recent fetch history:
+//! pages that changed between most fetches are revisited sooner, stable ones later.
+//! Intervals stay within [`RecrawlPolicy::min`] and [`RecrawlPolicy::max`].
+
+use std::time::Duration;
+
+use super::sitemap::{ChangeFreq, SitemapHint};
+
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(3_600);
+pub const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(30 * 86_400);
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(86_400);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecrawlPolicy {
+    pub min: Duration,
+    pub max: Duration,
+    /// Interval of a URL with no hints and no history.
+    pub default: Duration,
+}
+
+impl Default for RecrawlPolicy {
+    fn default() -> Self {
+        Self {
+            min: DEFAULT_MIN_INTERVAL,
+            max: DEFAULT_MAX_INTERVAL,
+            default: DEFAULT_INTERVAL,
+        }
+    }
+}
+
+/// A successful fetch: when it happened and whether the content differed from the
+/// fetch before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observation {
+    /// Unix seconds.
+    pub at: i64,
+    pub changed: bool,
+}
+
+impl RecrawlPolicy {
+    /// Read overrides from `GURT_RECRAWL_MIN_SECS`, `GURT_RECRAWL_MAX_SECS` and
+    /// `GURT_RECRAWL_DEFAULT_SECS`.
+    pub fn from_env() -> Self {
+        let secs = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+        };
+        let mut policy = Self::default();
+        if let Some(v) = secs("GURT_RECRAWL_MIN_SECS") {
+            policy.min = v.max(Duration::from_secs(60));
+        }
+        if let Some(v) = secs("GURT_RECRAWL_MAX_SECS") {
+            policy.max = v;
+        }
+        policy.max = policy.max.max(policy.min);
+        if let Some(v) = secs("GURT_RECRAWL_DEFAULT_SECS") {
+            policy.default = v;
+        }
+        policy.default = policy.clamp(policy.default);
+        policy
+    }
+
+    pub fn clamp(&self, interval: Duration) -> Duration {
+        interval.clamp(self.min, self.max)
+    }
+
+    /// Starting interval from sitemap hints, or None without any. `now` is unix seconds.
+    pub fn initial_interval(&self, hint: &SitemapHint, now: i64) -> Option<Duration> {
+        const DAY: u64 = 86_400;
+        let interval = match (hint.changefreq, hint.lastmod) {
+            (Some(freq), _) => match freq {
+                ChangeFreq::Always => self.min,
+                ChangeFreq::Hourly => Duration::from_secs(3_600),
+                ChangeFreq::Daily => Duration::from_secs(DAY),
+                ChangeFreq::Weekly => Duration::from_secs(7 * DAY),
+                ChangeFreq::Monthly => Duration::from_secs(30 * DAY),
+                ChangeFreq::Yearly => Duration::from_secs(365 * DAY),
+                ChangeFreq::Never => self.max,
+            },
+            // Unchanged for a long time: likely to stay that way about as long again.
+            (None, Some(lastmod)) => Duration::from_secs(now.saturating_sub(lastmod).max(0) as u64),
+            (None, None) => return None,
+        };
+        Some(self.clamp(interval))
+    }
+
+    /// Interval after a fetch, given the current one and the URL's recent successful
+    /// fetches (oldest first).
+    ///
+    /// The change rate is estimated as `-ln((n - X + 0.5) / (n + 0.5)) / I` for `X`
+    /// changes seen over `n` fetch intervals of mean length `I` (Cho & Garcia-Molina);
+    /// the next interval is its inverse. It moves at most by a factor of two per fetch so
+    /// a short streak does not jump to a bound.
+    pub fn next_interval(&self, current: Duration, history: &[Observation]) -> Duration {
+        let current = self.clamp(current);
+        let (Some(first), Some(last)) = (history.first(), history.last()) else {
+            return current;
+        };
+        let n = (history.len() - 1) as f64;
+        let span = last.at - first.at;
+        if n == 0.0 || span <= 0 {
+            return current;
+        }
+        let changes = history[1..].iter().filter(|o| o.changed).count() as f64;
+        let mean_interval = span as f64 / n;
+        let rate = -((n - changes + 0.5) / (n + 0.5)).ln() / mean_interval;
+        let estimate = if rate > 0.0 {
+            1.0 / rate
+        } else {
+            f64::INFINITY
+        };
+        let current_secs = current.as_secs_f64();
+        let next = estimate.clamp(current_secs / 2.0, current_secs * 2.0);
+        self.clamp(Duration::from_secs_f64(next))
+    }
+}
+
+/// Observations from a URL's successful fetches (oldest first): `(at, None)` for a 304,
+/// `(at, Some(hash))` for a full response. A 304 is unchanged; a body counts as changed
+/// when its hash differs from the previous body's.
+pub fn observations<'a, I>(fetches: I) -> Vec<Observation>
+where
+    I: IntoIterator<Item = (i64, Option<&'a [u8]>)>,
+{
+    let mut previous: Option<&[u8]> = None;
+    fetches
+        .into_iter()
+        .map(|(at, hash)| {
+            let changed = match hash {
+                Some(h) => {
+                    let changed = previous.is_some_and(|p| p != h);
+                    previous = Some(h);
+                    changed
+                }
+                None => false,
+            };
+            Observation { at, changed }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3_600;
+    const DAY: u64 = 86_400;
+
+    fn history(interval: i64, changed: &[bool]) -> Vec<Observation> {
+        std::iter::once(false)
+            .chain(changed.iter().copied())
+            .enumerate()
+            .map(|(i, changed)| Observation {
+                at: i as i64 * interval,
+                changed,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stable_pages_back_off_and_changing_pages_speed_up() {
+        let policy = RecrawlPolicy::default();
+        let day = Duration::from_secs(DAY);
+        let stable = history(DAY as i64, &[false, false, false]);
+        assert_eq!(policy.next_interval(day, &stable), 2 * day);
+        let busy = history(DAY as i64, &[true, true, true, true]);
+        assert_eq!(policy.next_interval(day, &busy), day / 2);
+        // Changed in one of four fetches: a little under four days, capped at 2x.
+        let some = history(DAY as i64, &[false, true, false, false]);
+        assert_eq!(policy.next_interval(day, &some), 2 * day);
+        let some = history(DAY as i64, &[false, true, false, false]);
+        let next = policy.next_interval(4 * day, &some);
+        assert!(next > 3 * day && next < 4 * day, "{next:?}");
+    }
+
+    #[test]
+    fn intervals_stay_within_bounds() {
+        let policy = RecrawlPolicy::default();
+        let stable = history(DAY as i64, &[false; 5]);
+        assert_eq!(
+            policy.next_interval(policy.max, &stable),
+            DEFAULT_MAX_INTERVAL
+        );
+        let busy = history(HOUR as i64, &[true; 5]);
+        assert_eq!(
+            policy.next_interval(policy.min, &busy),
+            DEFAULT_MIN_INTERVAL
+        );
+        // Too little history keeps the current interval.
+        let day = Duration::from_secs(DAY);
+        assert_eq!(policy.next_interval(day, &history(DAY as i64, &[])), day);
+        assert_eq!(policy.next_interval(day, &[]), day);
+    }
+
+    #[test]
+    fn sitemap_hints_seed_intervals() {
+        let policy = RecrawlPolicy::default();
+        let now = 100 * DAY as i64;
+        let hint = |changefreq, lastmod| SitemapHint {
+            lastmod,
+            changefreq,
+            priority: None,
+        };
+        assert_eq!(policy.initial_interval(&hint(None, None), now), None);
+        assert_eq!(
+            policy.initial_interval(&hint(Some(ChangeFreq::Weekly), Some(now)), now),
+            Some(Duration::from_secs(7 * DAY))
+        );
+        assert_eq!(
+            policy.initial_interval(&hint(Some(ChangeFreq::Always), None), now),
+            Some(policy.min)
+        );
+        assert_eq!(
+            policy.initial_interval(&hint(Some(ChangeFreq::Yearly), None), now),
+            Some(policy.max)
+        );
+        assert_eq!(
+            policy.initial_interval(&hint(None, Some(now - 3 * DAY as i64)), now),
+            Some(Duration::from_secs(3 * DAY))
+        );
+    }
+
+    #[test]
+    fn observations_compare_consecutive_bodies() {
+        let obs = observations([
+            (1, Some(&b"a"[..])),
+            (2, None),
+            (3, Some(&b"a"[..])),
+            (4, Some(&b"b"[..])),
+        ]);
+        let changed: Vec<bool> = obs.iter().map(|o| o.changed).collect();
+        assert_eq!(changed, vec![false, false, false, true]);
+    }
+}
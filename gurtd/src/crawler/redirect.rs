@@ -0,0 +1,223 @@
+//! Redirect following for crawler fetches.
+//!
+//! 301, 302, 307 and 308 responses are followed through their `location` header,
+//! resolved against the URL that returned it. A chain stops after `max_hops` redirects
+//! or when it reaches a URL it already visited (compared in canonical form). With
+//! `same_domain` set, a redirect to another host ends the chain instead of being
+//! followed, so the caller can treat the target as a separate domain.
+
+use std::fmt;
+
+use crate::link::canonicalize_url;
+
+pub const DEFAULT_MAX_HOPS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    /// Redirects followed per fetch; 0 returns redirect responses as they are.
+    pub max_hops: usize,
+    /// Refuse redirects that leave the host of the requested URL.
+    pub same_domain: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_hops: DEFAULT_MAX_HOPS,
+            same_domain: true,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Read overrides from `GURT_MAX_REDIRECTS` and `GURT_REDIRECT_CROSS_DOMAIN` (`1`
+    /// follows redirects to other hosts).
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(v) = std::env::var("GURT_MAX_REDIRECTS")
+            .ok()
+            .and_then(|s| s.trim().parse::<usize>().ok())
+        {
+            policy.max_hops = v.min(32);
+        }
+        if let Ok(v) = std::env::var("GURT_REDIRECT_CROSS_DOMAIN") {
+            policy.same_domain = !matches!(v.trim(), "1" | "true" | "yes" | "on");
+        }
+        policy
+    }
+
+    /// Whether a response with `status` is followed under this policy.
+    pub fn follows(&self, status: u16) -> bool {
+        self.max_hops > 0 && is_redirect(status)
+    }
+}
+
+pub fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 307 | 308)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectError {
+    /// A redirect without a `location` header.
+    MissingLocation,
+    /// `location` is not a gurt:// URL, even relative to the redirecting URL.
+    InvalidLocation(String),
+    TooManyHops(usize),
+    /// The chain came back to a URL it already fetched.
+    Loop(String),
+    /// The redirect leaves the domain under a same-domain policy.
+    OffDomain(String),
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingLocation => write!(f, "redirect without location"),
+            Self::InvalidLocation(l) => write!(f, "invalid redirect location {l:?}"),
+            Self::TooManyHops(n) => write!(f, "more than {n} redirects"),
+            Self::Loop(url) => write!(f, "redirect loop at {url}"),
+            Self::OffDomain(url) => write!(f, "redirect to another domain: {url}"),
+        }
+    }
+}
+
+impl std::error::Error for RedirectError {}
+
+/// URLs visited by one fetch, starting with the requested URL.
+#[derive(Debug, Clone)]
+pub struct RedirectChain {
+    policy: RedirectPolicy,
+    urls: Vec<String>,
+    seen: Vec<String>,
+}
+
+impl RedirectChain {
+    pub fn new(url: &str, policy: RedirectPolicy) -> Self {
+        Self {
+            policy,
+            urls: vec![url.to_string()],
+            seen: vec![canonical(url)],
+        }
+    }
+
+    /// The URL to fetch next.
+    pub fn current(&self) -> &str {
+        self.urls.last().map(String::as_str).unwrap_or_default()
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    pub fn hops(&self) -> usize {
+        self.urls.len() - 1
+    }
+
+    /// Follow a redirect of the current URL to `location`; on success the target becomes
+    /// [`current`](Self::current).
+    pub fn follow(&mut self, location: Option<&str>) -> Result<&str, RedirectError> {
+        let location = location
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .ok_or(RedirectError::MissingLocation)?;
+        let target = url::Url::parse(self.current())
+            .and_then(|base| base.join(location))
+            .ok()
+            .filter(|u| u.scheme() == "gurt" && u.host_str().is_some())
+            .ok_or_else(|| RedirectError::InvalidLocation(location.to_string()))?;
+        let target = target.to_string();
+        if self.policy.same_domain && host_of(&target) != host_of(&self.urls[0]) {
+            return Err(RedirectError::OffDomain(target));
+        }
+        let key = canonical(&target);
+        if self.seen.contains(&key) {
+            return Err(RedirectError::Loop(target));
+        }
+        if self.hops() >= self.policy.max_hops {
+            return Err(RedirectError::TooManyHops(self.policy.max_hops));
+        }
+        self.seen.push(key);
+        self.urls.push(target);
+        Ok(self.current())
+    }
+}
+
+fn canonical(url: &str) -> String {
+    canonicalize_url(url).unwrap_or_else(|| url.to_string())
+}
+
+fn host_of(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    parsed
+        .host_str()
+        .map(|h| h.trim_end_matches('.').to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_relative_locations() {
+        let mut chain = RedirectChain::new("gurt://a.real/docs/", RedirectPolicy::default());
+        assert_eq!(chain.follow(Some("intro")), Ok("gurt://a.real/docs/intro"));
+        assert_eq!(
+            chain.follow(Some("/home?x=1")),
+            Ok("gurt://a.real/home?x=1")
+        );
+        assert_eq!(
+            chain.follow(Some("gurt://A.real/end")),
+            Ok("gurt://A.real/end")
+        );
+        assert_eq!(chain.hops(), 3);
+        assert_eq!(chain.urls()[0], "gurt://a.real/docs/");
+    }
+
+    #[test]
+    fn stops_on_loops_and_hop_limit() {
+        let mut chain = RedirectChain::new("gurt://a.real/", RedirectPolicy::default());
+        chain.follow(Some("/a")).unwrap();
+        // Same URL in another spelling is still a loop.
+        assert_eq!(
+            chain.follow(Some("gurt://a.real:4878/#top")),
+            Err(RedirectError::Loop("gurt://a.real:4878/#top".to_string()))
+        );
+
+        let policy = RedirectPolicy {
+            max_hops: 2,
+            same_domain: true,
+        };
+        let mut chain = RedirectChain::new("gurt://a.real/0", policy);
+        chain.follow(Some("/1")).unwrap();
+        chain.follow(Some("/2")).unwrap();
+        assert_eq!(chain.follow(Some("/3")), Err(RedirectError::TooManyHops(2)));
+        assert_eq!(chain.follow(None), Err(RedirectError::MissingLocation));
+        assert!(matches!(
+            chain.follow(Some("https://a.real/")),
+            Err(RedirectError::InvalidLocation(_))
+        ));
+    }
+
+    #[test]
+    fn same_domain_policy_refuses_other_hosts() {
+        let mut chain = RedirectChain::new("gurt://a.real/", RedirectPolicy::default());
+        assert_eq!(
+            chain.follow(Some("gurt://b.real/x")),
+            Err(RedirectError::OffDomain("gurt://b.real/x".to_string()))
+        );
+        assert_eq!(chain.current(), "gurt://a.real/");
+
+        let open = RedirectPolicy {
+            same_domain: false,
+            ..RedirectPolicy::default()
+        };
+        let mut chain = RedirectChain::new("gurt://a.real/", open);
+        assert_eq!(chain.follow(Some("gurt://b.real/x")), Ok("gurt://b.real/x"));
+        assert!(!RedirectPolicy {
+            max_hops: 0,
+            ..open
+        }
+        .follows(301));
+        assert!(open.follows(308) && !open.follows(304));
+    }
+}
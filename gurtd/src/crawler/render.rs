@@ -1,4 +1,25 @@
-use std::time::Duration;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+
+use super::client::ClientResponse;
+use super::script::{self, ScriptLimits, ScriptNetwork};
+
+/// Default VM instruction budget for a page's scripts.
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 10_000_000;
+const SCRIPT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+const SCRIPT_MAX_FETCHES: usize = 4;
+const RENDER_GRACE: Duration = Duration::from_millis(250);
+
+/// Fetches a URL on behalf of a page script.
+pub type ScriptFetchFn = dyn Fn(String) -> Pin<Box<dyn Future<Output = anyhow::Result<ClientResponse>> + Send>>
+    + Send
+    + Sync;
+
+static SCRIPT_FETCHER: OnceCell<Arc<ScriptFetchFn>> = OnceCell::new();
 
 /// Reasons that mark a page as a candidate for dynamic render.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,12 +28,25 @@ pub enum DynamicReason {
     NetworkFetch,
 }
 
+impl DynamicReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DynamicReason::LuaScriptTag => "lua_script_tag",
+            DynamicReason::NetworkFetch => "network_fetch",
+        }
+    }
+}
+
 /// Render configuration for the selective render-once pipeline.
 #[derive(Debug, Clone)]
 pub struct RenderConfig {
     pub time_budget: Duration,
     /// Internal/testing knob to simulate render work cost.
     pub simulated_cost: Option<Duration>,
+    /// URL the page was fetched from; relative script fetches resolve against it.
+    pub page_url: Option<String>,
+    /// VM instructions the page's scripts may execute in total.
+    pub instruction_limit: u64,
 }
 
 impl RenderConfig {
@@ -20,6 +54,8 @@ impl RenderConfig {
         Self {
             time_budget: Duration::from_millis(ms),
             simulated_cost: None,
+            page_url: None,
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
         }
     }
 }
@@ -48,9 +84,10 @@ pub fn detect_dynamic(html: &str) -> Option<DynamicReason> {
 }
 
 /// Perform a render-once pipeline if heuristics indicate dynamic content.
-/// This is a stub implementation: it removes <script>...</script> blocks and emits a
-/// "rendered" marker if completed within `time_budget`. If the budget is exceeded,
-/// returns the original content as static with `timed_out = true`.
+/// The page's Lua scripts run in a sandbox ([`script`](super::script)) against a DOM
+/// built from `html`; the resulting document, without `<script>` elements, is returned
+/// with a "rendered" marker. If `simulated_cost` exceeds `time_budget`, or the scripts
+/// use up the budget, the original content is returned as static with `timed_out = true`.
 pub async fn render_once(html: &str, cfg: &RenderConfig) -> RenderOutcome {
     let reason = detect_dynamic(html);
     if reason.is_none() {
@@ -61,30 +98,50 @@ pub async fn render_once(html: &str, cfg: &RenderConfig) -> RenderOutcome {
             reason: None,
         };
     }
+    let started = Instant::now();
+    let fallback = |reason| RenderOutcome {
+        content: html.to_string(),
+        render_mode: "static".into(),
+        timed_out: true,
+        reason,
+    };
 
-    // Enforce budget using a simulated cost (tests) or minimal yield
+    // Enforce budget using a simulated cost (tests)
     if let Some(cost) = cfg.simulated_cost {
         if cost > cfg.time_budget {
             // Exceeds budget: return static fallback
-            return RenderOutcome {
-                content: html.to_string(),
-                render_mode: "static".into(),
-                timed_out: true,
-                reason,
-            };
+            return fallback(reason);
         }
         // sleep to simulate work but within budget
         if !cost.is_zero() {
             tokio::time::sleep(cost).await;
         }
-    } else {
-        // Yield once to simulate minimal work without exceeding budget
-        tokio::task::yield_now().await;
     }
 
-    // Perform a simple transformation: strip <script> blocks and append a marker
-    let mut out = html.to_string();
-    out = strip_script_blocks(&out);
+    let limits = ScriptLimits {
+        deadline: started + cfg.time_budget,
+        instruction_limit: cfg.instruction_limit,
+        memory_limit: SCRIPT_MEMORY_LIMIT,
+        max_fetches: SCRIPT_MAX_FETCHES,
+    };
+    let network = ScriptNetwork {
+        fetcher: SCRIPT_FETCHER.get().cloned(),
+        handle: tokio::runtime::Handle::current(),
+        allowed_hosts: fetch_allowlist(),
+    };
+    let source = html.to_string();
+    let page_url = cfg.page_url.clone();
+    let remaining = cfg.time_budget.saturating_sub(started.elapsed());
+    // The VM hook stops scripts at the deadline; the grace covers a blocked fetch.
+    let task = tokio::task::spawn_blocking(move || {
+        script::run_page_scripts(&source, page_url.as_deref(), &limits, network)
+    });
+    let run = match tokio::time::timeout(remaining + RENDER_GRACE, task).await {
+        Ok(Ok(run)) if !run.timed_out => run,
+        _ => return fallback(reason),
+    };
+
+    let mut out = run.html;
     if !out.contains("<!-- rendered -->") {
         out.push_str("\n<!-- rendered -->");
     }
@@ -97,30 +154,23 @@ pub async fn render_once(html: &str, cfg: &RenderConfig) -> RenderOutcome {
     }
 }
 
-fn strip_script_blocks(input: &str) -> String {
-    // TODO: this is a naive scan; swap for a real HTML tokenizer when script edge cases start to bite.
-    let mut out = String::new();
-    let mut rest = input;
-    loop {
-        let lower = rest.to_ascii_lowercase();
-        if let Some(start) = lower.find("<script") {
-            out.push_str(&rest[..start]);
-            let after = &rest[start..];
-            if let Some(close_pos) = lower[start..].find("</script>") {
-                // skip the whole script segment
-                let end = start + close_pos + "</script>".len();
-                rest = &rest[end..];
-                continue;
-            } else {
-                // No closing tag; drop remainder
-                break;
-            }
-        } else {
-            out.push_str(rest);
-            break;
-        }
-    }
-    out
+/// Install the function page scripts fetch through (`network.fetch`, `<script src>`).
+/// Without one, script fetches fail with status 0.
+pub fn set_script_fetcher(fetcher: Arc<ScriptFetchFn>) {
+    let _ = SCRIPT_FETCHER.set(fetcher);
+}
+
+/// Hosts besides the page's own that scripts may fetch from, from
+/// `GURT_RENDER_FETCH_ALLOW` (comma-separated).
+fn fetch_allowlist() -> Vec<String> {
+    std::env::var("GURT_RENDER_FETCH_ALLOW")
+        .map(|v| {
+            v.split(',')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -174,4 +224,25 @@ mod tests {
         assert!(out.timed_out);
         assert_eq!(out.content, html);
     }
+
+    #[tokio::test]
+    async fn render_indexes_script_output() {
+        let html = "<html><body><div id=\"app\">loading</div><script type=\"text/lua\">\
+            gurt.select('#app').text = 'Hello from Lua'</script></body></html>";
+        let out = render_once(html, &RenderConfig::with_budget_ms(500)).await;
+        assert_eq!(out.render_mode, "rendered");
+        assert!(out.content.contains("<div id=\"app\">Hello from Lua</div>"));
+        assert!(!out.content.contains("<script"));
+    }
+
+    #[tokio::test]
+    async fn render_instruction_limit_falls_back_to_static() {
+        let html = "<body><script type=\"text/lua\">while true do end</script></body>";
+        let mut cfg = RenderConfig::with_budget_ms(2_000);
+        cfg.instruction_limit = 100_000;
+        let out = render_once(html, &cfg).await;
+        assert_eq!(out.render_mode, "static");
+        assert!(out.timed_out);
+        assert_eq!(out.content, html);
+    }
 }
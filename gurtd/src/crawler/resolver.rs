@@ -0,0 +1,141 @@
+//! Host name resolution for [`tcp_connector`](super::client::tcp_connector).
+//!
+//! Production resolves through GURT DNS
+//! ([`GurtDnsResolver`](crate::indexing::dns::GurtDnsResolver)); [`StaticResolver`] is a
+//! fixed table, optionally read from a hosts file, for tests and air-gapped setups.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+
+use anyhow::{Context, Result};
+
+pub type ResolveFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<IpAddr>, ResolveError>> + Send + 'a>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The name has no address (NXDOMAIN, or a CNAME chain that is too deep or loops).
+    NotFound,
+    /// No answer could be had (resolver unreachable, timed out or answered garbage).
+    Failed,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "name not found"),
+            Self::Failed => write!(f, "resolution failed"),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+pub trait Resolver: Send + Sync {
+    /// Addresses of `host`, preferred first, so a connect can fail over to the next one.
+    /// Never empty on success.
+    fn resolve<'a>(&'a self, host: &'a str) -> ResolveFuture<'a>;
+}
+
+/// Lowercase, without the trailing dot of a fully qualified name.
+pub fn normalize_host(host: &str) -> String {
+    host.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Fixed name-to-address table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `addr` to the addresses of `name`.
+    pub fn with_host(mut self, name: &str, addr: IpAddr) -> Self {
+        self.insert(name, addr);
+        self
+    }
+
+    pub fn insert(&mut self, name: &str, addr: IpAddr) {
+        let addrs = self.hosts.entry(normalize_host(name)).or_default();
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+
+    /// Entries of a hosts file: `<address> <name> [<alias>...]` per line, `#` comments.
+    /// Lines without a valid address are skipped.
+    pub fn parse_hosts(text: &str) -> Self {
+        let mut out = Self::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            let Some(Ok(addr)) = fields.next().map(str::parse::<IpAddr>) else {
+                continue;
+            };
+            for name in fields {
+                out.insert(name, addr);
+            }
+        }
+        out
+    }
+
+    pub fn from_hosts_file(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading hosts file '{path}'"))?;
+        Ok(Self::parse_hosts(&text))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> ResolveFuture<'a> {
+        let addrs = self.hosts.get(&normalize_host(host)).cloned();
+        Box::pin(async move { addrs.ok_or(ResolveError::NotFound) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[tokio::test]
+    async fn hosts_file_entries_and_aliases() {
+        let hosts = StaticResolver::parse_hosts(
+            "# test hosts\n\
+             10.0.0.1   example.real www.example.real  # primary\n\
+             2001:db8::1 example.real\n\
+             not-an-ip  broken.real\n\
+             10.0.0.2 Other.Real.\n",
+        );
+        assert_eq!(
+            hosts.resolve("WWW.example.real.").await,
+            Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))])
+        );
+        assert_eq!(
+            hosts.resolve("example.real").await,
+            Ok(vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            ])
+        );
+        assert_eq!(
+            hosts.resolve("other.real").await,
+            Ok(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))])
+        );
+        assert_eq!(
+            hosts.resolve("broken.real").await,
+            Err(ResolveError::NotFound)
+        );
+    }
+}
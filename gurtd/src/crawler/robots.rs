@@ -6,6 +6,8 @@ use std::time::Duration;
 pub struct RobotsTxt {
     /// Rules grouped by user-agent token (lowercased). `*` is the wildcard group.
     groups: Vec<AgentGroup>,
+    /// `Sitemap:` URLs, in file order. These are not tied to any group.
+    sitemaps: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,20 +19,25 @@ struct AgentGroup {
 }
 
 impl RobotsTxt {
-    /// Parse a robots.txt document with basic HTTP-like semantics.
-    /// - Supports User-agent, Allow, Disallow, Crawl-delay.
-    /// - Path matching is prefix-based. Longest rule wins; ties prefer Allow.
+    /// Parse a robots.txt document following RFC 9309.
+    /// - Supports User-agent, Allow, Disallow, Crawl-delay and Sitemap.
+    /// - Consecutive User-agent lines share one group; a User-agent line after rules starts
+    ///   a new group. Groups naming the same agent are merged.
+    /// - Rules before any User-agent line apply to `*`.
     /// - User-agent matches are case-insensitive and prefer the longest matching agent; fallback to `*`.
     pub fn parse(input: &str) -> Self {
         let mut groups: Vec<AgentGroup> = Vec::new();
+        let mut sitemaps: Vec<String> = Vec::new();
         let mut current_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
 
         for raw_line in input.lines() {
-            let line = raw_line.trim();
-            if line.is_empty() {
-                continue;
+            let line = match raw_line.split_once('#') {
+                Some((before, _)) => before,
+                None => raw_line,
             }
-            if line.starts_with('#') {
+            .trim();
+            if line.is_empty() {
                 continue;
             }
             let Some((k, v)) = line.split_once(':') else {
@@ -40,43 +47,31 @@ impl RobotsTxt {
             let val = v.trim();
             match key.as_str() {
                 "user-agent" => {
-                    let agent = val.to_ascii_lowercase();
-                    if current_agents.is_empty() {
+                    if in_rules {
                         current_agents.clear();
+                        in_rules = false;
                     }
+                    let agent = val.to_ascii_lowercase();
+                    get_or_create_group_index(&mut groups, &agent);
                     current_agents.push(agent);
-                    // ensure groups exist
-                    for a in &current_agents {
-                        get_or_create_group_index(&mut groups, a);
-                    }
                 }
-                "allow" => {
+                "allow" | "disallow" | "crawl-delay" => {
+                    in_rules = true;
                     if current_agents.is_empty() {
                         current_agents.push("*".to_string());
                     }
                     for a in &current_agents {
                         let idx = get_or_create_group_index(&mut groups, a);
-                        groups[idx].allow.push(val.to_string());
+                        let group = &mut groups[idx];
+                        match key.as_str() {
+                            "allow" => group.allow.push(normalize_pattern(val)),
+                            "disallow" => group.disallow.push(normalize_pattern(val)),
+                            _ => group.crawl_delay = parse_crawl_delay(val),
+                        }
                     }
                 }
-                "disallow" => {
-                    if current_agents.is_empty() {
-                        current_agents.push("*".to_string());
-                    }
-                    for a in &current_agents {
-                        let idx = get_or_create_group_index(&mut groups, a);
-                        groups[idx].disallow.push(val.to_string());
-                    }
-                }
-                "crawl-delay" => {
-                    let delay = parse_crawl_delay(val);
-                    if current_agents.is_empty() {
-                        current_agents.push("*".to_string());
-                    }
-                    for a in &current_agents {
-                        let idx = get_or_create_group_index(&mut groups, a);
-                        groups[idx].crawl_delay = delay;
-                    }
+                "sitemap" if !val.is_empty() && !sitemaps.iter().any(|s| s == val) => {
+                    sitemaps.push(val.to_string());
                 }
                 _ => {}
             }
@@ -91,56 +86,54 @@ impl RobotsTxt {
                 crawl_delay: None,
             });
         }
-        Self { groups }
+        Self { groups, sitemaps }
     }
 
-    /// Determine whether a path is allowed for the given user-agent token.
-    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
-        // Choose applicable group: exact/longest match of agent token; fallback to '*'
+    /// Sitemap URLs announced with `Sitemap:` directives.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+
+    /// Choose the applicable group: longest matching agent token, falling back to `*`.
+    fn group_for(&self, user_agent: &str) -> Option<&AgentGroup> {
         let ua = user_agent.to_ascii_lowercase();
         let mut best: Option<&AgentGroup> = None;
         for g in &self.groups {
             if g.agent == "*" || ua.contains(&g.agent) {
                 best = match best {
-                    None => Some(g),
-                    Some(prev) => {
-                        // prefer longer agent token (more specific)
-                        if g.agent.len() > prev.agent.len() {
-                            Some(g)
-                        } else {
-                            Some(prev)
-                        }
-                    }
+                    // prefer longer agent token (more specific)
+                    Some(prev) if prev.agent.len() >= g.agent.len() => Some(prev),
+                    _ => Some(g),
                 };
             }
         }
-        let group = best.unwrap_or(&self.groups[0]);
-        match most_specific_rule(group, path) {
+        best
+    }
+
+    /// Determine whether a path (including any query string) is allowed for the given
+    /// user-agent token.
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        let Some(group) = self.group_for(user_agent) else {
+            return true;
+        };
+        match most_specific_rule(group, &normalize_path(path)) {
             None => true, // default allow
-            Some(Rule::Allow(_)) => true,
-            Some(Rule::Disallow(_)) => false,
+            Some(Rule::Allow) => true,
+            Some(Rule::Disallow) => false,
         }
     }
 
+    /// Whether the rules for `user_agent` disallow the whole site (`Disallow: /` with no
+    /// Allow rule carving anything back out).
+    pub fn disallows_all(&self, user_agent: &str) -> bool {
+        self.group_for(user_agent).is_some_and(|g| {
+            g.disallow.iter().any(|p| p == "/" || p == "/*") && g.allow.iter().all(String::is_empty)
+        })
+    }
+
     /// Get crawl-delay directive for the given user-agent, if any.
     pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
-        let ua = user_agent.to_ascii_lowercase();
-        let mut best: Option<&AgentGroup> = None;
-        for g in &self.groups {
-            if g.agent == "*" || ua.contains(&g.agent) {
-                best = match best {
-                    None => Some(g),
-                    Some(prev) => {
-                        if g.agent.len() > prev.agent.len() {
-                            Some(g)
-                        } else {
-                            Some(prev)
-                        }
-                    }
-                };
-            }
-        }
-        best.and_then(|g| g.crawl_delay)
+        self.group_for(user_agent).and_then(|g| g.crawl_delay)
     }
 }
 
@@ -154,57 +147,122 @@ fn parse_crawl_delay(s: &str) -> Option<Duration> {
         return Some(Duration::from_secs(n));
     }
     if let Ok(f) = sv.parse::<f64>() {
-        return Some(Duration::from_secs_f64(f.max(0.0)));
+        if f.is_finite() {
+            return Some(Duration::from_secs_f64(f.max(0.0)));
+        }
     }
     None
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Rule {
-    Allow(String),
-    Disallow(String),
+    Allow,
+    Disallow,
 }
 
+/// RFC 9309 section 2.2.2: the matching rule with the longest pattern wins; on a tie
+/// Allow wins over Disallow. Empty patterns never match.
 fn most_specific_rule(group: &AgentGroup, path: &str) -> Option<Rule> {
-    let mut best: Option<Rule> = None;
-    let test = |pattern: &str, kind: fn(String) -> Rule, best: &mut Option<Rule>| {
-        if pattern.is_empty() {
-            return;
+    let longest = |patterns: &[String]| {
+        patterns
+            .iter()
+            .filter(|p| !p.is_empty() && pattern_matches(p, path))
+            .map(|p| p.len())
+            .max()
+    };
+    match (longest(&group.allow), longest(&group.disallow)) {
+        (None, None) => None,
+        (Some(_), None) => Some(Rule::Allow),
+        (None, Some(_)) => Some(Rule::Disallow),
+        (Some(a), Some(d)) => match a.cmp(&d) {
+            Ordering::Less => Some(Rule::Disallow),
+            Ordering::Equal | Ordering::Greater => Some(Rule::Allow),
+        },
+    }
+}
+
+/// Match a normalized pattern against a normalized path. `*` matches any sequence of
+/// characters and a trailing `$` anchors the pattern to the end of the path; otherwise
+/// patterns match as prefixes.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, rest) = parts.split_first().expect("split yields at least one part");
+    if rest.is_empty() {
+        return if anchored {
+            path == *first
+        } else {
+            path.starts_with(first)
+        };
+    }
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut pos = first.len();
+    let (last, middle) = rest.split_last().expect("rest is not empty");
+    // Greedy leftmost matching of the middle parts is sufficient because every part is
+    // separated by a wildcard.
+    for part in middle {
+        match path[pos..].find(part) {
+            Some(i) => pos += i + part.len(),
+            None => return false,
         }
-        // Basic prefix match. Standard allows wildcards; out of scope for v1.
-        if path.starts_with(pattern) {
-            match best {
-                None => {
-                    *best = Some(kind(pattern.to_string()));
-                }
-                Some(prev) => {
-                    let prev_len = match prev {
-                        Rule::Allow(s) | Rule::Disallow(s) => s.len(),
-                    };
-                    match pattern.len().cmp(&prev_len) {
-                        Ordering::Greater => *best = Some(kind(pattern.to_string())),
-                        Ordering::Equal => {
-                            // tie-breaker: Allow wins over Disallow
-                            if matches!(prev, Rule::Disallow(_))
-                                && matches!(kind(String::new()), Rule::Allow(_))
-                            {
-                                *best = Some(kind(pattern.to_string()));
-                            }
-                        }
-                        Ordering::Less => {}
-                    }
+    }
+    if anchored {
+        path.len() >= pos + last.len() && path.ends_with(last)
+    } else {
+        path[pos..].contains(last)
+    }
+}
+
+/// Normalize a rule pattern: percent-encoding is canonicalized and a `$` anywhere but
+/// the end is treated literally.
+fn normalize_pattern(pattern: &str) -> String {
+    match pattern.strip_suffix('$') {
+        Some(p) => format!("{}$", normalize_path(p).replace('$', "%24")),
+        None => normalize_path(pattern).replace('$', "%24"),
+    }
+}
+
+/// Canonicalize percent-encoding (RFC 9309 section 2.2.2): encoded unreserved ASCII
+/// octets are decoded, other escapes get uppercase hex, and non-ASCII octets are encoded.
+fn normalize_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex_val(bytes[i + 1]), hex_val(bytes[i + 2])) {
+                let decoded = hi * 16 + lo;
+                if is_unreserved(decoded) {
+                    out.push(decoded as char);
+                } else {
+                    out.push_str(&format!("%{:02X}", decoded));
                 }
+                i += 3;
+                continue;
             }
         }
-    };
-
-    for a in &group.allow {
-        test(a, Rule::Allow, &mut best);
-    }
-    for d in &group.disallow {
-        test(d, Rule::Disallow, &mut best);
+        if b.is_ascii() {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+        i += 1;
     }
-    best
+    out
+}
+
+fn hex_val(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
 }
 
 fn get_or_create_group_index(groups: &mut Vec<AgentGroup>, agent: &str) -> usize {
@@ -220,6 +278,33 @@ fn get_or_create_group_index(groups: &mut Vec<AgentGroup>, agent: &str) -> usize
     groups.len() - 1
 }
 
+impl RobotsTxt {
+    /// Fetch and parse robots.txt for a domain using the provided client.
+    /// Returns None if missing (non-2xx) or on network/protocol errors.
+    pub async fn fetch_for_domain(
+        client: &crate::crawler::client::GurtClient,
+        domain: &str,
+    ) -> Option<Self> {
+        let url = format!("gurt://{}/robots.txt", domain);
+        match client.fetch_with_retries(&url, 1).await {
+            Ok(resp) if (200..300).contains(&resp.code) => {
+                let body = String::from_utf8(resp.body).unwrap_or_default();
+                Some(Self::parse(&body))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Determine if a URL path is allowed for a given domain and user-agent.
+/// - If robots is None (missing/unfetchable), default allow per requirements.
+pub fn is_allowed_with_robots(robots: Option<&RobotsTxt>, user_agent: &str, path: &str) -> bool {
+    match robots {
+        Some(r) => r.is_allowed(user_agent, path),
+        None => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,6 +325,17 @@ Crawl-delay: 2.5\n\
         assert!(d.as_secs_f64() > 2.4 && d.as_secs_f64() < 2.6);
     }
 
+    #[test]
+    fn disallow_all_only_without_allow_rules() {
+        let r = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: gurt\nDisallow: /\nAllow: /public\n",
+        );
+        assert!(r.disallows_all("otherbot"));
+        assert!(!r.disallows_all("gurtbot"));
+        assert!(!RobotsTxt::parse("User-agent: *\nDisallow: /private\n").disallows_all("gurtbot"));
+        assert!(!RobotsTxt::parse("").disallows_all("gurtbot"));
+    }
+
     #[test]
     fn agent_specificity() {
         let txt = "\
@@ -253,31 +349,70 @@ Allow: /\n\
         assert!(!r.is_allowed("gurtbot", "/blocked/page"));
         assert!(r.is_allowed("otherbot", "/blocked/page"));
     }
-}
 
-impl RobotsTxt {
-    /// Fetch and parse robots.txt for a domain using the provided client.
-    /// Returns None if missing (non-2xx) or on network/protocol errors.
-    pub async fn fetch_for_domain(
-        client: &crate::crawler::client::GurtClient,
-        domain: &str,
-    ) -> Option<Self> {
-        let url = format!("gurt://{}/robots.txt", domain);
-        match client.fetch_with_retries(&url, 1).await {
-            Ok(resp) if (200..300).contains(&resp.code) => {
-                let body = String::from_utf8(resp.body).unwrap_or_default();
-                Some(Self::parse(&body))
-            }
-            _ => None,
-        }
+    #[test]
+    fn wildcards_and_end_anchor() {
+        let txt = "\
+User-agent: *\n\
+Disallow: /*?session=\n\
+Disallow: /*.lua\n\
+Allow: /*.lua$\n\
+Disallow: /tmp/*/cache\n\
+";
+        let r = RobotsTxt::parse(txt);
+        assert!(!r.is_allowed("gurtbot", "/page?session=abc"));
+        assert!(!r.is_allowed("gurtbot", "/a/b?session=abc&x=1"));
+        assert!(r.is_allowed("gurtbot", "/page?sess=abc"));
+        assert!(r.is_allowed("gurtbot", "/scripts/app.lua"));
+        assert!(!r.is_allowed("gurtbot", "/scripts/app.lua?v=2"));
+        assert!(!r.is_allowed("gurtbot", "/tmp/x/y/cache/1"));
+        assert!(r.is_allowed("gurtbot", "/tmp/cache"));
     }
-}
 
-/// Determine if a URL path is allowed for a given domain and user-agent.
-/// - If robots is None (missing/unfetchable), default allow per requirements.
-pub fn is_allowed_with_robots(robots: Option<&RobotsTxt>, user_agent: &str, path: &str) -> bool {
-    match robots {
-        Some(r) => r.is_allowed(user_agent, path),
-        None => true,
+    #[test]
+    fn longest_match_wins_and_ties_allow() {
+        let r = RobotsTxt::parse("User-agent: *\nDisallow: /docs\nAllow: /docs/*\nDisallow: /docs/private\nAllow: /page\nDisallow: /page\n");
+        assert!(!r.is_allowed("gurtbot", "/docs"));
+        assert!(r.is_allowed("gurtbot", "/docs/intro"));
+        assert!(!r.is_allowed("gurtbot", "/docs/private/x"));
+        assert!(r.is_allowed("gurtbot", "/page"));
+    }
+
+    #[test]
+    fn percent_encoding_is_normalized() {
+        let r = RobotsTxt::parse(
+            "User-agent: *\nDisallow: /%7Euser/\nDisallow: /caf\u{e9}\nDisallow: /a%2fb\n",
+        );
+        assert!(!r.is_allowed("gurtbot", "/~user/home"));
+        assert!(!r.is_allowed("gurtbot", "/caf%C3%A9/menu"));
+        assert!(!r.is_allowed("gurtbot", "/a%2Fb"));
+        // An encoded reserved character is not the same as the literal one.
+        assert!(r.is_allowed("gurtbot", "/a/b"));
+    }
+
+    #[test]
+    fn groups_and_sitemaps() {
+        let txt = "\
+Sitemap: gurt://example.real/maps/index.xml\n\
+User-agent: gurtbot\n\
+User-agent: otherbot\n\
+Disallow: /shared # both agents\n\
+\n\
+User-agent: *\n\
+Disallow: /\n\
+Sitemap: gurt://example.real/news.xml\n\
+";
+        let r = RobotsTxt::parse(txt);
+        assert!(!r.is_allowed("gurtbot", "/shared"));
+        assert!(!r.is_allowed("otherbot/2.0", "/shared"));
+        assert!(r.is_allowed("gurtbot", "/other"));
+        assert!(!r.is_allowed("unknownbot", "/other"));
+        assert_eq!(
+            r.sitemaps(),
+            [
+                "gurt://example.real/maps/index.xml".to_string(),
+                "gurt://example.real/news.xml".to_string()
+            ]
+        );
     }
 }
@@ -3,13 +3,20 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 
+/// Politeness state of a crawl-delay host: a single fetch slot and the start of the
+/// last fetch.
+struct PoliteGate {
+    slot: Arc<Semaphore>,
+    last: Mutex<Option<Instant>>,
+}
+
 #[derive(Clone)]
 pub struct HostScheduler {
     global: Arc<Semaphore>,
     per_host_limit: usize,
     hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
     // Politeness gate per host to honor crawl-delay when requested
-    polite: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Instant>>>>>>,
+    polite: Arc<Mutex<HashMap<String, Arc<PoliteGate>>>>,
 }
 
 impl HostScheduler {
@@ -44,39 +51,52 @@ impl HostScheduler {
         (g, h)
     }
 
-    async fn host_polite_gate(&self, host: &str) -> Arc<tokio::sync::Mutex<Option<Instant>>> {
+    async fn host_polite_gate(&self, host: &str) -> Arc<PoliteGate> {
         let mut map = self.polite.lock().await;
         if let Some(g) = map.get(host) {
             return g.clone();
         }
-        let g = Arc::new(tokio::sync::Mutex::new(None));
+        let g = Arc::new(PoliteGate {
+            slot: Arc::new(Semaphore::new(1)),
+            last: Mutex::new(None),
+        });
         map.insert(host.to_string(), g.clone());
         g
     }
 
     /// Acquire permits while honoring an optional crawl-delay for the host.
     /// If `crawl_delay` is None, behaves like `acquire` (fast as possible).
+    /// With a crawl-delay the host gets one fetch at a time, whatever the per-host
+    /// limit, and each one starts at least `crawl_delay` after the previous one.
     pub async fn acquire_polite(
         &self,
         host: &str,
         crawl_delay: Option<Duration>,
     ) -> (OwnedSemaphorePermit, OwnedSemaphorePermit) {
-        if let Some(delay) = crawl_delay {
-            let gate = self.host_polite_gate(host).await;
-            let mut last = gate.lock().await;
-            if let Some(prev) = *last {
-                let now = Instant::now();
-                let earliest = prev + delay;
-                if let Some(wait) = earliest.checked_duration_since(now) {
-                    if !wait.is_zero() {
-                        tokio::time::sleep(wait).await;
-                    }
+        let Some(delay) = crawl_delay else {
+            return self.acquire(host).await;
+        };
+        let gate = self.host_polite_gate(host).await;
+        let h = gate.slot.clone().acquire_owned().await.expect("semaphore");
+        let mut last = gate.last.lock().await;
+        if let Some(prev) = *last {
+            let now = Instant::now();
+            let earliest = prev + delay;
+            if let Some(wait) = earliest.checked_duration_since(now) {
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
                 }
             }
-            // record new timestamp to space subsequent calls
-            *last = Some(Instant::now());
-            drop(last);
         }
-        self.acquire(host).await
+        // record new timestamp to space subsequent calls
+        *last = Some(Instant::now());
+        drop(last);
+        let g = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore");
+        (g, h)
     }
 }
@@ -0,0 +1,1091 @@
+//! Sandboxed Lua runtime for page scripts (`<script type="text/lua">`).
+//!
+//! Implements the content-shaping part of the `typings/gurt.lua` API: `gurt`, `document`,
+//! `window`, `Element` (attributes, tree mutation, `innerHTML`/`textContent`,
+//! `classList`), `network.fetch`/`fetch`, `JSON`, `trace`, timers and the string/URL
+//! helpers. UI-only APIs (canvas, audio, tweens, websockets, clipboard, crumbs storage)
+//! are absent or inert; calling a missing one fails that script only.
+//!
+//! Scripts run without `io`, `os`, `package`, `debug` or chunk loading, under a memory
+//! cap and an instruction/time budget enforced by a VM hook. Event listeners are never
+//! triggered by input; `DOMContentLoaded`/`load` listeners and timers run once after all
+//! scripts, in delay order, without actually waiting.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use mlua::{
+    Function, HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Table, UserData, UserDataFields,
+    UserDataMethods, UserDataRef, Value, Variadic,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::client::ClientResponse;
+use super::dom::{Dom, NodeId};
+use super::render::ScriptFetchFn;
+
+/// Bytes of a script fetch response kept.
+const MAX_FETCH_BODY: usize = 512 * 1024;
+/// Timer and listener callbacks run after the scripts, at most.
+const MAX_CALLBACKS: usize = 256;
+/// Nesting limit for `JSON.stringify`/`JSON.parse`.
+const MAX_JSON_DEPTH: usize = 32;
+/// VM instructions between budget checks.
+const HOOK_INTERVAL: u32 = 1_000;
+const BUDGET_EXCEEDED: &str = "render budget exceeded";
+
+/// What `encodeURIComponent` leaves alone (as in JavaScript).
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+pub struct ScriptLimits {
+    pub deadline: Instant,
+    /// Total VM instructions across all scripts and callbacks.
+    pub instruction_limit: u64,
+    pub memory_limit: usize,
+    /// `network.fetch` calls (and external script loads) per page.
+    pub max_fetches: usize,
+}
+
+/// Outcome of running a page's scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptRun {
+    /// The resulting document without `<script>` elements.
+    pub html: String,
+    pub scripts_run: usize,
+    /// Scripts or callbacks that raised an error.
+    pub errors: usize,
+    /// The budget ran out; `html` reflects the partial run.
+    pub timed_out: bool,
+}
+
+/// Host side of `network.fetch`.
+pub struct ScriptNetwork {
+    pub fetcher: Option<Arc<ScriptFetchFn>>,
+    /// Runtime the fetcher's futures are driven on; scripts run on a blocking thread.
+    pub handle: tokio::runtime::Handle,
+    /// Hosts reachable besides the page's own.
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Run the Lua scripts of `html` (fetched from `page_url`) and serialize the result.
+/// Must be called from a thread that may block, e.g. inside `spawn_blocking`.
+pub fn run_page_scripts(
+    html: &str,
+    page_url: Option<&str>,
+    limits: &ScriptLimits,
+    network: ScriptNetwork,
+) -> ScriptRun {
+    let page = Rc::new(Page {
+        dom: RefCell::new(Dom::parse(html)),
+        page_url: page_url.and_then(|u| url::Url::parse(u).ok()),
+        listeners: RefCell::new(Vec::new()),
+        timers: RefCell::new(Vec::new()),
+        next_timer_id: Cell::new(1),
+        fetches_left: Cell::new(limits.max_fetches),
+        network,
+        deadline: limits.deadline,
+    });
+    let mut run = ScriptRun {
+        html: String::new(),
+        scripts_run: 0,
+        errors: 0,
+        timed_out: false,
+    };
+    let exceeded = Rc::new(Cell::new(false));
+    match new_sandbox(&page, limits, exceeded.clone()) {
+        Ok(lua) => execute(&lua, &page, &exceeded, &mut run),
+        Err(_) => run.errors += 1,
+    }
+    run.timed_out = exceeded.get();
+    run.html = page.dom.borrow().to_static_html();
+    run
+}
+
+fn execute(lua: &Lua, page: &Rc<Page>, exceeded: &Cell<bool>, run: &mut ScriptRun) {
+    let sources = page.script_sources();
+    for (i, source) in sources.iter().enumerate() {
+        if exceeded.get() {
+            return;
+        }
+        run.scripts_run += 1;
+        if lua
+            .load(source.as_str())
+            .set_name(format!("=script{}", i + 1))
+            .exec()
+            .is_err()
+        {
+            run.errors += 1;
+        }
+    }
+
+    let listeners = std::mem::take(&mut *page.listeners.borrow_mut());
+    let mut callbacks = 0;
+    for key in listeners {
+        if exceeded.get() || callbacks >= MAX_CALLBACKS {
+            return;
+        }
+        callbacks += 1;
+        if call_registered(lua, &key).is_err() {
+            run.errors += 1;
+        }
+    }
+    while let Some(timer) = page.next_timer() {
+        if exceeded.get() || callbacks >= MAX_CALLBACKS {
+            return;
+        }
+        callbacks += 1;
+        if call_registered(lua, &timer.callback).is_err() {
+            run.errors += 1;
+        }
+    }
+}
+
+fn call_registered(lua: &Lua, key: &RegistryKey) -> mlua::Result<()> {
+    let f: Function = lua.registry_value(key)?;
+    f.call::<_, ()>(Value::Nil)
+}
+
+fn new_sandbox(
+    page: &Rc<Page>,
+    limits: &ScriptLimits,
+    exceeded: Rc<Cell<bool>>,
+) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    lua.set_memory_limit(limits.memory_limit)?;
+    let deadline = limits.deadline;
+    let limit = limits.instruction_limit;
+    let executed = Cell::new(0u64);
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(HOOK_INTERVAL),
+        move |_, _| {
+            executed.set(executed.get() + u64::from(HOOK_INTERVAL));
+            if exceeded.get() || executed.get() > limit || Instant::now() >= deadline {
+                exceeded.set(true);
+                return Err(mlua::Error::runtime(BUDGET_EXCEEDED));
+            }
+            Ok(())
+        },
+    );
+    install_globals(&lua, page)?;
+    Ok(lua)
+}
+
+struct Timer {
+    id: i64,
+    delay_ms: i64,
+    callback: RegistryKey,
+}
+
+struct Page {
+    dom: RefCell<Dom>,
+    page_url: Option<url::Url>,
+    listeners: RefCell<Vec<RegistryKey>>,
+    timers: RefCell<Vec<Timer>>,
+    next_timer_id: Cell<i64>,
+    fetches_left: Cell<usize>,
+    network: ScriptNetwork,
+    deadline: Instant,
+}
+
+impl Page {
+    fn dom(&self) -> Ref<'_, Dom> {
+        self.dom.borrow()
+    }
+
+    fn dom_mut(&self) -> RefMut<'_, Dom> {
+        self.dom.borrow_mut()
+    }
+
+    /// Source of every Lua script in document order; `src` scripts are fetched.
+    fn script_sources(&self) -> Vec<String> {
+        let scripts: Vec<(Option<String>, String)> = {
+            let dom = self.dom();
+            dom.select_all(Dom::ROOT, "script")
+                .into_iter()
+                .filter_map(|n| {
+                    let kind = dom.attr(n, "type").unwrap_or("").to_ascii_lowercase();
+                    let src = dom.attr(n, "src").map(str::to_owned);
+                    let is_lua = kind.contains("lua")
+                        || (kind.is_empty() && src.as_deref().is_some_and(|s| s.ends_with(".lua")));
+                    is_lua.then(|| (src, dom.text_content(n)))
+                })
+                .collect()
+        };
+        scripts
+            .into_iter()
+            .filter_map(|(src, inline)| match src {
+                Some(src) => self
+                    .fetch(&src, "GET")
+                    .ok()
+                    .filter(|r| (200..300).contains(&r.code))
+                    .map(|r| String::from_utf8_lossy(&r.body).into_owned()),
+                None => Some(inline),
+            })
+            .collect()
+    }
+
+    fn element(self: &Rc<Self>, id: NodeId) -> Element {
+        Element {
+            page: self.clone(),
+            id,
+        }
+    }
+
+    fn add_timer(&self, callback: RegistryKey, delay_ms: i64) -> i64 {
+        let id = self.next_timer_id.get();
+        self.next_timer_id.set(id + 1);
+        self.timers.borrow_mut().push(Timer {
+            id,
+            delay_ms: delay_ms.max(0),
+            callback,
+        });
+        id
+    }
+
+    /// Timer with the smallest delay (then the oldest), removed from the queue.
+    fn next_timer(&self) -> Option<Timer> {
+        let mut timers = self.timers.borrow_mut();
+        let pos = timers
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, t)| (t.delay_ms, t.id))
+            .map(|(i, _)| i)?;
+        Some(timers.remove(pos))
+    }
+
+    /// Fetch `target` through the crawler, within the allowlist and the page budget.
+    fn fetch(&self, target: &str, method: &str) -> Result<ClientResponse, String> {
+        if !method.eq_ignore_ascii_case("GET") {
+            return Err(format!("method {method} not allowed"));
+        }
+        let url = match &self.page_url {
+            Some(base) => base.join(target),
+            None => url::Url::parse(target),
+        }
+        .map_err(|e| format!("invalid url: {e}"))?;
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        let own_host = self
+            .page_url
+            .as_ref()
+            .and_then(|u| u.host_str())
+            .is_some_and(|h| h.eq_ignore_ascii_case(&host));
+        if url.scheme() != "gurt" || !(own_host || self.network.allowed_hosts.contains(&host)) {
+            return Err(format!("{url} is not allowed"));
+        }
+        let left = self.fetches_left.get();
+        if left == 0 {
+            return Err("fetch limit reached".into());
+        }
+        self.fetches_left.set(left - 1);
+        let Some(fetcher) = self.network.fetcher.clone() else {
+            return Err("network unavailable".into());
+        };
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(BUDGET_EXCEEDED.into());
+        }
+        let fut = fetcher(url.to_string());
+        let mut resp = self
+            .network
+            .handle
+            .block_on(async { tokio::time::timeout(remaining, fut).await })
+            .map_err(|_| "fetch timeout".to_string())?
+            .map_err(|e| format!("{e:#}"))?;
+        resp.body.truncate(MAX_FETCH_BODY);
+        Ok(resp)
+    }
+}
+
+#[derive(Clone)]
+struct Element {
+    page: Rc<Page>,
+    id: NodeId,
+}
+
+impl Element {
+    fn related(&self, f: impl FnOnce(&Dom, NodeId) -> Option<NodeId>) -> Option<Element> {
+        let id = f(&self.page.dom(), self.id)?;
+        self.page
+            .dom()
+            .is_element(id)
+            .then(|| self.page.element(id))
+    }
+}
+
+impl UserData for Element {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("tagName", |_, e| {
+            Ok(e.page.dom().tag(e.id).map(str::to_ascii_uppercase))
+        });
+        fields.add_field_method_get("id", |_, e| {
+            Ok(e.page.dom().attr(e.id, "id").map(str::to_owned))
+        });
+        fields.add_field_method_get("innerHTML", |_, e| Ok(e.page.dom().inner_html(e.id)));
+        fields.add_field_method_set("innerHTML", |_, e, html: String| {
+            e.page.dom_mut().set_inner_html(e.id, &html);
+            Ok(())
+        });
+        for name in ["textContent", "text"] {
+            fields.add_field_method_get(name, |_, e| Ok(e.page.dom().text_content(e.id)));
+            fields.add_field_method_set(name, |_, e, text: String| {
+                e.page.dom_mut().set_text_content(e.id, &text);
+                Ok(())
+            });
+        }
+        fields.add_field_method_get("value", |_, e| {
+            Ok(e.page.dom().attr(e.id, "value").map(str::to_owned))
+        });
+        fields.add_field_method_set("value", |_, e, value: Value| {
+            let value = match value {
+                Value::Nil => None,
+                Value::String(s) => Some(s.to_string_lossy().into_owned()),
+                other => Some(lua_to_json(&other, 0).to_string()),
+            };
+            let mut dom = e.page.dom_mut();
+            match value {
+                Some(v) => dom.set_attr(e.id, "value", &v),
+                None => dom.remove_attr(e.id, "value"),
+            }
+            Ok(())
+        });
+        fields.add_field_method_get("visible", |_, e| {
+            Ok(e.page.dom().attr(e.id, "hidden").is_none())
+        });
+        fields.add_field_method_set("visible", |_, e, visible: bool| {
+            set_visible(e, visible);
+            Ok(())
+        });
+        fields.add_field_method_get("children", |_, e| {
+            let children = e.page.dom().element_children(e.id);
+            Ok(children
+                .into_iter()
+                .map(|c| e.page.element(c))
+                .collect::<Vec<_>>())
+        });
+        fields.add_field_method_get("parent", |_, e| Ok(e.related(|d, n| d.parent(n))));
+        fields.add_field_method_get("nextSibling", |_, e| {
+            Ok(e.related(|d, n| d.element_sibling(n, true)))
+        });
+        fields.add_field_method_get("previousSibling", |_, e| {
+            Ok(e.related(|d, n| d.element_sibling(n, false)))
+        });
+        fields.add_field_method_get("firstChild", |_, e| {
+            Ok(e.related(|d, n| d.element_children(n).first().copied()))
+        });
+        fields.add_field_method_get("lastChild", |_, e| {
+            Ok(e.related(|d, n| d.element_children(n).last().copied()))
+        });
+        fields.add_field_method_get("classList", |_, e| {
+            Ok(ClassList {
+                page: e.page.clone(),
+                id: e.id,
+            })
+        });
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("setAttribute", |_, e, (name, value): (String, String)| {
+            e.page.dom_mut().set_attr(e.id, &name, &value);
+            Ok(())
+        });
+        methods.add_method("getAttribute", |_, e, name: String| {
+            Ok(e.page.dom().attr(e.id, &name).map(str::to_owned))
+        });
+        methods.add_method("removeAttribute", |_, e, name: String| {
+            e.page.dom_mut().remove_attr(e.id, &name);
+            Ok(())
+        });
+        for name in ["appendChild", "append"] {
+            methods.add_method(name, |_, e, child: UserDataRef<Element>| {
+                e.page.dom_mut().append_child(e.id, child.id);
+                Ok(())
+            });
+        }
+        methods.add_method("remove", |_, e, ()| {
+            e.page.dom_mut().detach(e.id);
+            Ok(())
+        });
+        methods.add_method(
+            "insertBefore",
+            |_, e, (new, reference): (UserDataRef<Element>, UserDataRef<Element>)| {
+                insert_beside(e, &new, &reference, false);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "insertAfter",
+            |_, e, (new, reference): (UserDataRef<Element>, UserDataRef<Element>)| {
+                insert_beside(e, &new, &reference, true);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "replace",
+            |_, e, (old, new): (UserDataRef<Element>, UserDataRef<Element>)| {
+                let mut dom = e.page.dom_mut();
+                if dom.parent(old.id) == Some(e.id) {
+                    dom.replace(old.id, new.id);
+                }
+                Ok(())
+            },
+        );
+        methods.add_method("clone", |_, e, deep: Option<bool>| {
+            let id = e.page.dom_mut().clone_node(e.id, deep.unwrap_or(false));
+            Ok(e.page.element(id))
+        });
+        methods.add_method("show", |_, e, ()| {
+            set_visible(e, true);
+            Ok(())
+        });
+        methods.add_method("hide", |_, e, ()| {
+            set_visible(e, false);
+            Ok(())
+        });
+        methods.add_method("focus", |_, _, ()| Ok(()));
+        methods.add_method("unfocus", |_, _, ()| Ok(()));
+        // Input events never happen while rendering; handlers are accepted and dropped.
+        methods.add_method("on", |_, _, (_event, _handler): (String, Function)| {
+            Ok(Subscription)
+        });
+        methods.add_meta_method("__eq", |_, e, other: UserDataRef<Element>| {
+            Ok(Rc::ptr_eq(&e.page, &other.page) && e.id == other.id)
+        });
+        methods.add_meta_method("__tostring", |_, e, ()| {
+            Ok(format!(
+                "Element<{}>",
+                e.page.dom().tag(e.id).unwrap_or("#node")
+            ))
+        });
+    }
+}
+
+fn set_visible(e: &Element, visible: bool) {
+    let mut dom = e.page.dom_mut();
+    if visible {
+        dom.remove_attr(e.id, "hidden");
+    } else {
+        dom.set_attr(e.id, "hidden", "");
+    }
+}
+
+/// `parent:insertBefore(new, reference)`; ignored unless `reference` is a child of `parent`.
+fn insert_beside(parent: &Element, new: &Element, reference: &Element, after: bool) {
+    let mut dom = parent.page.dom_mut();
+    if dom.parent(reference.id) == Some(parent.id) {
+        dom.insert_beside(reference.id, new.id, after);
+    }
+}
+
+struct ClassList {
+    page: Rc<Page>,
+    id: NodeId,
+}
+
+impl ClassList {
+    fn classes(&self) -> Vec<String> {
+        self.page
+            .dom()
+            .attr(self.id, "class")
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn store(&self, classes: &[String]) {
+        let mut dom = self.page.dom_mut();
+        if classes.is_empty() {
+            dom.remove_attr(self.id, "class");
+        } else {
+            dom.set_attr(self.id, "class", &classes.join(" "));
+        }
+    }
+}
+
+impl UserData for ClassList {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("length", |_, c| Ok(c.classes().len()));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("add", |_, c, class: String| {
+            let mut classes = c.classes();
+            if !classes.contains(&class) {
+                classes.push(class);
+                c.store(&classes);
+            }
+            Ok(())
+        });
+        methods.add_method("remove", |_, c, class: String| {
+            let mut classes = c.classes();
+            classes.retain(|x| *x != class);
+            c.store(&classes);
+            Ok(())
+        });
+        methods.add_method("contains", |_, c, class: String| {
+            Ok(c.classes().contains(&class))
+        });
+        methods.add_method("toggle", |_, c, class: String| {
+            let mut classes = c.classes();
+            let had = classes.contains(&class);
+            if had {
+                classes.retain(|x| *x != class);
+            } else {
+                classes.push(class);
+            }
+            c.store(&classes);
+            Ok(!had)
+        });
+        // 1-based, like Lua sequences.
+        methods.add_method("item", |_, c, index: usize| {
+            Ok(index
+                .checked_sub(1)
+                .and_then(|i| c.classes().get(i).cloned()))
+        });
+    }
+}
+
+struct Subscription;
+
+impl UserData for Subscription {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("unsubscribe", |_, _, ()| Ok(()));
+    }
+}
+
+fn install_globals(lua: &Lua, page: &Rc<Page>) -> mlua::Result<()> {
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "load", "loadstring", "collectgarbage"] {
+        globals.raw_remove(name)?;
+    }
+    let noop = lua.create_function(|_, _: Variadic<Value>| Ok(()))?;
+    globals.set("print", noop.clone())?;
+    let trace = lua.create_table()?;
+    for name in ["log", "warn", "error"] {
+        trace.set(name, noop.clone())?;
+    }
+    globals.set("trace", trace)?;
+
+    // gurt
+    let gurt = lua.create_table()?;
+    let body = page.dom().body();
+    gurt.set("body", page.element(body))?;
+    let p = page.clone();
+    gurt.set(
+        "select",
+        lua.create_function(move |_, selector: String| {
+            let found = p.dom().select(Dom::ROOT, &selector);
+            Ok(found.map(|n| p.element(n)))
+        })?,
+    )?;
+    let p = page.clone();
+    gurt.set(
+        "selectAll",
+        lua.create_function(move |_, selector: String| {
+            let found = p.dom().select_all(Dom::ROOT, &selector);
+            Ok(found.into_iter().map(|n| p.element(n)).collect::<Vec<_>>())
+        })?,
+    )?;
+    let p = page.clone();
+    gurt.set(
+        "create",
+        lua.create_function(move |_, (tag, options): (String, Option<Table>)| {
+            let id = p.dom_mut().create_element(&tag);
+            if let Some(options) = options {
+                let mut dom = p.dom_mut();
+                if let Some(text) = options.get::<_, Option<String>>("text")? {
+                    dom.set_text_content(id, &text);
+                }
+                for attr in ["style", "id", "class"] {
+                    if let Some(v) = options.get::<_, Option<String>>(attr)? {
+                        dom.set_attr(id, attr, &v);
+                    }
+                }
+            }
+            Ok(p.element(id))
+        })?,
+    )?;
+    gurt.set("width", lua.create_function(|_, ()| Ok(1280))?)?;
+    gurt.set("height", lua.create_function(|_, ()| Ok(720))?)?;
+    gurt.set("location", location_table(lua, page.page_url.as_ref())?)?;
+    let crumbs = lua.create_table()?;
+    crumbs.set("set", noop.clone())?;
+    crumbs.set("get", lua.create_function(|_, _: Value| Ok(Value::Nil))?)?;
+    crumbs.set("delete", lua.create_function(|_, _: Value| Ok(false))?)?;
+    crumbs.set("getAll", lua.create_function(|lua, ()| lua.create_table())?)?;
+    gurt.set("crumbs", crumbs)?;
+    globals.set("gurt", gurt)?;
+
+    // document / window
+    let document = lua.create_table()?;
+    let p = page.clone();
+    document.set(
+        "createElement",
+        lua.create_function(move |_, tag: String| {
+            let id = p.dom_mut().create_element(&tag);
+            Ok(p.element(id))
+        })?,
+    )?;
+    let p = page.clone();
+    let add_listener = lua.create_function(move |lua, (event, handler): (String, Function)| {
+        if matches!(event.as_str(), "DOMContentLoaded" | "load") {
+            let key = lua.create_registry_value(handler)?;
+            p.listeners.borrow_mut().push(key);
+        }
+        Ok(Subscription)
+    })?;
+    document.set("addEventListener", add_listener.clone())?;
+    globals.set("document", document)?;
+    let window = lua.create_table()?;
+    window.set("addEventListener", add_listener)?;
+    globals.set("window", window)?;
+
+    // network
+    let p = page.clone();
+    let fetch = lua.create_function(move |lua, (url, options): (String, Option<Table>)| {
+        let method = match options {
+            Some(o) => o.get::<_, Option<String>>("method")?,
+            None => None,
+        };
+        response_table(lua, p.fetch(&url, method.as_deref().unwrap_or("GET")))
+    })?;
+    let network = lua.create_table()?;
+    network.set("fetch", fetch.clone())?;
+    globals.set("network", network)?;
+    globals.set("fetch", fetch)?;
+
+    // JSON
+    let json = lua.create_table()?;
+    json.set(
+        "stringify",
+        lua.create_function(|_, value: Value| Ok(lua_to_json(&value, 0).to_string()))?,
+    )?;
+    json.set(
+        "parse",
+        lua.create_function(|lua, text: String| {
+            match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(v) => Ok((json_to_lua(lua, &v, 0)?, Value::Nil)),
+                Err(e) => Ok((Value::Nil, Value::String(lua.create_string(e.to_string())?))),
+            }
+        })?,
+    )?;
+    globals.set("JSON", json)?;
+
+    // timers
+    let p = page.clone();
+    let set_timeout = lua.create_function(move |lua, (cb, ms): (Function, Option<i64>)| {
+        let key = lua.create_registry_value(cb)?;
+        Ok(p.add_timer(key, ms.unwrap_or(0)))
+    })?;
+    // Intervals fire once: there is no clock to repeat them against.
+    globals.set("setTimeout", set_timeout.clone())?;
+    globals.set("setInterval", set_timeout)?;
+    let p = page.clone();
+    let clear_timer = lua.create_function(move |_, id: i64| {
+        p.timers.borrow_mut().retain(|t| t.id != id);
+        Ok(())
+    })?;
+    globals.set("clearTimeout", clear_timer.clone())?;
+    globals.set("clearInterval", clear_timer)?;
+    let p = page.clone();
+    globals.set(
+        "onNextFrame",
+        lua.create_function(move |lua, cb: Function| {
+            let key = lua.create_registry_value(cb)?;
+            p.add_timer(key, 0);
+            Ok(())
+        })?,
+    )?;
+    let time = lua.create_table()?;
+    time.set(
+        "now",
+        lua.create_function(|_, ()| {
+            Ok(std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or(0.0))
+        })?,
+    )?;
+    time.set("sleep", noop)?;
+    globals.set("Time", time)?;
+
+    // URL helpers
+    let encode = lua.create_function(|_, s: Option<String>| {
+        Ok(utf8_percent_encode(s.as_deref().unwrap_or(""), URI_COMPONENT).to_string())
+    })?;
+    let decode = lua.create_function(|_, s: Option<String>| {
+        Ok(percent_decode_str(s.as_deref().unwrap_or(""))
+            .decode_utf8_lossy()
+            .into_owned())
+    })?;
+    globals.set("urlEncode", encode.clone())?;
+    globals.set("encodeURIComponent", encode)?;
+    globals.set("urlDecode", decode.clone())?;
+    globals.set("decodeURIComponent", decode)?;
+    let base = page.page_url.clone();
+    globals.set(
+        "getPathname",
+        lua.create_function(move |_, href: Option<String>| {
+            let parsed = match (&base, href.as_deref()) {
+                (Some(b), Some(h)) => b.join(h).ok(),
+                (None, Some(h)) => url::Url::parse(h).ok(),
+                (b, None) => b.clone(),
+            };
+            Ok(parsed.map(|u| u.path().to_string()).unwrap_or_default())
+        })?,
+    )?;
+
+    // string / table helpers
+    let string: Table = globals.get("string")?;
+    string.set(
+        "trim",
+        lua.create_function(|_, s: String| Ok(s.trim().to_string()))?,
+    )?;
+    string.set(
+        "replace",
+        lua.create_function(|_, (s, from, to): (String, String, String)| {
+            Ok(if from.is_empty() {
+                s
+            } else {
+                s.replacen(&from, &to, 1)
+            })
+        })?,
+    )?;
+    string.set(
+        "replaceAll",
+        lua.create_function(|_, (s, from, to): (String, String, String)| {
+            Ok(if from.is_empty() {
+                s
+            } else {
+                s.replace(&from, &to)
+            })
+        })?,
+    )?;
+    let table: Table = globals.get("table")?;
+    table.set(
+        "tostring",
+        lua.create_function(|_, t: Value| Ok(lua_to_json(&t, 0).to_string()))?,
+    )?;
+    Ok(())
+}
+
+fn location_table<'lua>(lua: &'lua Lua, page_url: Option<&url::Url>) -> mlua::Result<Table<'lua>> {
+    let location = lua.create_table()?;
+    location.set("href", page_url.map(|u| u.to_string()).unwrap_or_default())?;
+    let pairs: Rc<Vec<(String, String)>> = Rc::new(
+        page_url
+            .map(|u| u.query_pairs().into_owned().collect())
+            .unwrap_or_default(),
+    );
+    let query = lua.create_table()?;
+    let q = pairs.clone();
+    query.set(
+        "get",
+        lua.create_function(move |_, (_this, name): (Value, String)| {
+            Ok(q.iter().find(|(k, _)| *k == name).map(|(_, v)| v.clone()))
+        })?,
+    )?;
+    let q = pairs.clone();
+    query.set(
+        "has",
+        lua.create_function(move |_, (_this, name): (Value, String)| {
+            Ok(q.iter().any(|(k, _)| *k == name))
+        })?,
+    )?;
+    let q = pairs;
+    query.set(
+        "getAll",
+        lua.create_function(move |_, (_this, name): (Value, String)| {
+            Ok(q.iter()
+                .filter(|(k, _)| *k == name)
+                .map(|(_, v)| v.clone())
+                .collect::<Vec<_>>())
+        })?,
+    )?;
+    location.set("query", query)?;
+    // Navigation does not happen during a render.
+    let noop = lua.create_function(|_, _: Variadic<Value>| Ok(()))?;
+    location.set("goto", noop.clone())?;
+    location.set("reload", noop)?;
+    Ok(location)
+}
+
+/// Lua `Response` for a fetch; failures become status 0 with the reason in `statusText`.
+fn response_table<'lua>(
+    lua: &'lua Lua,
+    result: Result<ClientResponse, String>,
+) -> mlua::Result<Table<'lua>> {
+    let t = lua.create_table()?;
+    let (status, status_text, headers, body) = match result {
+        Ok(r) => (r.code, String::new(), r.headers, r.body),
+        Err(e) => (0, e, Vec::new(), Vec::new()),
+    };
+    t.set("status", status)?;
+    t.set("statusText", status_text)?;
+    let header_table = lua.create_table()?;
+    for (k, v) in headers {
+        header_table.set(k, v)?;
+    }
+    t.set("headers", header_table)?;
+    let text = Rc::new(String::from_utf8_lossy(&body).into_owned());
+    t.set(
+        "ok",
+        lua.create_function(move |_, _this: Value| Ok((200..300).contains(&status)))?,
+    )?;
+    let body_text = text.clone();
+    t.set(
+        "text",
+        lua.create_function(move |_, _this: Value| Ok(body_text.as_str().to_string()))?,
+    )?;
+    t.set(
+        "json",
+        lua.create_function(move |lua, _this: Value| {
+            match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(v) => json_to_lua(lua, &v, 0),
+                Err(_) => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+    Ok(t)
+}
+
+fn lua_to_json(value: &Value, depth: usize) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => Json::Bool(*b),
+        Value::Integer(i) => Json::from(*i),
+        Value::Number(n) => serde_json::Number::from_f64(*n)
+            .map(Json::Number)
+            .unwrap_or(Json::Null),
+        Value::String(s) => Json::String(s.to_string_lossy().into_owned()),
+        Value::Table(t) if depth < MAX_JSON_DEPTH => {
+            let entries: Vec<(Value, Value)> = t
+                .clone()
+                .pairs::<Value, Value>()
+                .filter_map(Result::ok)
+                .collect();
+            let len = t.raw_len();
+            if len > 0 && entries.len() == len {
+                return Json::Array(
+                    (1..=len)
+                        .map(|i| {
+                            let v: Value = t.raw_get(i).unwrap_or(Value::Nil);
+                            lua_to_json(&v, depth + 1)
+                        })
+                        .collect(),
+                );
+            }
+            let mut map = serde_json::Map::new();
+            for (k, v) in entries {
+                let key = match k {
+                    Value::String(s) => s.to_string_lossy().into_owned(),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Number(n) => n.to_string(),
+                    _ => continue,
+                };
+                map.insert(key, lua_to_json(&v, depth + 1));
+            }
+            Json::Object(map)
+        }
+        _ => Json::Null,
+    }
+}
+
+fn json_to_lua<'lua>(
+    lua: &'lua Lua,
+    value: &serde_json::Value,
+    depth: usize,
+) -> mlua::Result<Value<'lua>> {
+    use serde_json::Value as Json;
+    if depth >= MAX_JSON_DEPTH {
+        return Ok(Value::Nil);
+    }
+    Ok(match value {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::Boolean(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Number(n.as_f64().unwrap_or(0.0)),
+        },
+        Json::String(s) => Value::String(lua.create_string(s)?),
+        Json::Array(items) => {
+            let t = lua.create_table_with_capacity(items.len(), 0)?;
+            for (i, item) in items.iter().enumerate() {
+                t.raw_set(i + 1, json_to_lua(lua, item, depth + 1)?)?;
+            }
+            Value::Table(t)
+        }
+        Json::Object(map) => {
+            let t = lua.create_table_with_capacity(0, map.len())?;
+            for (k, v) in map {
+                t.raw_set(k.as_str(), json_to_lua(lua, v, depth + 1)?)?;
+            }
+            Value::Table(t)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits(ms: u64) -> ScriptLimits {
+        ScriptLimits {
+            deadline: Instant::now() + Duration::from_millis(ms),
+            instruction_limit: 1_000_000,
+            memory_limit: 8 * 1024 * 1024,
+            max_fetches: 2,
+        }
+    }
+
+    fn offline() -> ScriptNetwork {
+        ScriptNetwork {
+            fetcher: None,
+            handle: tokio::runtime::Handle::current(),
+            allowed_hosts: Vec::new(),
+        }
+    }
+
+    fn run(html: &str) -> ScriptRun {
+        run_page_scripts(
+            html,
+            Some("gurt://example.real/p?q=rust"),
+            &limits(2_000),
+            offline(),
+        )
+    }
+
+    #[tokio::test]
+    async fn builds_content_with_the_element_api() {
+        let out = run(r##"<body><ul id="list"></ul><p class="old">x</p>
+            <script type="text/lua">
+              local list = gurt.select("#list")
+              for i, name in ipairs({"alpha", "beta"}) do
+                local li = gurt.create("li", { text = name, id = "i" .. i })
+                list:appendChild(li)
+              end
+              list.firstChild.classList:add("first")
+              local p = gurt.select("p")
+              p.textContent = gurt.location.query:get("q") .. " & more"
+              p.classList:toggle("old")
+              local note = document.createElement("em")
+              note.innerHTML = "<b>bold</b>"
+              list:insertBefore(note, list.lastChild)
+              document.addEventListener("DOMContentLoaded", function()
+                gurt.body:append(gurt.create("footer", { text = "loaded" }))
+              end)
+              setTimeout(function() gurt.select("#i1").text = "ALPHA" end, 10)
+            </script></body>"##);
+        assert_eq!(out.errors, 0);
+        assert!(!out.timed_out);
+        assert_eq!(out.scripts_run, 1);
+        assert!(!out.html.contains("<script"));
+        assert!(out.html.contains(
+            r#"<ul id="list"><li id="i1" class="first">ALPHA</li><em><b>bold</b></em><li id="i2">beta</li></ul>"#
+        ));
+        assert!(out.html.contains("<p>rust &amp; more</p>"));
+        assert!(out.html.contains("<footer>loaded</footer>"));
+    }
+
+    #[tokio::test]
+    async fn sandbox_has_no_host_access() {
+        let out = run(r#"<script type="text/lua">
+              local ok = (io == nil) and (os == nil) and (load == nil) and (require == nil)
+                and (debug == nil) and (dofile == nil)
+              gurt.body:append(gurt.create("p", { text = tostring(ok) }))
+              os.exit(1)
+            </script>"#);
+        assert_eq!(out.errors, 1);
+        assert_eq!(out.html, "<p>true</p>");
+    }
+
+    #[tokio::test]
+    async fn json_and_helpers() {
+        let out = run(r#"<script type="text/lua">
+              local data, err = JSON.parse('{"n": 2, "xs": [1, "a"], "t": true}')
+              local out = { data.n, data.xs[2], err == nil, ("  pad "):trim(),
+                string.replaceAll("a-b-c", "-", "+"), encodeURIComponent("a b/c"),
+                getPathname("/x/y?z=1") }
+              gurt.body:append(gurt.create("pre", { text = JSON.stringify(out) }))
+            </script>"#);
+        assert_eq!(out.errors, 0);
+        assert_eq!(
+            out.html,
+            r#"<pre>[2,"a",true,"pad","a+b+c","a%20b%2Fc","/x/y"]</pre>"#
+        );
+    }
+
+    #[tokio::test]
+    async fn runaway_script_hits_the_budget() {
+        let mut l = limits(2_000);
+        l.instruction_limit = 50_000;
+        let out = run_page_scripts(
+            r#"<p>kept</p><script type="text/lua">while true do end</script>"#,
+            None,
+            &l,
+            offline(),
+        );
+        assert!(out.timed_out);
+        assert_eq!(out.html, "<p>kept</p>");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn fetch_is_limited_to_the_page_host() {
+        let fetched = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = fetched.clone();
+        let fetcher: Arc<ScriptFetchFn> = Arc::new(move |url: String| {
+            log.lock().unwrap().push(url);
+            Box::pin(async {
+                Ok(ClientResponse {
+                    code: 200,
+                    headers: vec![("content-type".into(), "application/json".into())],
+                    body: br#"{"title": "From API"}"#.to_vec(),
+                })
+            })
+        });
+        let network = ScriptNetwork {
+            fetcher: Some(fetcher),
+            handle: tokio::runtime::Handle::current(),
+            allowed_hosts: Vec::new(),
+        };
+        let html = r#"<h1></h1><script type="text/lua">
+              local r = network.fetch("/api/data")
+              gurt.select("h1").text = r:ok() and r:json().title or "failed"
+              local other = fetch("gurt://elsewhere.real/api")
+              local post = fetch("/api/data", { method = "POST" })
+              gurt.body:append(gurt.create("i", { text = other.status .. "," .. post.status }))
+            </script>"#;
+        let out = tokio::task::spawn_blocking(move || {
+            run_page_scripts(
+                html,
+                Some("gurt://example.real/page"),
+                &limits(2_000),
+                network,
+            )
+        })
+        .await
+        .unwrap();
+        assert_eq!(out.errors, 0);
+        assert_eq!(out.html, "<h1>From API</h1><i>0,0</i>");
+        assert_eq!(
+            *fetched.lock().unwrap(),
+            vec!["gurt://example.real/api/data"]
+        );
+    }
+}
@@ -1,41 +1,366 @@
-/// Extract URLs inside <loc>...</loc> tags. Whitespace is trimmed.
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::io::Read;
+
+use super::client::{ClientResponse, GurtClient};
+use super::html::decode_reference;
+
+/// Sitemaps fetched per domain crawl, including the default `/sitemap.xml` and those
+/// listed by indexes.
+pub const MAX_SITEMAPS: usize = 8;
+/// Largest sitemap accepted after decompression (the sitemaps.org limit).
+pub const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+/// How deep sitemap indexes are followed: 1 reads the sitemaps listed by a root index,
+/// 2 also those of an index nested in it.
+pub const MAX_SITEMAP_DEPTH: usize = 2;
+
+/// Extract URLs inside <loc>...</loc> tags, entity-decoded. Whitespace is trimmed.
 pub fn parse_sitemap_xml(xml: &str) -> Vec<String> {
+    parse_sitemap(xml)
+        .entries
+        .into_iter()
+        .map(|e| e.loc)
+        .collect()
+}
+
+/// `<changefreq>` of a sitemap entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFreq {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "hourly" => Some(Self::Hourly),
+            "daily" => Some(Self::Daily),
+            "weekly" => Some(Self::Weekly),
+            "monthly" => Some(Self::Monthly),
+            "yearly" => Some(Self::Yearly),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Recrawl hints of a sitemap entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SitemapHint {
+    /// `<lastmod>`, unix seconds.
+    pub lastmod: Option<i64>,
+    pub changefreq: Option<ChangeFreq>,
+    /// `<priority>`, 0.0 to 1.0.
+    pub priority: Option<f32>,
+}
+
+impl SitemapHint {
+    /// `<priority>` on the 0-10 scale of `urls.fetch_priority`; 0 without one.
+    pub fn fetch_priority(&self) -> i32 {
+        self.priority.map_or(0, |p| (p * 10.0).round() as i32)
+    }
+}
+
+/// One `<url>` (or `<sitemap>`) entry of a sitemap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub hint: SitemapHint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SitemapKind {
+    /// `<urlset>`: entries are pages.
+    UrlSet,
+    /// `<sitemapindex>`: entries are further sitemaps.
+    Index,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sitemap {
+    pub kind: SitemapKind,
+    pub entries: Vec<SitemapEntry>,
+}
+
+/// Parse a sitemap or sitemap index into its entries with their `<lastmod>`,
+/// `<changefreq>` and `<priority>`. Text is entity-decoded; unparseable hints are
+/// dropped.
+pub fn parse_sitemap(xml: &str) -> Sitemap {
+    let kind = if xml.contains("<sitemapindex") {
+        SitemapKind::Index
+    } else {
+        SitemapKind::UrlSet
+    };
     let mut out = Vec::new();
-    let mut rest = xml;
-    while let Some(start) = rest.find("<loc") {
-        let after_start = &rest[start..];
-        // find closing '>' of <loc ...>
-        if let Some(gt) = after_start.find('>') {
-            let after_tag = &after_start[gt + 1..];
-            if let Some(end) = after_tag.find("</loc>") {
-                let url = &after_tag[..end];
-                let u = url.trim().to_string();
-                if !u.is_empty() {
-                    out.push(u);
-                }
-                rest = &after_tag[end + 6..]; // move past </loc>
-                continue;
+    // End of the previous entry.
+    let mut pos = 0;
+    while let Some(rel) = xml[pos..].find("<loc") {
+        let loc_at = pos + rel;
+        // An entry spans its <url>/<sitemap> element; without one, just its <loc> up to
+        // the next <loc>.
+        let open = ["<url>", "<sitemap>"]
+            .iter()
+            .filter_map(|t| xml[pos..loc_at].rfind(t))
+            .max()
+            .map_or(loc_at, |i| pos + i);
+        let after = loc_at + 4;
+        let next_loc = xml[after..].find("<loc").map_or(xml.len(), |i| after + i);
+        let close = ["</url>", "</sitemap>"]
+            .iter()
+            .filter_map(|t| xml[after..next_loc].find(t))
+            .min()
+            .map_or(next_loc, |i| after + i);
+        let block = &xml[open..close];
+        let text = |tag| tag_text(block, tag).map(decode_xml_text);
+        if let Some(loc) = text("loc").filter(|l| !l.is_empty()) {
+            out.push(SitemapEntry {
+                loc,
+                hint: SitemapHint {
+                    lastmod: text("lastmod").as_deref().and_then(parse_w3c_datetime),
+                    changefreq: text("changefreq").as_deref().and_then(ChangeFreq::parse),
+                    priority: text("priority")
+                        .and_then(|p| p.parse::<f32>().ok())
+                        .filter(|p| (0.0..=1.0).contains(p)),
+                },
+            });
+        }
+        pos = close;
+    }
+    Sitemap { kind, entries: out }
+}
+
+/// Trimmed text of the first `<tag>...</tag>` in `xml`.
+fn tag_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = xml.find(&format!("<{tag}"))?;
+    let after_open = &xml[open..];
+    let gt = after_open.find('>')?;
+    let inner = &after_open[gt + 1..];
+    let end = inner.find(&format!("</{tag}>"))?;
+    Some(inner[..end].trim())
+}
+
+/// Resolve CDATA sections and the XML entities (predefined and numeric) in element text.
+fn decode_xml_text(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(pos) = rest.find(['&', '<']) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").unwrap_or(after.len());
+            out.push_str(&after[..end]);
+            rest = after.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_reference(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
             }
         }
-        // no proper <loc>...</loc> found; stop to avoid infinite loop
-        break;
     }
-    out
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
+/// Sitemap text from a response body, gunzipping `.xml.gz` sitemaps (detected by the
+/// gzip magic bytes). None for bodies that are not UTF-8, fail to decompress or exceed
+/// [`MAX_SITEMAP_BYTES`].
+pub fn decode_sitemap_body(body: &[u8]) -> Option<String> {
+    let bytes = if body.starts_with(&[0x1f, 0x8b]) {
+        let mut out = Vec::new();
+        flate2::read::GzDecoder::new(body)
+            .take(MAX_SITEMAP_BYTES as u64 + 1)
+            .read_to_end(&mut out)
+            .ok()?;
+        out
+    } else {
+        body.to_vec()
+    };
+    if bytes.len() > MAX_SITEMAP_BYTES {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Sitemaps to fetch for one domain, breadth first through nested indexes. Limits the
+/// total number of sitemaps and how deep indexes are followed, and skips repeats.
+#[derive(Debug)]
+pub struct SitemapWalk {
+    pending: VecDeque<(String, usize)>,
+    seen: HashSet<String>,
+    max_sitemaps: usize,
+    max_depth: usize,
+}
+
+impl SitemapWalk {
+    pub fn new(max_sitemaps: usize, max_depth: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            seen: HashSet::new(),
+            max_sitemaps,
+            max_depth,
+        }
+    }
+
+    /// Queue a sitemap found at `depth` (0 for the domain's own sitemaps, `d + 1` for one
+    /// listed by an index at depth `d`). Returns false when it is skipped.
+    pub fn push(&mut self, url: String, depth: usize) -> bool {
+        if depth > self.max_depth || self.seen.len() >= self.max_sitemaps {
+            return false;
+        }
+        if !self.seen.insert(url.clone()) {
+            return false;
+        }
+        self.pending.push_back((url, depth));
+        true
+    }
+
+    /// Next sitemap URL with its depth.
+    pub fn pop(&mut self) -> Option<(String, usize)> {
+        self.pending.pop_front()
+    }
+}
+
+/// Parse a W3C datetime (`YYYY-MM-DD`, optionally `Thh:mm[:ss[.f]]` with `Z` or
+/// `±hh:mm`) into unix seconds. A time without a zone is taken as UTC.
+pub fn parse_w3c_datetime(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (date, time) = match s.split_once('T') {
+        Some((d, t)) => (d, Some(t)),
+        None => (s, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next().map_or(Some(1), |m| m.parse().ok())?;
+    let day: u32 = parts.next().map_or(Some(1), |d| d.parse().ok())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut secs = days_from_civil(year, month, day) * 86_400;
+    if let Some(time) = time {
+        let (clock, offset) = if let Some(t) = time.strip_suffix('Z') {
+            (t, 0)
+        } else if let Some(pos) = time.rfind(['+', '-']) {
+            let (h, m) = time[pos + 1..].split_once(':')?;
+            let offset = h.parse::<i64>().ok()? * 3_600 + m.parse::<i64>().ok()? * 60;
+            let sign = if time[pos..].starts_with('-') { -1 } else { 1 };
+            (&time[..pos], sign * offset)
+        } else {
+            (time, 0)
+        };
+        let mut fields = clock.split(':');
+        let h: i64 = fields.next()?.parse().ok()?;
+        let m: i64 = fields.next()?.parse().ok()?;
+        let sec: f64 = fields.next().map_or(Some(0.0), |s| s.parse().ok())?;
+        if h > 23 || m > 59 || !(0.0..61.0).contains(&sec) {
+            return None;
+        }
+        secs += h * 3_600 + m * 60 + sec as i64 - offset;
+    }
+    Some(secs)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Fetch gurt://<domain>/sitemap.xml and return its page URLs, following sitemap
+/// indexes on the same domain within [`MAX_SITEMAPS`] and [`MAX_SITEMAP_DEPTH`].
+pub async fn fetch_sitemap_urls(client: &GurtClient, domain: &str) -> Vec<String> {
+    let fetch = |url: String| async move { client.fetch_with_retries(&url, 1).await.ok() };
+    fetch_sitemap_entries(fetch, domain, &[], usize::MAX)
+        .await
+        .into_iter()
+        .map(|e| e.loc)
+        .collect()
 }
 
-/// Fetch sitemap.xml from gurt://<domain>/sitemap.xml and parse URLs.
-pub async fn fetch_sitemap_urls(
-    client: &crate::crawler::client::GurtClient,
+/// Page entries of the sitemaps of `domain`, at most `max_urls`. `/sitemap.xml` is
+/// always tried, then the sitemaps of `announced` (e.g. from robots.txt), then those
+/// their indexes list on the same host, within [`MAX_SITEMAPS`] and
+/// [`MAX_SITEMAP_DEPTH`]. Locations are resolved against the domain and canonicalized;
+/// unusable ones are dropped. Every sitemap is requested through `fetch`, so a crawler
+/// can put it under the domain's robots.txt and crawl-delay; None skips the sitemap.
+pub async fn fetch_sitemap_entries<F, Fut>(
+    fetch: F,
     domain: &str,
-) -> Vec<String> {
-    let url = format!("gurt://{}/sitemap.xml", domain);
-    match client.fetch_with_retries(&url, 1).await {
-        Ok(resp) if (200..300).contains(&resp.code) => {
-            let body = String::from_utf8(resp.body).unwrap_or_default();
-            parse_sitemap_xml(&body)
+    announced: &[String],
+    max_urls: usize,
+) -> Vec<SitemapEntry>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Option<ClientResponse>>,
+{
+    let Ok(base) = url::Url::parse(&format!("gurt://{}/", domain)) else {
+        return Vec::new();
+    };
+    let mut walk = SitemapWalk::new(MAX_SITEMAPS, MAX_SITEMAP_DEPTH);
+    walk.push(format!("gurt://{}/sitemap.xml", domain), 0);
+    for raw in announced {
+        if let Some(url) = crate::link::resolve_url(&base, raw) {
+            walk.push(url, 0);
+        }
+    }
+    let mut out = Vec::new();
+    while let Some((url, depth)) = walk.pop() {
+        if out.len() >= max_urls {
+            break;
+        }
+        let body = match fetch(url.clone()).await {
+            Some(resp) if (200..300).contains(&resp.code) => resp.body,
+            _ => continue,
+        };
+        let Some(sitemap) = decode_sitemap_body(&body).map(|x| parse_sitemap(&x)) else {
+            eprintln!("[indexing] unreadable sitemap url={}", url);
+            continue;
+        };
+        for entry in sitemap.entries {
+            let Some(loc) = crate::link::resolve_url(&base, &entry.loc) else {
+                continue;
+            };
+            match sitemap.kind {
+                SitemapKind::Index => {
+                    // An index may only list sitemaps of its own host.
+                    let host = url::Url::parse(&loc)
+                        .ok()
+                        .and_then(|u| u.host_str().map(str::to_owned));
+                    if host.is_some_and(|h| h.eq_ignore_ascii_case(domain)) {
+                        walk.push(loc, depth + 1);
+                    }
+                }
+                SitemapKind::UrlSet if out.len() < max_urls => {
+                    out.push(SitemapEntry {
+                        loc,
+                        hint: entry.hint,
+                    });
+                }
+                SitemapKind::UrlSet => break,
+            }
         }
-        _ => Vec::new(),
     }
+    out
 }
 
 /// Reorder candidate URLs by prioritizing those present in the sitemap list.
@@ -87,6 +412,108 @@ mod tests {
         assert_eq!(urls[2], "gurt://example.real/blog/1");
     }
 
+    #[test]
+    fn parse_entries_with_hints() {
+        let xml = r#"<urlset>
+  <url><loc>gurt://example.real/</loc><changefreq>Daily</changefreq></url>
+  <url>
+    <lastmod>2024-03-01</lastmod>
+    <loc>gurt://example.real/about</loc>
+  </url>
+  <url><loc>gurt://example.real/x</loc><lastmod>soon</lastmod><changefreq>often</changefreq></url>
+</urlset>"#;
+        let sitemap = parse_sitemap(xml);
+        assert_eq!(sitemap.kind, SitemapKind::UrlSet);
+        let entries = sitemap.entries;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].loc, "gurt://example.real/");
+        assert_eq!(entries[0].hint.changefreq, Some(ChangeFreq::Daily));
+        assert_eq!(entries[0].hint.lastmod, None);
+        assert_eq!(entries[1].loc, "gurt://example.real/about");
+        assert_eq!(entries[1].hint.lastmod, Some(1_709_251_200));
+        assert_eq!(entries[1].hint.changefreq, None);
+        assert_eq!(entries[2].hint, SitemapHint::default());
+    }
+
+    #[test]
+    fn parse_index_with_entities_and_priority() {
+        let xml = r#"<?xml version="1.0"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>gurt://example.real/s.xml?a=1&amp;b=2</loc></sitemap>
+  <sitemap><loc><![CDATA[gurt://example.real/s2.xml.gz]]></loc></sitemap>
+</sitemapindex>"#;
+        let index = parse_sitemap(xml);
+        assert_eq!(index.kind, SitemapKind::Index);
+        assert_eq!(
+            parse_sitemap_xml(xml),
+            vec![
+                "gurt://example.real/s.xml?a=1&b=2",
+                "gurt://example.real/s2.xml.gz"
+            ]
+        );
+
+        let xml = "<urlset><url><loc>gurt://example.real/caf&#xE9;&#33;</loc>\
+                   <priority>0.8</priority></url>\
+                   <url><loc>gurt://example.real/&bogus;</loc><priority>7</priority></url></urlset>";
+        let entries = parse_sitemap(xml).entries;
+        assert_eq!(entries[0].loc, "gurt://example.real/caf\u{e9}!");
+        assert_eq!(entries[0].hint.priority, Some(0.8));
+        assert_eq!(entries[0].hint.fetch_priority(), 8);
+        assert_eq!(entries[1].loc, "gurt://example.real/&bogus;");
+        assert_eq!(entries[1].hint.priority, None);
+        assert_eq!(entries[1].hint.fetch_priority(), 0);
+    }
+
+    #[test]
+    fn gzip_sitemaps_are_decompressed() {
+        use std::io::Write;
+        let xml = "<urlset><url><loc>gurt://example.real/</loc></url></urlset>";
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(xml.as_bytes()).unwrap();
+        let body = gz.finish().unwrap();
+        assert_eq!(decode_sitemap_body(&body).as_deref(), Some(xml));
+        assert_eq!(decode_sitemap_body(xml.as_bytes()).as_deref(), Some(xml));
+        assert_eq!(decode_sitemap_body(&body[..body.len() / 2]), None);
+        assert_eq!(decode_sitemap_body(&[0xff, 0xfe]), None);
+    }
+
+    #[test]
+    fn walk_caps_depth_and_count() {
+        let mut walk = SitemapWalk::new(3, 1);
+        assert!(walk.push("gurt://a.real/sitemap.xml".into(), 0));
+        assert!(!walk.push("gurt://a.real/sitemap.xml".into(), 0), "repeat");
+        assert!(
+            !walk.push("gurt://a.real/deep.xml".into(), 2),
+            "past max depth"
+        );
+        assert!(walk.push("gurt://a.real/1.xml".into(), 1));
+        assert!(walk.push("gurt://a.real/2.xml".into(), 1));
+        assert!(!walk.push("gurt://a.real/3.xml".into(), 1), "count cap");
+        assert_eq!(walk.pop(), Some(("gurt://a.real/sitemap.xml".into(), 0)));
+        assert_eq!(walk.pop(), Some(("gurt://a.real/1.xml".into(), 1)));
+        assert_eq!(walk.pop(), Some(("gurt://a.real/2.xml".into(), 1)));
+        assert_eq!(walk.pop(), None);
+    }
+
+    #[test]
+    fn w3c_datetimes() {
+        assert_eq!(parse_w3c_datetime("1970-01-01"), Some(0));
+        assert_eq!(
+            parse_w3c_datetime("2015-10-21T07:28:00Z"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(
+            parse_w3c_datetime("2015-10-21T09:28:00.5+02:00"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(
+            parse_w3c_datetime("2015-10-21T02:28-05:00"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(parse_w3c_datetime("2015-13-01"), None);
+        assert_eq!(parse_w3c_datetime("yesterday"), None);
+    }
+
     #[test]
     fn prioritize_urls_with_sitemap() {
         let cand = vec![
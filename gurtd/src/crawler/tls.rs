@@ -0,0 +1,434 @@
+//! TLS upgrades for [`super::client::GurtClient`]: how a server's certificate is trusted.
+//!
+//! - verify: the chain must lead to one of the root CAs loaded from PEM and name the host.
+//! - tofu (trust on first use): the first leaf certificate seen for a host is pinned by
+//!   its SHA-256, in Postgres when services are up; a different one later is refused or,
+//!   if configured, only flagged. The handshake signature is still checked against the
+//!   presented certificate, so a pinned certificate cannot be replayed without its key.
+//! - insecure: any certificate. Only when asked for explicitly.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+
+use super::client::{ClientError, DynStream, UpgradeFn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsMode {
+    Verify,
+    Tofu,
+    Insecure,
+}
+
+/// What to do when a host presents a certificate other than its pinned one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinMismatch {
+    /// Fail the connection with [`ClientError::Certificate`].
+    Refuse,
+    /// Log and record the mismatch, then carry on.
+    Flag,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsSettings {
+    pub mode: TlsMode,
+    /// PEM bundle of root CAs for [`TlsMode::Verify`].
+    pub ca_file: Option<String>,
+    pub on_mismatch: PinMismatch,
+}
+
+impl TlsSettings {
+    /// Settings from the environment:
+    /// - GURT_TLS_MODE: verify | tofu | insecure (default verify; unknown values verify)
+    /// - GURT_TLS_CA_FILE: root CAs, required in verify mode (see [`check`](Self::check))
+    /// - GURT_TLS_PIN_MISMATCH: refuse | flag (default refuse)
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("GURT_TLS_MODE")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "tofu" => TlsMode::Tofu,
+            "insecure" => TlsMode::Insecure,
+            _ => TlsMode::Verify,
+        };
+        let ca_file = std::env::var("GURT_TLS_CA_FILE")
+            .ok()
+            .filter(|p| !p.trim().is_empty());
+        let on_mismatch = match std::env::var("GURT_TLS_PIN_MISMATCH") {
+            Ok(v) if v.trim().eq_ignore_ascii_case("flag") => PinMismatch::Flag,
+            _ => PinMismatch::Refuse,
+        };
+        Self {
+            mode,
+            ca_file,
+            on_mismatch,
+        }
+    }
+
+    /// Refuse settings under which no server can be trusted: verify mode without a
+    /// loadable CA file. Checked at startup, so the crawler does not run with every
+    /// fetch failing on its certificate.
+    pub fn check(&self) -> Result<()> {
+        if self.mode != TlsMode::Verify {
+            return Ok(());
+        }
+        let path = self.ca_file.as_deref().ok_or_else(|| {
+            anyhow!("GURT_TLS_MODE=verify needs GURT_TLS_CA_FILE (or set GURT_TLS_MODE=tofu)")
+        })?;
+        load_roots(path).map(|_| ())
+    }
+
+    /// Upgrade for these settings. A CA file that cannot be loaded is logged and leaves
+    /// verify mode without roots: connections fail rather than fall back to trusting
+    /// everything.
+    pub fn upgrade(&self) -> Arc<UpgradeFn> {
+        match self.mode {
+            TlsMode::Verify => {
+                let roots = match self.ca_file.as_deref().map(load_roots) {
+                    Some(Ok(roots)) => roots,
+                    Some(Err(err)) => {
+                        eprintln!("[tls] GURT_TLS_CA_FILE not loaded: {err:#}");
+                        RootCertStore::empty()
+                    }
+                    None => {
+                        eprintln!("[tls] GURT_TLS_CA_FILE not set; no certificate will verify");
+                        RootCertStore::empty()
+                    }
+                };
+                verifying_tls_upgrade(roots)
+            }
+            TlsMode::Tofu => tofu_tls_upgrade(Arc::new(PinStore::persistent(self.on_mismatch))),
+            TlsMode::Insecure => {
+                eprintln!("[tls] GURT_TLS_MODE=insecure: server certificates are not checked");
+                insecure_tls_upgrade()
+            }
+        }
+    }
+}
+
+static FROM_ENV: Lazy<Arc<UpgradeFn>> = Lazy::new(|| TlsSettings::from_env().upgrade());
+
+/// Upgrade configured by [`TlsSettings::from_env`], shared by the process's clients so
+/// they share one pin store.
+pub fn upgrade_from_env() -> Arc<UpgradeFn> {
+    FROM_ENV.clone()
+}
+
+/// Root CAs from a PEM file holding one or more certificates.
+pub fn load_roots(path: &str) -> Result<RootCertStore> {
+    let f = File::open(path).with_context(|| format!("opening CA file '{path}'"))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(f))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow!("invalid pem in {path}"))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates found in {path}"));
+    }
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots
+            .add(cert)
+            .map_err(|e| anyhow!("invalid CA certificate in {path}: {e}"))?;
+    }
+    Ok(roots)
+}
+
+/// Upgrade that requires a chain to one of `roots` valid for the host.
+pub fn verifying_tls_upgrade(roots: RootCertStore) -> Arc<UpgradeFn> {
+    let cfg = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    upgrade_with(cfg, None)
+}
+
+/// Upgrade that pins each host's leaf certificate in `pins` on first use.
+pub fn tofu_tls_upgrade(pins: Arc<PinStore>) -> Arc<UpgradeFn> {
+    let builder = rustls::ClientConfig::builder();
+    let verifier = SignatureOnlyVerifier(builder.crypto_provider().clone());
+    let cfg = builder
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    upgrade_with(cfg, Some(pins))
+}
+
+/// Upgrade that accepts any certificate. Do not use in production.
+pub fn insecure_tls_upgrade() -> Arc<UpgradeFn> {
+    let cfg = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerifier))
+        .with_no_client_auth();
+    upgrade_with(cfg, None)
+}
+
+fn upgrade_with(mut cfg: rustls::ClientConfig, pins: Option<Arc<PinStore>>) -> Arc<UpgradeFn> {
+    cfg.alpn_protocols = vec![b"GURT/1.0".to_vec()];
+    let tls = tokio_rustls::TlsConnector::from(Arc::new(cfg));
+    Arc::new(move |host: &str, stream: DynStream| {
+        let tls = tls.clone();
+        let pins = pins.clone();
+        let host = host.to_string();
+        Box::pin(async move {
+            let server_name = server_name_from_host(&host).ok_or(ClientError::InvalidMessage)?;
+            let stream = tls
+                .connect(server_name, stream)
+                .await
+                .map_err(|err| handshake_error(&err))?;
+            if let Some(pins) = pins {
+                let leaf = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|certs| certs.first())
+                    .ok_or(ClientError::Certificate)?;
+                pins.check(&host, &leaf_sha256(leaf)).await?;
+            }
+            Ok(Box::pin(stream) as DynStream)
+        })
+    })
+}
+
+fn handshake_error(err: &std::io::Error) -> ClientError {
+    match err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<rustls::Error>())
+    {
+        Some(rustls::Error::InvalidCertificate(_))
+        | Some(rustls::Error::NoCertificatesPresented) => ClientError::Certificate,
+        _ => ClientError::Connection,
+    }
+}
+
+pub fn server_name_from_host(host: &str) -> Option<ServerName<'static>> {
+    match host.parse::<std::net::IpAddr>() {
+        Ok(ip) => Some(ServerName::IpAddress(ip.into())),
+        Err(_) => ServerName::try_from(host.to_owned()).ok(),
+    }
+}
+
+/// Lowercase hex SHA-256 of a DER certificate.
+pub fn leaf_sha256(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Leaf fingerprints pinned per host. Lookups are served from memory; a persistent store
+/// also pins in Postgres, so pins survive restarts and are shared between workers.
+pub struct PinStore {
+    pins: Mutex<HashMap<String, String>>,
+    persist: bool,
+    on_mismatch: PinMismatch,
+}
+
+impl PinStore {
+    /// Pins kept in memory only (tests, tools).
+    pub fn new(on_mismatch: PinMismatch) -> Self {
+        Self {
+            pins: Mutex::new(HashMap::new()),
+            persist: false,
+            on_mismatch,
+        }
+    }
+
+    /// Pins also stored in the cert_pins table once services are initialized.
+    pub fn persistent(on_mismatch: PinMismatch) -> Self {
+        Self {
+            persist: true,
+            ..Self::new(on_mismatch)
+        }
+    }
+
+    /// Pin `sha256` for `host` on first use; otherwise compare it with the pin.
+    pub async fn check(&self, host: &str, sha256: &str) -> Result<(), ClientError> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let cached = self.pins.lock().unwrap().get(&host).cloned();
+        let pinned = match cached {
+            Some(pinned) => pinned,
+            None => {
+                let stored = self.pin_in_db(&host, sha256).await;
+                let mut pins = self.pins.lock().unwrap();
+                pins.entry(host.clone())
+                    .or_insert_with(|| stored.unwrap_or_else(|| sha256.to_string()))
+                    .clone()
+            }
+        };
+        if pinned == sha256 {
+            return Ok(());
+        }
+        eprintln!(
+            "[tls] certificate mismatch host={} pinned={} presented={} action={:?}",
+            host, pinned, sha256, self.on_mismatch
+        );
+        if let Some(pool) = self.db() {
+            if let Err(err) = crate::storage::cert_pins::record_mismatch(pool, &host, sha256).await
+            {
+                eprintln!("[tls] record mismatch host={} error={:?}", host, err);
+            }
+        }
+        match self.on_mismatch {
+            PinMismatch::Refuse => Err(ClientError::Certificate),
+            PinMismatch::Flag => Ok(()),
+        }
+    }
+
+    async fn pin_in_db(&self, host: &str, sha256: &str) -> Option<String> {
+        let pool = self.db()?;
+        match crate::storage::cert_pins::pin_first_use(pool, host, sha256).await {
+            Ok(pinned) => Some(pinned),
+            Err(err) => {
+                eprintln!("[tls] pin host={} error={:?}", host, err);
+                None
+            }
+        }
+    }
+
+    fn db(&self) -> Option<&'static gurt_db::PgPool> {
+        self.persist.then(crate::services::try_db).flatten()
+    }
+}
+
+/// Accepts any certificate but checks the handshake signature against it; trust comes
+/// from the pin check after the handshake.
+#[derive(Debug)]
+struct SignatureOnlyVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SignatureOnlyVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+// Development-only certificate verifier (accepts any cert). Do not use in production.
+#[derive(Debug)]
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        use SignatureScheme::*;
+        vec![
+            ECDSA_NISTP256_SHA256,
+            ECDSA_NISTP384_SHA384,
+            ED25519,
+            RSA_PKCS1_SHA256,
+            RSA_PKCS1_SHA384,
+            RSA_PKCS1_SHA512,
+            RSA_PSS_SHA256,
+            RSA_PSS_SHA384,
+            RSA_PSS_SHA512,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pins_on_first_use_and_refuses_or_flags_changes() {
+        let pins = PinStore::new(PinMismatch::Refuse);
+        assert_eq!(pins.check("Example.Real.", "aa").await, Ok(()));
+        assert_eq!(pins.check("example.real", "aa").await, Ok(()));
+        assert_eq!(
+            pins.check("example.real", "bb").await,
+            Err(ClientError::Certificate)
+        );
+        assert_eq!(pins.check("other.real", "bb").await, Ok(()));
+
+        let flagging = PinStore::new(PinMismatch::Flag);
+        assert_eq!(flagging.check("example.real", "aa").await, Ok(()));
+        assert_eq!(flagging.check("example.real", "bb").await, Ok(()));
+        // Flagging does not move the pin.
+        assert_eq!(flagging.check("example.real", "aa").await, Ok(()));
+    }
+
+    #[test]
+    fn load_roots_rejects_missing_and_empty_files() {
+        assert!(load_roots("/nonexistent/ca.pem").is_err());
+        let dir = std::env::temp_dir().join(format!("gurt-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.pem");
+        std::fs::write(&empty, "no certificates here\n").unwrap();
+        assert!(load_roots(empty.to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
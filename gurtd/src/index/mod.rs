@@ -1,5 +1,5 @@
 pub use gurt_index::{noop, tantivy};
-pub use gurt_index::{IndexDocument, IndexEngine, SearchHit};
+pub use gurt_index::{IndexDocument, IndexEngine, SearchHit, SearchResults};
 
 pub fn make_engine(name: &str) -> anyhow::Result<Box<dyn IndexEngine>> {
     gurt_index::register_defaults();
@@ -0,0 +1,129 @@
+//! Periodic PageRank over the stored link graph.
+//!
+//! Each run loads `link_edges`, ranks it with [`LinkGraph::pagerank`], writes the results
+//! to `link_authority` and swaps them into [`global_authority`], which the ranker reads.
+//! On start the last stored scores are loaded first, so a restart does not rank without
+//! authority until the next run. Recorded CNAME depths (domain trust) are reloaded with
+//! every run as well.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use gurt_db::PgPool;
+
+use crate::link::{authority_scores, global_authority, set_cname_depth, AuthorityStore, LinkGraph};
+use crate::storage::domains;
+use crate::storage::links::{self, Authority};
+
+const DAMPING: f64 = 0.85;
+const ITERATIONS: usize = 30;
+const DEFAULT_INTERVAL_SECS: u64 = 3_600;
+/// Delay before the first run, so a fresh start crawls a little before ranking.
+const FIRST_RUN_DELAY: Duration = Duration::from_secs(60);
+
+/// - GURT_PAGERANK_INTERVAL_SECS (default 3600; 0 disables the job)
+pub(super) async fn run(pool: PgPool) {
+    let interval_secs = std::env::var("GURT_PAGERANK_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    load_domain_trust(&pool).await;
+    match links::load_authority(&pool).await {
+        Ok(rows) if !rows.is_empty() => {
+            eprintln!("[authority] loaded scores urls={}", rows.len());
+            install(rows);
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("[authority] load error={:?}", err),
+    }
+    if interval_secs == 0 {
+        eprintln!("[authority] pagerank disabled via GURT_PAGERANK_INTERVAL_SECS");
+        return;
+    }
+    tokio::time::sleep(FIRST_RUN_DELAY).await;
+    loop {
+        load_domain_trust(&pool).await;
+        if let Err(err) = run_once(&pool).await {
+            eprintln!("[authority] pagerank error={:?}", err);
+        }
+        tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+    }
+}
+
+async fn run_once(pool: &PgPool) -> anyhow::Result<()> {
+    let started = std::time::Instant::now();
+    let edges = links::load_edges(pool).await?;
+    if edges.is_empty() {
+        return Ok(());
+    }
+    let rows = rank(&edges);
+    let written = links::store_authority(pool, &rows).await?;
+    eprintln!(
+        "[authority] pagerank edges={} urls={} written={} took={:?}",
+        edges.len(),
+        rows.len(),
+        written,
+        started.elapsed()
+    );
+    install(rows.into_iter().map(|a| (a.url, a.score)));
+    Ok(())
+}
+
+async fn load_domain_trust(pool: &PgPool) {
+    match domains::load_cname_depths(pool).await {
+        Ok(rows) => {
+            for (domain, depth) in rows {
+                set_cname_depth(&domain, depth.max(0) as usize);
+            }
+        }
+        Err(err) => eprintln!("[authority] load cname depths error={:?}", err),
+    }
+}
+
+/// PageRank plus link counts for every URL in the graph.
+fn rank(edges: &[(String, String)]) -> Vec<Authority> {
+    let mut graph = LinkGraph::new();
+    let mut inbound: HashMap<&str, i32> = HashMap::new();
+    for (src, dst) in edges {
+        graph.add_edge(src, dst);
+        *inbound.entry(dst.as_str()).or_default() += 1;
+    }
+    let ranks = graph.pagerank(DAMPING, ITERATIONS);
+    let scores = authority_scores(&ranks);
+    ranks
+        .iter()
+        .map(|(url, pr)| Authority {
+            url: url.clone(),
+            page_rank: *pr,
+            inbound_links: inbound.get(url.as_str()).copied().unwrap_or(0),
+            outbound_links: graph.edges.get(url).map_or(0, |v| v.len() as i32),
+            score: scores.get(url).copied().unwrap_or(0.0),
+        })
+        .collect()
+}
+
+fn install(scores: impl IntoIterator<Item = (String, f64)>) {
+    let mut store = AuthorityStore::new();
+    for (url, score) in scores {
+        store.set(url, score as f32);
+    }
+    *global_authority().write().unwrap() = store;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_counts_links() {
+        let e = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let rows = rank(&[e("a", "hub"), e("b", "hub"), e("hub", "a")]);
+        let get = |u: &str| rows.iter().find(|r| r.url == u).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(get("hub").inbound_links, 2);
+        assert_eq!(get("hub").outbound_links, 1);
+        assert_eq!(get("b").inbound_links, 0);
+        assert_eq!(get("hub").score, 1.0);
+        assert!(get("hub").page_rank > get("b").page_rank);
+    }
+}
@@ -1,15 +1,32 @@
-use anyhow::anyhow;
 use once_cell::sync::Lazy;
 use serde_json::json;
 use std::collections::HashMap;
 use std::net::IpAddr;
-use std::time::{Duration as StdDuration, Instant};
-use tokio::io::AsyncWriteExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::fetch::tls_connector;
+use crate::crawler::client::{tcp_connector, ClientRequest, GurtClient};
+use crate::crawler::keepalive::ConnPool;
+use crate::crawler::resolver::{
+    normalize_host, ResolveError, ResolveFuture, Resolver, StaticResolver,
+};
+use crate::crawler::tls::upgrade_from_env;
 
-const DNS_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(2);
-const DNS_CACHE_TTL: StdDuration = StdDuration::from_secs(60);
+/// Bound on one `/resolve-full` exchange.
+const DNS_TIMEOUT: Duration = Duration::from_secs(2);
+/// Cache lifetime of an answer whose records carry no ttl.
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// Record ttls are clamped to this range: a ttl of 0 would send every connect to the DNS
+/// service, a very long one would keep a moved site on its old address.
+const MIN_TTL: Duration = Duration::from_secs(5);
+const MAX_TTL: Duration = Duration::from_secs(6 * 3600);
+/// How long a name without address (NXDOMAIN, rejected CNAME chain) is remembered.
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+/// How long a failed lookup is remembered, so an unreachable DNS service is not asked
+/// again by every fetch.
+const FAILURE_TTL: Duration = Duration::from_secs(5);
+/// Above this many cached names, expired entries are dropped on insert.
+const MAX_CACHE_ENTRIES: usize = 10_000;
 
 pub fn dns_service_endpoint() -> (String, Option<IpAddr>, u16) {
     let host = std::env::var("GURT_DNS_HOST").unwrap_or_else(|_| "dns.web".to_string());
@@ -23,238 +40,282 @@ pub fn dns_service_endpoint() -> (String, Option<IpAddr>, u16) {
     (host, addr, port)
 }
 
-pub async fn resolve_via_gurt_dns(domain: &str) -> Option<IpAddr> {
-    if let Some(ip) = dns_cache_get(domain) {
-        debug_log(|| format!("[indexing] dns cache hit domain={} ip={}", domain, ip));
-        return Some(ip);
-    }
-    let (dns_host, dns_addr, dns_port) = dns_service_endpoint();
-    debug_log(|| {
-        format!(
-            "[indexing] dns resolve domain={} via host={} addr={:?} port={}",
-            domain, dns_host, dns_addr, dns_port
-        )
-    });
+/// Most CNAME hops followed. Deeper chains do not resolve and, as recorded, earn no trust
+/// (see [`crate::link::domain_trust_from_cname_depth`]).
+const MAX_CNAME_DEPTH: usize = 5;
 
-    let mut current = domain.to_string();
-    let original = current.clone();
-    let mut depth = 0usize;
-    const MAX_CNAME_DEPTH: usize = 5;
-    while depth < MAX_CNAME_DEPTH {
-        depth += 1;
-        let body_val = json!({ "domain": current });
-        let body = match serde_json::to_vec(&body_val) {
-            Ok(b) => b,
-            Err(_) => return None,
-        };
+/// Outcome of walking a domain's CNAME chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// Addresses of the chain's last name, A before AAAA; empty when the chain was too
+    /// deep or looped.
+    pub addrs: Vec<IpAddr>,
+    /// CNAME targets in the order they were followed; empty for a direct address record.
+    pub cname_chain: Vec<String>,
+    /// Smallest record ttl along the chain.
+    pub ttl: Option<Duration>,
+}
 
-        let work = async {
-            let mut tcp = match match dns_addr {
-                Some(ip) => tokio::net::TcpStream::connect((ip, dns_port)).await,
-                None => tokio::net::TcpStream::connect((dns_host.as_str(), dns_port)).await,
-            } {
-                Ok(s) => s,
-                Err(_) => return None,
-            };
-            tcp.set_nodelay(true).ok();
-            if super::fetch::perform_handshake(&mut tcp, &dns_host)
-                .await
-                .is_err()
-            {
-                return None;
-            }
-            let connector = tls_connector();
-            let server_name = match server_name_from_host(&dns_host) {
-                Ok(n) => n,
-                Err(_) => return None,
-            };
-            let mut tls = match connector.connect(server_name, tcp).await {
-                Ok(t) => t,
-                Err(_) => return None,
-            };
-            if send_request_with_body(
-                &mut tls,
-                &dns_host,
-                "/resolve-full",
-                "POST",
-                &[
-                    ("content-type", "application/json"),
-                    ("accept", "application/json"),
-                ],
-                &body,
-            )
-            .await
-            .is_err()
-            {
-                return None;
-            }
-            let resp = match super::fetch::read_response(&mut tls).await {
-                Ok(r) => r,
-                Err(_) => return None,
-            };
-            if resp.code < 200 || resp.code >= 300 {
-                return None;
-            }
-            if let Some(ip) = pick_ip_from_dns_response(&resp.body) {
-                dns_cache_put(&current, ip);
-                return Some(ip);
-            }
-            if let Some(next) = pick_cname_from_dns_response(&resp.body) {
-                debug_log(|| format!("[indexing] dns cname {} -> {}", current, next));
-                // Update outer current
-                return None; // signal to outer loop to update current via second request below
-            }
-            None
-        };
-        match tokio::time::timeout(DNS_TIMEOUT, work).await {
-            Ok(Some(ip)) => {
-                dns_cache_put(&current, ip);
-                dns_cache_put(&original, ip);
-                return Some(ip);
-            }
-            Ok(None) => {
-                // obtain CNAME explicitly
-                let body_val = json!({ "domain": current });
-                let body = match serde_json::to_vec(&body_val) {
-                    Ok(b) => b,
-                    Err(_) => return None,
-                };
-                let next = tokio::time::timeout(DNS_TIMEOUT, async {
-                    let mut tcp = match match dns_addr {
-                        Some(ip) => tokio::net::TcpStream::connect((ip, dns_port)).await,
-                        None => tokio::net::TcpStream::connect((dns_host.as_str(), dns_port)).await,
-                    } {
-                        Ok(s) => s,
-                        Err(_) => return None,
-                    };
-                    tcp.set_nodelay(true).ok();
-                    if super::fetch::perform_handshake(&mut tcp, &dns_host)
-                        .await
-                        .is_err()
-                    {
-                        return None;
-                    }
-                    let connector = tls_connector();
-                    let server_name = server_name_from_host(&dns_host).ok()?;
-                    let mut tls = connector.connect(server_name, tcp).await.ok()?;
-                    if send_request_with_body(
-                        &mut tls,
-                        &dns_host,
-                        "/resolve-full",
-                        "POST",
-                        &[
-                            ("content-type", "application/json"),
-                            ("accept", "application/json"),
-                        ],
-                        &body,
-                    )
-                    .await
-                    .is_err()
-                    {
-                        return None;
-                    }
-                    let resp = super::fetch::read_response(&mut tls).await.ok()?;
-                    pick_cname_from_dns_response(&resp.body)
-                })
-                .await
-                .ok()
-                .flatten();
-                if let Some(next) = next {
-                    current = next;
-                    continue;
+/// Records of one `/resolve-full` answer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsAnswer {
+    /// A records, then AAAA records, each in response order.
+    pub addrs: Vec<IpAddr>,
+    pub cname: Option<String>,
+    /// Smallest ttl of the address records, or the CNAME's when there are none.
+    pub ttl: Option<Duration>,
+}
+
+/// Resolver used by the crawler's connections:
+/// - GURT_HOSTS_FILE: resolve from this hosts file only, never asking GURT DNS (tests,
+///   air-gapped setups)
+/// - otherwise GURT DNS, see [`GurtDnsResolver::from_env`]
+pub fn resolver() -> Arc<dyn Resolver> {
+    static RESOLVER: Lazy<Arc<dyn Resolver>> = Lazy::new(|| {
+        if let Some(path) = std::env::var("GURT_HOSTS_FILE")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+        {
+            match StaticResolver::from_hosts_file(&path) {
+                Ok(hosts) => return Arc::new(hosts),
+                Err(err) => {
+                    eprintln!("[indexing] GURT_HOSTS_FILE not loaded, using GURT DNS: {err:#}")
                 }
-                break;
-            }
-            Err(_) => {
-                debug_log(|| format!("[indexing] dns resolve timeout domain={}", current));
-                return None;
             }
         }
-    }
-    None
+        Arc::new(GurtDnsResolver::from_env())
+    });
+    RESOLVER.clone()
 }
 
-pub fn pick_ip_from_dns_response(body: &[u8]) -> Option<IpAddr> {
-    let v: serde_json::Value = serde_json::from_slice(body).ok()?;
-    let records = v.get("records")?.as_array()?;
-    for rec in records {
-        let typ = rec.get("type").and_then(|t| t.as_str()).unwrap_or("");
-        if typ.eq_ignore_ascii_case("A") {
-            if let Some(val) = rec.get("value").and_then(|x| x.as_str()) {
-                if let Ok(ip) = val.parse::<IpAddr>() {
-                    if matches!(ip, IpAddr::V4(_)) {
-                        return Some(ip);
-                    }
+type CacheEntry = (Result<Vec<IpAddr>, ResolveError>, Instant);
+
+/// [`Resolver`] asking the GURT DNS service's `/resolve-full`. Answers are cached for
+/// their records' ttl, names without address for [`NEGATIVE_TTL`] and failures for
+/// [`FAILURE_TTL`]. The client keeps its connection to the service alive, so the
+/// requests of one CNAME chain go over one connection.
+pub struct GurtDnsResolver {
+    client: GurtClient,
+    url: String,
+    /// Answer per name and when it expires.
+    cache: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl GurtDnsResolver {
+    pub fn new(client: GurtClient, dns_host: &str, dns_port: u16) -> Self {
+        Self {
+            client,
+            url: format!("gurt://{}:{}/resolve-full", dns_host, dns_port),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolver for [`dns_service_endpoint`]. It connects to `GURT_DNS_ADDR` when set and
+    /// otherwise resolves the service host with the OS resolver, never through GURT DNS
+    /// itself.
+    pub fn from_env() -> Self {
+        let (host, addr, port) = dns_service_endpoint();
+        let resolver = addr.map(|ip| -> Arc<dyn Resolver> {
+            Arc::new(StaticResolver::new().with_host(&host, ip))
+        });
+        let mut client = GurtClient::new_with_connector(tcp_connector(resolver))
+            .with_upgrade(upgrade_from_env())
+            .with_keepalive(Arc::new(ConnPool::from_env()));
+        client.user_agent = Some(super::fetch::user_agent());
+        Self::new(client, &host, port)
+    }
+
+    /// Addresses of `domain`, from the cache or a fresh walk of its CNAME chain. A fresh
+    /// walk also records the chain (see [`record_cname_chain`]).
+    pub async fn lookup(&self, domain: &str) -> Result<Vec<IpAddr>, ResolveError> {
+        let domain = normalize_host(domain);
+        if let Some(answer) = self.cached(&domain) {
+            debug_log(|| {
+                format!(
+                    "[indexing] dns cache hit domain={} answer={:?}",
+                    domain, answer
+                )
+            });
+            return answer;
+        }
+        let (answer, ttl) = match self.resolve_cname_chain(&domain).await {
+            Ok(resolution) => {
+                record_cname_chain(&domain, &resolution.cname_chain).await;
+                if resolution.addrs.is_empty() {
+                    (Err(ResolveError::NotFound), NEGATIVE_TTL)
+                } else {
+                    let ttl = resolution.ttl.unwrap_or(DEFAULT_TTL);
+                    (Ok(resolution.addrs), ttl.clamp(MIN_TTL, MAX_TTL))
                 }
             }
+            Err(ResolveError::NotFound) => (Err(ResolveError::NotFound), NEGATIVE_TTL),
+            Err(ResolveError::Failed) => (Err(ResolveError::Failed), FAILURE_TTL),
+        };
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            cache.retain(|_, (_, expires)| *expires > now);
         }
+        cache.insert(domain, (answer.clone(), now + ttl));
+        answer
     }
-    for rec in records {
-        let typ = rec.get("type").and_then(|t| t.as_str()).unwrap_or("");
-        if typ.eq_ignore_ascii_case("AAAA") {
-            if let Some(val) = rec.get("value").and_then(|x| x.as_str()) {
-                if let Ok(ip) = val.parse::<IpAddr>() {
-                    return Some(ip);
-                }
+
+    fn cached(&self, domain: &str) -> Option<Result<Vec<IpAddr>, ResolveError>> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(domain) {
+            Some((answer, expires)) if *expires > Instant::now() => Some(answer.clone()),
+            Some(_) => {
+                cache.remove(domain);
+                None
             }
+            None => None,
         }
     }
-    None
-}
 
-pub fn pick_cname_from_dns_response(body: &[u8]) -> Option<String> {
-    let v: serde_json::Value = serde_json::from_slice(body).ok()?;
-    let records = v.get("records")?.as_array()?;
-    for rec in records {
-        let typ = rec.get("type").and_then(|t| t.as_str()).unwrap_or("");
-        if typ.eq_ignore_ascii_case("CNAME") {
-            if let Some(val) = rec.get("value").and_then(|x| x.as_str()) {
-                let target = val.trim().trim_end_matches('.').to_string();
-                if !target.is_empty() {
-                    return Some(target);
+    /// Follow CNAME records from `domain` to its addresses. NotFound when a name of the
+    /// chain has neither an address nor a CNAME.
+    pub async fn resolve_cname_chain(&self, domain: &str) -> Result<Resolution, ResolveError> {
+        debug_log(|| format!("[indexing] dns resolve domain={} via {}", domain, self.url));
+
+        let mut chain: Vec<String> = Vec::new();
+        let mut ttl: Option<Duration> = None;
+        let mut current = domain.to_string();
+        loop {
+            let answer = match tokio::time::timeout(DNS_TIMEOUT, self.resolve_full(&current)).await
+            {
+                Ok(answer) => answer?,
+                Err(_) => {
+                    debug_log(|| format!("[indexing] dns resolve timeout domain={}", current));
+                    return Err(ResolveError::Failed);
                 }
+            };
+            ttl = min_ttl(ttl, answer.ttl);
+            if !answer.addrs.is_empty() {
+                return Ok(Resolution {
+                    addrs: answer.addrs,
+                    cname_chain: chain,
+                    ttl,
+                });
+            }
+            let next = answer.cname.ok_or(ResolveError::NotFound)?;
+            debug_log(|| format!("[indexing] dns cname {} -> {}", current, next));
+            let looped = next.eq_ignore_ascii_case(domain)
+                || chain.iter().any(|c| c.eq_ignore_ascii_case(&next));
+            chain.push(next.clone());
+            if looped || chain.len() > MAX_CNAME_DEPTH {
+                debug_log(|| {
+                    format!(
+                        "[indexing] dns cname chain rejected domain={} chain={:?}",
+                        domain, chain
+                    )
+                });
+                return Ok(Resolution {
+                    addrs: Vec::new(),
+                    cname_chain: chain,
+                    ttl,
+                });
             }
+            current = next;
+        }
+    }
+
+    /// One `/resolve-full` request for `name`.
+    async fn resolve_full(&self, name: &str) -> Result<DnsAnswer, ResolveError> {
+        let body =
+            serde_json::to_vec(&json!({ "domain": name })).map_err(|_| ResolveError::Failed)?;
+        let req = ClientRequest::post(self.url.clone(), body)
+            .header("content-type", "application/json")
+            .header("accept", "application/json");
+        let resp = self
+            .client
+            .send(&req)
+            .await
+            .map_err(|_| ResolveError::Failed)?;
+        match resp.code {
+            200..=299 => parse_dns_response(&resp.body).ok_or(ResolveError::Failed),
+            404 => Err(ResolveError::NotFound),
+            _ => Err(ResolveError::Failed),
         }
     }
-    None
 }
 
-pub fn server_name_from_host(host: &str) -> anyhow::Result<rustls::pki_types::ServerName<'static>> {
-    if let Ok(ip) = host.parse::<IpAddr>() {
-        Ok(rustls::pki_types::ServerName::IpAddress(ip.into()))
-    } else {
-        rustls::pki_types::ServerName::try_from(host.to_owned())
-            .map_err(|_| anyhow!("invalid server name"))
+impl Resolver for GurtDnsResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> ResolveFuture<'a> {
+        Box::pin(self.lookup(host))
     }
 }
 
-async fn send_request_with_body(
-    stream: &mut tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
-    host: &str,
-    path: &str,
-    method: &str,
-    extra_headers: &[(&str, &str)],
-    body: &[u8],
-) -> anyhow::Result<()> {
-    let mut req = format!("{} {} GURT/1.0.0\r\nhost: {}\r\n", method, path, host);
-    for (k, v) in extra_headers {
-        req.push_str(k);
-        req.push_str(": ");
-        req.push_str(v);
-        req.push_str("\r\n");
+fn min_ttl(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
     }
-    if !extra_headers
-        .iter()
-        .any(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+}
+
+/// Publish `domain`'s CNAME depth to the ranker and store the chain in Postgres when it
+/// differs from what this process stored last.
+async fn record_cname_chain(domain: &str, chain: &[String]) {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    crate::link::set_cname_depth(&domain, chain.len());
     {
-        req.push_str(&format!("content-length: {}\r\n", body.len()));
+        let mut recorded = RECORDED_CHAINS.lock().unwrap();
+        if recorded.get(&domain).is_some_and(|c| c.as_slice() == chain) {
+            return;
+        }
+        recorded.insert(domain.clone(), chain.to_vec());
+    }
+    let Some(pool) = crate::services::try_db() else {
+        return;
+    };
+    if let Err(err) = crate::storage::domains::record_cname_chain(pool, &domain, chain).await {
+        eprintln!("[indexing] dns record domain={} error={:?}", domain, err);
+    }
+}
+
+/// Address and CNAME records of a `/resolve-full` response body. None if the body is not
+/// a JSON object with a `records` array.
+pub fn parse_dns_response(body: &[u8]) -> Option<DnsAnswer> {
+    let v: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let records = v.get("records")?.as_array()?;
+    let (mut v4, mut v6) = (Vec::new(), Vec::new());
+    let (mut addr_ttl, mut cname, mut cname_ttl) = (None, None, None);
+    for rec in records {
+        let typ = rec.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let Some(val) = rec.get("value").and_then(|x| x.as_str()) else {
+            continue;
+        };
+        let ttl = rec
+            .get("ttl")
+            .and_then(|t| t.as_u64())
+            .map(Duration::from_secs);
+        if typ.eq_ignore_ascii_case("A") || typ.eq_ignore_ascii_case("AAAA") {
+            let Ok(ip) = val.trim().parse::<IpAddr>() else {
+                continue;
+            };
+            let list = match (typ.eq_ignore_ascii_case("A"), ip) {
+                (true, IpAddr::V4(_)) => &mut v4,
+                (true, IpAddr::V6(_)) => continue,
+                (false, _) => &mut v6,
+            };
+            if !list.contains(&ip) {
+                list.push(ip);
+            }
+            addr_ttl = min_ttl(addr_ttl, ttl);
+        } else if typ.eq_ignore_ascii_case("CNAME") && cname.is_none() {
+            let target = val.trim().trim_end_matches('.').to_string();
+            if !target.is_empty() {
+                cname = Some(target);
+                cname_ttl = ttl;
+            }
+        }
     }
-    req.push_str("\r\n");
-    let mut bytes = req.into_bytes();
-    bytes.extend_from_slice(body);
-    stream.write_all(&bytes).await?;
-    stream.flush().await?;
-    Ok(())
+    v4.append(&mut v6);
+    let ttl = if v4.is_empty() { cname_ttl } else { addr_ttl };
+    Some(DnsAnswer {
+        addrs: v4,
+        cname,
+        ttl,
+    })
 }
 
 fn debug_log<F>(f: F)
@@ -272,30 +333,15 @@ where
     }
 }
 
-static DNS_CACHE: Lazy<std::sync::Mutex<HashMap<String, (IpAddr, Instant)>>> =
+/// Last CNAME chain stored per domain, to skip redundant writes.
+static RECORDED_CHAINS: Lazy<std::sync::Mutex<HashMap<String, Vec<String>>>> =
     Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
 
-fn dns_cache_get(domain: &str) -> Option<IpAddr> {
-    let mut map = DNS_CACHE.lock().ok()?;
-    if let Some((ip, t)) = map.get(domain) {
-        if t.elapsed() <= DNS_CACHE_TTL {
-            return Some(*ip);
-        }
-    }
-    map.remove(domain);
-    None
-}
-
-fn dns_cache_put(domain: &str, ip: IpAddr) {
-    if let Ok(mut map) = DNS_CACHE.lock() {
-        map.insert(domain.to_string(), (ip, Instant::now()));
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use super::{pick_cname_from_dns_response, pick_ip_from_dns_response};
+    use super::parse_dns_response;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
 
     #[test]
     fn picks_ipv4_a_record_first() {
@@ -306,7 +352,7 @@ mod tests {
                 {"id":1,"type":"A","name":"api.blog","value":"192.168.1.100","ttl":3600}
             ]
         }"#;
-        let ip = pick_ip_from_dns_response(body).unwrap();
+        let ip = parse_dns_response(body).unwrap().addrs[0];
         assert_eq!(ip, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 100)));
     }
 
@@ -319,7 +365,7 @@ mod tests {
                 {"id":2,"type":"AAAA","name":"x","value":"2001:db8::1","ttl":3600}
             ]
         }"#;
-        let ip = pick_ip_from_dns_response(body).unwrap();
+        let ip = parse_dns_response(body).unwrap().addrs[0];
         assert_eq!(
             ip,
             IpAddr::V6(Ipv6Addr::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1))
@@ -335,8 +381,8 @@ mod tests {
                 {"id":3,"type":"TXT","name":"x","value":"hello","ttl":60}
             ]
         }"#;
-        let ip = pick_ip_from_dns_response(body);
-        assert!(ip.is_none());
+        let answer = parse_dns_response(body).unwrap();
+        assert!(answer.addrs.is_empty());
     }
 
     #[test]
@@ -348,7 +394,42 @@ mod tests {
                 {"id":10,"type":"CNAME","name":"www","value":"example.web.","ttl":300}
             ]
         }"#;
-        let cname = pick_cname_from_dns_response(body);
+        let cname = parse_dns_response(body).unwrap().cname;
         assert_eq!(cname.as_deref(), Some("example.web"));
     }
+
+    #[test]
+    fn collects_every_address_with_the_smallest_ttl() {
+        let body = br#"{
+            "records": [
+                {"id":1,"type":"AAAA","name":"x","value":"2001:db8::1","ttl":120},
+                {"id":2,"type":"A","name":"x","value":"10.0.0.1","ttl":300},
+                {"id":3,"type":"A","name":"x","value":"10.0.0.2","ttl":60},
+                {"id":4,"type":"A","name":"x","value":"10.0.0.1","ttl":300}
+            ]
+        }"#;
+        let answer = parse_dns_response(body).unwrap();
+        assert_eq!(
+            answer.addrs,
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1)),
+            ]
+        );
+        assert_eq!(answer.ttl, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn cname_answer_carries_the_cname_ttl() {
+        let body = br#"{
+            "records": [
+                {"id":10,"type":"CNAME","name":"www","value":"example.web.","ttl":30}
+            ]
+        }"#;
+        let answer = parse_dns_response(body).unwrap();
+        assert!(answer.addrs.is_empty());
+        assert_eq!(answer.cname.as_deref(), Some("example.web"));
+        assert_eq!(answer.ttl, Some(Duration::from_secs(30)));
+    }
 }
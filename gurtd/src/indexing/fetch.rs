@@ -1,29 +1,263 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio_rustls::client::TlsStream;
 
-use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use gurt_api::limits::{enforce_max_message_size, MAX_MESSAGE_BYTES};
-use rustls::{DigitallySignedStruct, SignatureScheme};
+use gurt_api::status::StatusCode;
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedSemaphorePermit;
 
-use crate::crawler::client::ClientResponse;
+use crate::crawler::client::{tcp_connector, ClientRequest, ClientResponse, GurtClient};
+use crate::crawler::keepalive::ConnPool;
+use crate::crawler::meta_robots::RobotsDirectives;
 use crate::crawler::pipeline::{process_fetched_document, DynamicReCrawlQueue};
+use crate::crawler::redirect::{RedirectChain, RedirectError, RedirectPolicy};
+use crate::crawler::scheduler::HostScheduler;
+use crate::crawler::tls::upgrade_from_env;
+use crate::link::{canonicalize_url, extract_page_links, PageLinks};
 use crate::services;
 
-use super::dns::{resolve_via_gurt_dns, server_name_from_host};
+use super::robots::DomainRobots;
 
-const DEFAULT_PORT: u16 = super::DEFAULT_PORT;
-const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 10_000;
-const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 5_000;
-const DEFAULT_FETCH_TIMEOUT_MS: u64 = 30_000;
-const DEFAULT_READ_IDLE_MS: u64 = 500;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_millis(10_000);
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(5_000);
+const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_millis(30_000);
+const DEFAULT_READ_IDLE: Duration = Duration::from_millis(500);
 const MIN_READ_IDLE_MS: u64 = 100;
 const MAX_READ_IDLE_MS: u64 = 5_000;
 
-pub async fn index_single_url(url: &str, recrawl: &DynamicReCrawlQueue) -> Result<()> {
-    let resp = fetch_gurt(url).await?;
+/// What one fetch attempt observed; persisted to `fetch_history` and the `urls` row.
+#[derive(Debug, Clone, Default)]
+pub struct FetchAttempt {
+    /// URL this attempt requested.
+    pub url: String,
+    /// Response status, None when no response was received.
+    pub status: Option<u16>,
+    pub latency: Duration,
+    pub headers: Vec<(String, String)>,
+    /// Body bytes received.
+    pub content_length: Option<u64>,
+    /// SHA-256 of the body.
+    pub content_hash: Option<Vec<u8>>,
+    /// The redirect responses that led to this one, in order.
+    pub redirects: Vec<FetchAttempt>,
+}
+
+impl FetchAttempt {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header_value(&self.headers, name)
+    }
+
+    /// `last-modified` of the response, unix seconds.
+    pub fn last_modified(&self) -> Option<i64> {
+        let t = httpdate::parse_http_date(self.header("last-modified")?).ok()?;
+        let secs = t.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+        i64::try_from(secs).ok()
+    }
+
+    /// Response headers as a JSON object; repeated headers are joined with ", ".
+    pub fn headers_json(&self) -> Option<serde_json::Value> {
+        self.status?;
+        let mut map = serde_json::Map::new();
+        for (name, value) in &self.headers {
+            match map.get_mut(name) {
+                Some(serde_json::Value::String(existing)) => {
+                    existing.push_str(", ");
+                    existing.push_str(value);
+                }
+                _ => {
+                    map.insert(name.clone(), serde_json::Value::String(value.clone()));
+                }
+            }
+        }
+        Some(serde_json::Value::Object(map))
+    }
+}
+
+/// What the last successful fetch of a URL returned. The etag and date are sent as
+/// `if-none-match`/`if-modified-since`; the body hash catches servers that ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+    pub content_hash: Option<Vec<u8>>,
+}
+
+impl Validators {
+    fn request_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(("if-none-match", etag.clone()));
+        }
+        if let Some(t) = self.last_modified {
+            headers.push(("if-modified-since", httpdate::fmt_http_date(t)));
+        }
+        headers
+    }
+}
+
+/// What every crawler request to a domain goes through: its robots.txt, and the
+/// scheduler that bounds concurrency and spaces requests by crawl-delay.
+pub struct Politeness<'a> {
+    pub domain: &'a str,
+    pub robots: &'a DomainRobots,
+    pub scheduler: &'a HostScheduler,
+}
+
+impl Politeness<'_> {
+    /// Permits for one request to the domain; hold them until the response is read.
+    async fn acquire(&self) -> (OwnedSemaphorePermit, OwnedSemaphorePermit) {
+        self.scheduler
+            .acquire_polite(self.domain, self.robots.crawl_delay())
+            .await
+    }
+
+    /// Fetch `url` (a page script or a sitemap), unless robots.txt disallows it.
+    pub(super) async fn fetch_allowed(&self, url: &str) -> Result<ClientResponse> {
+        if !self.robots.allows(url) {
+            return Err(anyhow!("{url} blocked by robots.txt"));
+        }
+        let permits = self.acquire().await;
+        let resp = fetch_gurt(url).await;
+        drop(permits);
+        resp
+    }
+}
+
+/// Result of [`index_single_url`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fetched {
+    /// The page was (re-)indexed, or removed from the index because it is `noindex`; its
+    /// links (empty for non-HTML responses and `nofollow` pages).
+    Indexed(PageLinks),
+    /// 304, or the same body as last time: the indexed version is current.
+    Unchanged,
+    /// A redirect to another domain, which is crawled as a domain of its own rather than
+    /// followed; the target URL.
+    Redirected(String),
+    /// A same-domain redirect to a URL robots.txt disallows, which was not requested;
+    /// the target URL.
+    Blocked(String),
+    /// 404 or 410: the page is gone and was removed from the index.
+    Gone,
+}
+
+/// Fetch and index one URL, rendering dynamic pages within `render_budget`.
+/// Every request goes through `polite`, whose permits are released before indexing.
+/// Redirects within the domain are followed per `redirects` as long as robots.txt allows
+/// the target, and the page at the end of the chain is indexed under its canonical URL. With
+/// `validators` of an earlier fetch the request is conditional and an unchanged page is
+/// not re-indexed. Alongside the result, returns what the fetch observed, whether or not
+/// indexing succeeded.
+pub async fn index_single_url(
+    url: &str,
+    polite: &Politeness<'_>,
+    validators: &Validators,
+    redirects: RedirectPolicy,
+    recrawl: &DynamicReCrawlQueue,
+    render_budget: Duration,
+) -> (FetchAttempt, Result<Fetched>) {
+    // The stored validators describe the end of the chain, so every request sends them.
+    let conditional = validators.request_headers();
+    let mut chain = RedirectChain::new(url, redirects);
+    let mut hops = Vec::new();
+    let (mut attempt, resp) = loop {
+        let permits = polite.acquire().await;
+        let started = Instant::now();
+        let resp = fetch_gurt_with(chain.current(), &conditional).await;
+        drop(permits);
+        let mut attempt = FetchAttempt {
+            url: chain.current().to_string(),
+            latency: started.elapsed(),
+            ..FetchAttempt::default()
+        };
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(err) => {
+                attempt.redirects = hops;
+                return (attempt, Err(err));
+            }
+        };
+        attempt.status = Some(resp.code);
+        if !redirects.follows(resp.code) {
+            break (attempt, resp);
+        }
+        attempt.headers = resp.headers;
+        let followed = chain.follow(attempt.header("location")).map(str::to_owned);
+        match followed {
+            // Even when the policy follows cross-domain redirects, `polite` only covers
+            // this domain's robots.txt and scheduler.
+            Ok(next) if !on_domain(&next, polite.domain) => {
+                attempt.redirects = hops;
+                return (attempt, Ok(Fetched::Redirected(next)));
+            }
+            Ok(next) if !polite.robots.allows(&next) => {
+                attempt.redirects = hops;
+                return (attempt, Ok(Fetched::Blocked(next)));
+            }
+            Ok(next) => {
+                debug_log(|| format!("[indexing] redirect url={} -> {}", attempt.url, next));
+                hops.push(attempt);
+            }
+            Err(err) => {
+                attempt.redirects = hops;
+                let result = match err {
+                    RedirectError::OffDomain(target) => Ok(Fetched::Redirected(target)),
+                    err => Err(err.into()),
+                };
+                return (attempt, result);
+            }
+        }
+    };
+    attempt.redirects = hops;
+    let result = if StatusCode::from_u16(resp.code) == Some(StatusCode::NotModified) {
+        Ok(Fetched::Unchanged)
+    } else if matches!(resp.code, 404 | 410) {
+        eprintln!("[indexing] gone status={} url={}", resp.code, attempt.url);
+        delete_variants(url, &attempt.url).map(|_| Fetched::Gone)
+    } else {
+        let hash = Sha256::digest(&resp.body).to_vec();
+        // A page that turned noindex through its headers alone still has to be removed.
+        let header_noindex = RobotsDirectives::for_page(
+            resp.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+            None,
+            &user_agent(),
+        )
+        .noindex;
+        let unchanged = (200..300).contains(&resp.code)
+            && !header_noindex
+            && validators.content_hash.as_deref() == Some(hash.as_slice());
+        attempt.content_length = Some(resp.body.len() as u64);
+        attempt.content_hash = Some(hash);
+        if unchanged {
+            Ok(Fetched::Unchanged)
+        } else {
+            index_response(url, &attempt.url, &resp, recrawl, render_budget)
+                .await
+                .map(Fetched::Indexed)
+        }
+    };
+    attempt.headers = resp.headers;
+    (attempt, result)
+}
+
+fn on_domain(url: &str, domain: &str) -> bool {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.eq_ignore_ascii_case(domain)))
+        .unwrap_or(false)
+}
+
+/// Index the response of `url`, the end of the redirect chain that started at
+/// `requested`.
+async fn index_response(
+    requested: &str,
+    url: &str,
+    resp: &ClientResponse,
+    recrawl: &DynamicReCrawlQueue,
+    render_budget: Duration,
+) -> Result<PageLinks> {
     if !(200..300).contains(&resp.code) {
         eprintln!(
             "[indexing] fetch status={} url={} headers={:?}",
@@ -34,7 +268,7 @@ pub async fn index_single_url(url: &str, recrawl: &DynamicReCrawlQueue) -> Resul
     let content_type = header_value(&resp.headers, "content-type");
     if let Some(ct) = content_type {
         if !ct.to_ascii_lowercase().contains("text/html") {
-            return Ok(());
+            return Ok(PageLinks::default());
         }
     }
     let body = String::from_utf8(resp.body.clone())
@@ -44,302 +278,95 @@ pub async fn index_single_url(url: &str, recrawl: &DynamicReCrawlQueue) -> Resul
     if domain.is_empty() {
         return Err(anyhow!("missing host"));
     }
-    let title = extract_title(&body).unwrap_or_else(|| domain.to_string());
-    let fetch_time = current_unix_timestamp();
+    let directives = RobotsDirectives::for_page(
+        resp.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        Some(&body),
+        &user_agent(),
+    );
+    // A same-domain rel=canonical collapses this variant into the canonical document.
+    let mut links = extract_page_links(url, &body);
+    if directives.nofollow {
+        links.links.clear();
+        links.anchors.clear();
+    }
+    if directives.noindex {
+        eprintln!("[indexing] noindex url={}", url);
+        delete_variants(requested, url)?;
+        return Ok(links);
+    }
+    let final_url = canonicalize_url(url).unwrap_or_else(|| url.to_string());
     let engine = services::index_engine();
+    let doc_url = links
+        .canonical
+        .as_deref()
+        .filter(|c| {
+            url::Url::parse(c)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_owned))
+                .as_deref()
+                == Some(domain)
+        })
+        .unwrap_or(&final_url);
+    let fetch_time = current_unix_timestamp();
+    let variants = std::iter::once(requested).chain((url != requested).then_some(url));
+    for variant in variants {
+        if variant != doc_url {
+            debug_log(|| format!("[indexing] canonical url={} -> {}", variant, doc_url));
+            engine.delete_url(variant)?;
+        }
+    }
     process_fetched_document(
         engine,
         recrawl,
-        url,
+        doc_url,
+        domain,
         domain,
-        &title,
         &body,
         "en",
         fetch_time,
-        super::RENDER_BUDGET,
+        render_budget,
     )
     .await?;
-    Ok(())
-}
-
-pub async fn fetch_gurt(url: &str) -> Result<ClientResponse> {
-    let parsed = url::Url::parse(url)?;
-    let host = parsed
-        .host_str()
-        .ok_or_else(|| anyhow!("missing host"))?
-        .to_string();
-    let port = parsed.port().unwrap_or(DEFAULT_PORT);
-    let path = format_request_path(&parsed);
-
-    let connect_timeout = tokio::time::Duration::from_millis(
-        std::env::var("GURT_CONNECT_TIMEOUT_MS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .map(|v| v.clamp(500, 60_000))
-            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS),
-    );
-    let handshake_timeout = tokio::time::Duration::from_millis(
-        std::env::var("GURT_HANDSHAKE_TIMEOUT_MS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .map(|v| v.clamp(200, 30_000))
-            .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_MS),
-    );
-    let fetch_timeout = tokio::time::Duration::from_millis(
-        std::env::var("GURT_FETCH_TIMEOUT_MS")
-            .ok()
-            .and_then(|s| s.parse::<u64>().ok())
-            .map(|v| v.clamp(1_000, 120_000))
-            .unwrap_or(DEFAULT_FETCH_TIMEOUT_MS),
-    );
-
-    let fut = async move {
-        // direct IP > GURT DNS > OS DNS (fallback)
-        debug_log(|| format!("[indexing] resolve host={}", host));
-        let connect_target: ConnectTarget = if let Ok(ip) = host.parse::<IpAddr>() {
-            ConnectTarget::Ip(ip)
-        } else if host.eq_ignore_ascii_case("localhost") {
-            ConnectTarget::Host(host.clone())
-        } else if let Some(ip) = resolve_via_gurt_dns(&host).await {
-            ConnectTarget::Ip(ip)
-        } else {
-            ConnectTarget::Host(host.clone())
-        };
-
-        debug_log(|| match &connect_target {
-            ConnectTarget::Ip(ip) => format!("[indexing] connect target ip={} port={}", ip, port),
-            ConnectTarget::Host(h) => format!("[indexing] connect target host={} port={}", h, port),
-        });
-        let mut tcp = match connect_target {
-            ConnectTarget::Ip(ip) => {
-                tokio::time::timeout(connect_timeout, tokio::net::TcpStream::connect((ip, port)))
-                    .await
-                    .map_err(|_| anyhow!("connect timeout"))?
-                    .with_context(|| format!("connect to {}:{}", ip, port))?
-            }
-            ConnectTarget::Host(h) => tokio::time::timeout(
-                connect_timeout,
-                tokio::net::TcpStream::connect((h.as_str(), port)),
-            )
-            .await
-            .map_err(|_| anyhow!("connect timeout"))?
-            .with_context(|| format!("connect to {}:{}", h, port))?,
-        };
-        tcp.set_nodelay(true).ok();
-        debug_log(|| format!("[indexing] handshake start host={}", host));
-        tokio::time::timeout(handshake_timeout, perform_handshake(&mut tcp, &host))
-            .await
-            .map_err(|_| anyhow!("handshake timeout"))??;
-
-        let connector = tls_connector();
-        let server_name = server_name_from_host(&host)?;
-        debug_log(|| "[indexing] tls connect".to_string());
-        let mut tls = tokio::time::timeout(handshake_timeout, connector.connect(server_name, tcp))
-            .await
-            .map_err(|_| anyhow!("tls connect timeout"))??;
-
-        debug_log(|| format!("[indexing] send request path={}", path));
-        send_request(&mut tls, &host, port, &path).await?;
-        let resp = read_response(&mut tls).await?;
-        Ok(resp)
-    };
-
-    tokio::time::timeout(fetch_timeout, fut)
-        .await
-        .unwrap_or_else(|_| Err(anyhow!("fetch timeout")))
+    Ok(links)
 }
 
-fn format_request_path(url: &url::Url) -> String {
-    let mut path = url.path().to_string();
-    if path.is_empty() {
-        path.push('/');
-    }
-    if let Some(query) = url.query() {
-        path.push('?');
-        path.push_str(query);
-    }
-    path
-}
-
-pub(super) async fn perform_handshake(
-    stream: &mut tokio::net::TcpStream,
-    host: &str,
-) -> Result<()> {
-    let ua = std::env::var("GURT_USER_AGENT").unwrap_or_else(|_| "gurtd/0.1".to_string());
-    let request = format!(
-        "HANDSHAKE / GURT/1.0.0\r\nhost: {}\r\nuser-agent: {}\r\n\r\n",
-        host, ua
-    );
-    stream.write_all(request.as_bytes()).await?;
-    stream.flush().await?;
-
-    let mut buf = Vec::with_capacity(512);
-    let mut tmp = [0u8; 256];
-    loop {
-        let n = stream.read(&mut tmp).await?;
-        if n == 0 {
-            return Err(anyhow!("handshake closed"));
-        }
-        buf.extend_from_slice(&tmp[..n]);
-        if buf.len() > MAX_MESSAGE_BYTES {
-            return Err(anyhow!("handshake too large"));
-        }
-        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
-            break;
-        }
-    }
-
-    let text = String::from_utf8_lossy(&buf);
-    let first_line = text.lines().next().unwrap_or("");
-    let mut parts = first_line.split_whitespace();
-    let version = parts.next().unwrap_or("");
-    let code = parts.next().unwrap_or("");
-    if version != "GURT/1.0.0" || code != "101" {
-        return Err(anyhow!("unexpected handshake response: {}", first_line));
+/// Remove the document of `url`, the end of the redirect chain that started at
+/// `requested`, under every URL it may be indexed as.
+fn delete_variants(requested: &str, url: &str) -> Result<()> {
+    let final_url = canonicalize_url(url).unwrap_or_else(|| url.to_string());
+    let engine = services::index_engine();
+    for variant in [requested, url, final_url.as_str()] {
+        engine.delete_url(variant)?;
     }
     Ok(())
 }
 
-async fn send_request(
-    stream: &mut TlsStream<tokio::net::TcpStream>,
-    host: &str,
-    port: u16,
-    path: &str,
-) -> Result<()> {
-    let host_header = if port != DEFAULT_PORT {
-        format!("{}:{}", host, port)
-    } else {
-        host.to_string()
-    };
-    let req = format!(
-        "GET {} GURT/1.0.0\r\nhost: {}\r\nuser-agent: gurtd/0.1\r\naccept: text/html, */*\r\nconnection: close\r\n\r\n",
-        path, host_header
-    );
-    stream.write_all(req.as_bytes()).await?;
-    stream.flush().await?;
-    Ok(())
+pub async fn fetch_gurt(url: &str) -> Result<ClientResponse> {
+    fetch_gurt_with(url, &[]).await
 }
 
-pub(super) async fn read_response(
-    stream: &mut TlsStream<tokio::net::TcpStream>,
-) -> Result<ClientResponse> {
-    let read_idle_ms: u64 = std::env::var("GURT_READ_IDLE_MS")
-        .ok()
-        .and_then(|s| s.parse::<u64>().ok())
-        .map(|v| v.clamp(MIN_READ_IDLE_MS, MAX_READ_IDLE_MS))
-        .unwrap_or(DEFAULT_READ_IDLE_MS);
-    let read_idle_timeout = tokio::time::Duration::from_millis(read_idle_ms);
-    let mut buf = Vec::with_capacity(4096);
-    let mut tmp = [0u8; 2048];
-    let mut header_end: Option<usize> = None;
-    while header_end.is_none() {
-        let n = stream.read(&mut tmp).await?;
-        if n == 0 {
-            return Err(anyhow!("response closed"));
-        }
-        buf.extend_from_slice(&tmp[..n]);
-        if buf.len() > MAX_MESSAGE_BYTES {
-            return Err(anyhow!("response too large"));
-        }
-        if let Some(pos) = find_crlfcrlf(&buf) {
-            header_end = Some(pos);
-        }
-    }
-    let header_end = header_end.unwrap();
-    let (head, rest) = buf.split_at(header_end + 4);
-    let head_str = std::str::from_utf8(head)?;
-    let mut lines = head_str.split("\r\n");
-    let status_line = lines.next().unwrap_or("");
-    let mut sp = status_line.split_whitespace();
-    let _version = sp.next().unwrap_or("");
-    let code = sp
-        .next()
-        .ok_or_else(|| anyhow!("missing status code"))?
-        .parse::<u16>()?;
-    debug_log(|| format!("[indexing] recv status={}", code));
-
-    let mut headers: Vec<(String, String)> = Vec::new();
-    let mut content_length: Option<usize> = None;
-    for line in lines {
-        if line.is_empty() {
-            continue;
-        }
-        if let Some((name, value)) = line.split_once(':') {
-            let lname = name.trim().to_ascii_lowercase();
-            let val = value.trim().to_string();
-            if lname == "content-length" {
-                if let Ok(n) = val.parse::<usize>() {
-                    content_length = Some(n);
-                }
-            }
-            headers.push((lname, val));
-        }
+/// [`fetch_gurt`] with extra request headers.
+async fn fetch_gurt_with(url: &str, extra_headers: &[(&str, String)]) -> Result<ClientResponse> {
+    let mut req = ClientRequest::get(url).header("accept", "text/html, */*");
+    for (name, value) in extra_headers {
+        req = req.header(*name, value.clone());
     }
-    debug_log(|| {
-        format!(
-            "[indexing] recv headers content-length={:?}",
-            content_length
-        )
-    });
-
-    let mut body = rest.to_vec();
-    if let Some(len) = content_length {
-        enforce_max_message_size(header_end + 4 + len)?;
-        while body.len() < len {
-            let n = stream.read(&mut tmp).await?;
-            if n == 0 {
-                break;
-            }
-            body.extend_from_slice(&tmp[..n]);
-            enforce_max_message_size(header_end + 4 + body.len())?;
-        }
-        if body.len() > len {
-            body.truncate(len);
-        }
-        if body.len() < len {
-            debug_log(|| {
-                format!(
-                    "[indexing] body truncated: expected={} got={}",
-                    len,
-                    body.len()
-                )
-            });
-        } else {
-            debug_log(|| format!("[indexing] body length={} (content-length)", body.len()));
-        }
-    } else {
-        loop {
-            match tokio::time::timeout(read_idle_timeout, stream.read(&mut tmp)).await {
-                Ok(Ok(n)) => {
-                    if n == 0 {
-                        break;
-                    }
-                    body.extend_from_slice(&tmp[..n]);
-                    enforce_max_message_size(header_end + 4 + body.len())?;
-                }
-                Ok(Err(_)) => break,
-                Err(_) => {
-                    break;
-                }
-            }
-        }
-        debug_log(|| format!("[indexing] body length={} (read-until-idle)", body.len()));
-    }
-
+    let resp = client().send(&req).await?;
+    debug_log(|| format!("[indexing] recv status={} url={}", resp.code, url));
     if std::env::var("GURT_DEBUG_BODY")
         .ok()
         .filter(|v| v != "0")
         .is_some()
     {
-        let preview_len = body.len().min(2048);
-        let preview = String::from_utf8_lossy(&body[..preview_len]);
+        let preview_len = resp.body.len().min(2048);
+        let preview = String::from_utf8_lossy(&resp.body[..preview_len]);
         let sanitized = preview.replace('\n', "\\n").replace('\r', "");
         debug_log(|| {
             format!(
                 "[indexing] body preview ({} bytes): {}{}",
                 preview_len,
                 &sanitized,
-                if body.len() > preview_len {
+                if resp.body.len() > preview_len {
                     " ...<truncated>"
                 } else {
                     ""
@@ -347,19 +374,49 @@ pub(super) async fn read_response(
             )
         });
     }
+    Ok(resp)
+}
 
-    Ok(ClientResponse {
-        code,
-        headers,
-        body,
-    })
+/// Client for crawler fetches: names resolve through GURT DNS, streams are upgraded to
+/// TLS and kept alive between requests.
+/// - GURT_CONNECT_TIMEOUT_MS (default 10000)
+/// - GURT_HANDSHAKE_TIMEOUT_MS (default 5000): HANDSHAKE plus TLS
+/// - GURT_FETCH_TIMEOUT_MS (default 30000): request and response
+/// - GURT_READ_IDLE_MS (default 500): end of a body without content-length
+pub(super) fn client() -> &'static GurtClient {
+    static CLIENT: Lazy<GurtClient> = Lazy::new(|| {
+        let mut client =
+            GurtClient::new_with_connector(tcp_connector(Some(super::dns::resolver())))
+                .with_upgrade(upgrade_from_env())
+                .with_keepalive(Arc::new(ConnPool::from_env()));
+        client.user_agent = Some(user_agent());
+        client.req_timeout =
+            env_ms("GURT_CONNECT_TIMEOUT_MS", 500..=60_000).unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+        client.handshake_timeout =
+            env_ms("GURT_HANDSHAKE_TIMEOUT_MS", 200..=30_000).unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT);
+        client.fetch_timeout =
+            env_ms("GURT_FETCH_TIMEOUT_MS", 1_000..=120_000).unwrap_or(DEFAULT_FETCH_TIMEOUT);
+        client.read_idle = env_ms("GURT_READ_IDLE_MS", MIN_READ_IDLE_MS..=MAX_READ_IDLE_MS)
+            .unwrap_or(DEFAULT_READ_IDLE);
+        client.redirects = RedirectPolicy::from_env();
+        client
+    });
+    &CLIENT
+}
+
+fn env_ms(key: &str, range: std::ops::RangeInclusive<u64>) -> Option<Duration> {
+    let ms = std::env::var(key).ok()?.trim().parse::<u64>().ok()?;
+    Some(Duration::from_millis(
+        ms.clamp(*range.start(), *range.end()),
+    ))
 }
 
-fn find_crlfcrlf(buf: &[u8]) -> Option<usize> {
-    buf.windows(4).position(|w| w == b"\r\n\r\n")
+/// User-agent sent with every request; also the token matched against robots.txt groups.
+pub(super) fn user_agent() -> String {
+    std::env::var("GURT_USER_AGENT").unwrap_or_else(|_| "gurtd/0.1".to_string())
 }
 
-fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+pub(super) fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
     headers.iter().find_map(|(k, v)| {
         if k.eq_ignore_ascii_case(name) {
             Some(v.as_str())
@@ -369,25 +426,6 @@ fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a s
     })
 }
 
-fn extract_title(html: &str) -> Option<String> {
-    let lower = html.to_ascii_lowercase();
-    let start = lower.find("<title")?;
-    let after_tag = &lower[start..];
-    let gt = after_tag.find('>')?;
-    let content_start = start + gt + 1;
-    let after_start = &lower[content_start..];
-    let end_rel = after_start.find("</title>")?;
-    let content_end = content_start + end_rel;
-    let slice = html.get(content_start..content_end)?;
-    let collapsed = slice.split_whitespace().collect::<Vec<_>>().join(" ");
-    let trimmed = collapsed.trim();
-    if trimmed.is_empty() {
-        None
-    } else {
-        Some(trimmed.to_string())
-    }
-}
-
 fn current_unix_timestamp() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -395,69 +433,6 @@ fn current_unix_timestamp() -> i64 {
         .unwrap_or(0)
 }
 
-pub(super) fn tls_connector() -> tokio_rustls::TlsConnector {
-    static CONNECTOR: Lazy<tokio_rustls::TlsConnector> = Lazy::new(|| {
-        use rustls::ClientConfig;
-        use std::sync::Arc;
-        let mut cfg = ClientConfig::builder()
-            .dangerous()
-            .with_custom_certificate_verifier(Arc::new(NoVerifier))
-            .with_no_client_auth();
-        cfg.alpn_protocols = vec![b"GURT/1.0".to_vec()];
-        tokio_rustls::TlsConnector::from(Arc::new(cfg))
-    });
-    CONNECTOR.clone()
-}
-
-#[derive(Debug)]
-struct NoVerifier;
-
-impl rustls::client::danger::ServerCertVerifier for NoVerifier {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
-
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls::pki_types::CertificateDer<'_>,
-        _dss: &DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
-        vec![
-            SignatureScheme::ECDSA_NISTP256_SHA256,
-            SignatureScheme::ECDSA_NISTP384_SHA384,
-            SignatureScheme::ED25519,
-            SignatureScheme::RSA_PSS_SHA256,
-            SignatureScheme::RSA_PKCS1_SHA256,
-        ]
-    }
-}
-
-enum ConnectTarget {
-    Ip(IpAddr),
-    Host(String),
-}
-
 fn debug_log<F>(f: F)
 where
     F: FnOnce() -> String,
@@ -472,3 +447,63 @@ where
         eprintln!("{}", f());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_json_joins_repeated_headers() {
+        let attempt = FetchAttempt {
+            status: Some(200),
+            headers: vec![
+                ("content-type".into(), "text/html".into()),
+                ("vary".into(), "accept".into()),
+                ("vary".into(), "user-agent".into()),
+            ],
+            ..FetchAttempt::default()
+        };
+        assert_eq!(
+            attempt.headers_json(),
+            Some(serde_json::json!({
+                "content-type": "text/html",
+                "vary": "accept, user-agent",
+            }))
+        );
+        assert_eq!(attempt.header("Content-Type"), Some("text/html"));
+        assert!(FetchAttempt::default().headers_json().is_none());
+    }
+
+    #[test]
+    fn validators_round_trip_last_modified() {
+        let attempt = FetchAttempt {
+            status: Some(200),
+            headers: vec![
+                ("etag".into(), "\"v1\"".into()),
+                (
+                    "last-modified".into(),
+                    "Wed, 21 Oct 2015 07:28:00 GMT".into(),
+                ),
+            ],
+            ..FetchAttempt::default()
+        };
+        let secs = attempt.last_modified().unwrap();
+        assert_eq!(secs, 1_445_412_480);
+        let validators = Validators {
+            etag: attempt.header("etag").map(str::to_owned),
+            last_modified: Some(std::time::UNIX_EPOCH + Duration::from_secs(secs as u64)),
+            content_hash: None,
+        };
+        assert_eq!(
+            validators.request_headers(),
+            vec![
+                ("if-none-match", "\"v1\"".to_string()),
+                (
+                    "if-modified-since",
+                    "Wed, 21 Oct 2015 07:28:00 GMT".to_string()
+                ),
+            ]
+        );
+        assert!(Validators::default().request_headers().is_empty());
+    }
+}
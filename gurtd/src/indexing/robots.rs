@@ -0,0 +1,199 @@
+//! robots.txt enforcement for a single domain crawl.
+//!
+//! robots.txt is fetched once at the start of each crawl. A missing file (or one that
+//! cannot be fetched) allows everything, matching `crawler::robots::is_allowed_with_robots`.
+
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::crawler::client::ClientResponse;
+use crate::crawler::robots::{is_allowed_with_robots, RobotsTxt};
+use crate::services;
+
+use super::fetch;
+
+/// Upper bound on an honored crawl-delay; larger values would stall the indexer.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(30);
+
+/// Reason stored in `urls.last_fetch_error` for URLs dropped by robots.txt.
+pub(super) const BLOCKED_REASON: &str = "disallowed by robots.txt";
+
+/// robots.txt outcome for one domain crawl.
+pub(super) struct DomainRobots {
+    domain: String,
+    user_agent: String,
+    rules: Option<RobotsTxt>,
+    /// Status code of the robots.txt response, None when the fetch failed.
+    status: Option<u16>,
+    etag: Option<String>,
+    checksum: Option<Vec<u8>>,
+}
+
+impl DomainRobots {
+    /// Fetch and parse `gurt://<domain>/robots.txt`.
+    pub(super) async fn fetch(domain: &str) -> Self {
+        let url = format!("gurt://{}/robots.txt", domain);
+        let resp = match fetch::fetch_gurt(&url).await {
+            Ok(resp) => Some(resp),
+            Err(err) => {
+                eprintln!("[indexing] robots domain={} error={:?}", domain, err);
+                None
+            }
+        };
+        Self::from_response(domain, &fetch::user_agent(), resp.as_ref())
+    }
+
+    fn from_response(domain: &str, user_agent: &str, resp: Option<&ClientResponse>) -> Self {
+        let mut robots = Self {
+            domain: domain.to_ascii_lowercase(),
+            user_agent: user_agent.to_string(),
+            rules: None,
+            status: resp.map(|r| r.code),
+            etag: None,
+            checksum: None,
+        };
+        if let Some(resp) = resp.filter(|r| (200..300).contains(&r.code)) {
+            let body = String::from_utf8_lossy(&resp.body);
+            robots.rules = Some(RobotsTxt::parse(&body));
+            robots.etag = fetch::header_value(&resp.headers, "etag").map(str::to_owned);
+            robots.checksum = Some(Sha256::digest(&resp.body).to_vec());
+        }
+        robots
+    }
+
+    /// Whether `url` may be fetched. URLs on other hosts are not ours to judge.
+    pub(super) fn allows(&self, url: &str) -> bool {
+        let Ok(parsed) = url::Url::parse(url) else {
+            return true;
+        };
+        if !parsed
+            .host_str()
+            .is_some_and(|h| h.eq_ignore_ascii_case(&self.domain))
+        {
+            return true;
+        }
+        let mut path = parsed.path().to_string();
+        if let Some(q) = parsed.query() {
+            path.push('?');
+            path.push_str(q);
+        }
+        is_allowed_with_robots(self.rules.as_ref(), &self.user_agent, &path)
+    }
+
+    /// Whether robots.txt shuts our user-agent out of the whole domain.
+    pub(super) fn disallows_all(&self) -> bool {
+        self.rules
+            .as_ref()
+            .is_some_and(|r| r.disallows_all(&self.user_agent))
+    }
+
+    /// Crawl-delay for our user-agent, capped at [`MAX_CRAWL_DELAY`].
+    pub(super) fn crawl_delay(&self) -> Option<Duration> {
+        self.rules
+            .as_ref()
+            .and_then(|r| r.crawl_delay(&self.user_agent))
+            .map(|d| d.min(MAX_CRAWL_DELAY))
+    }
+
+    /// Sitemap URLs announced by robots.txt.
+    pub(super) fn sitemaps(&self) -> &[String] {
+        self.rules
+            .as_ref()
+            .map(|r| r.sitemaps())
+            .unwrap_or_default()
+    }
+
+    /// Store the fetch outcome in the `domains.robots_*` columns.
+    pub(super) async fn record(&self) {
+        let Some(pool) = services::try_db() else {
+            return;
+        };
+        let delay_ms = self
+            .crawl_delay()
+            .map(|d| d.as_millis().min(i32::MAX as u128) as i32);
+        if let Err(err) = crate::storage::domains::record_robots(
+            pool,
+            &self.domain,
+            self.status.map(|s| s as i16),
+            self.checksum.as_deref(),
+            delay_ms,
+            self.etag.as_deref(),
+        )
+        .await
+        {
+            eprintln!(
+                "[indexing] robots record domain={} error={:?}",
+                self.domain, err
+            );
+        }
+    }
+}
+
+/// Flag (or clear) `urls.robots_blocked` for `url`.
+pub(super) async fn mark_blocked(domain: &str, url: &str, blocked: bool) {
+    let Some(pool) = services::try_db() else {
+        return;
+    };
+    let reason = blocked.then_some(BLOCKED_REASON);
+    if let Err(err) =
+        crate::storage::urls::set_robots_blocked(pool, domain, url, blocked, reason).await
+    {
+        eprintln!("[indexing] robots mark url={} error={:?}", url, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(code: u16, body: &str) -> ClientResponse {
+        ClientResponse {
+            code,
+            headers: vec![("etag".into(), "\"v1\"".into())],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn disallowed_paths_and_delay() {
+        let resp = response(
+            200,
+            "User-agent: *\nDisallow: /private\nAllow: /private/open\nCrawl-delay: 120\n\nUser-agent: gurtd\nDisallow: /gurtd-only\n",
+        );
+        let robots = DomainRobots::from_response("Example.real", "gurtd/0.1", Some(&resp));
+        assert!(robots.allows("gurt://example.real/"));
+        assert!(robots.allows("gurt://example.real/private/open/a"));
+        assert!(!robots.allows("gurt://example.real/gurtd-only?x=1"));
+        assert!(robots.allows("gurt://other.real/gurtd-only"));
+        assert_eq!(robots.etag.as_deref(), Some("\"v1\""));
+        assert!(robots.checksum.is_some());
+        assert!(robots.sitemaps().is_empty());
+        assert!(!robots.disallows_all());
+
+        let star = DomainRobots::from_response(
+            "example.real",
+            "otherbot",
+            Some(&response(200, "User-agent: *\nCrawl-delay: 120\n")),
+        );
+        assert_eq!(star.crawl_delay(), Some(MAX_CRAWL_DELAY));
+    }
+
+    #[test]
+    fn missing_or_failed_robots_allows_everything() {
+        let missing = DomainRobots::from_response(
+            "example.real",
+            "gurtd/0.1",
+            Some(&response(404, "Disallow: /")),
+        );
+        assert!(missing.allows("gurt://example.real/anything"));
+        assert_eq!(missing.status, Some(404));
+        assert!(missing.checksum.is_none());
+        assert!(!missing.disallows_all());
+
+        let failed = DomainRobots::from_response("example.real", "gurtd/0.1", None);
+        assert!(failed.allows("gurt://example.real/anything"));
+        assert_eq!(failed.crawl_delay(), None);
+        assert!(failed.sitemaps().is_empty());
+    }
+}
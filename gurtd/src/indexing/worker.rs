@@ -0,0 +1,1022 @@
+//! Queue workers: seed domain crawls into Postgres and process leased URL jobs.
+//!
+//! A pool of fetch workers leases URLs concurrently. Every request, sitemaps' and page
+//! scripts' included, goes through [`HostScheduler::acquire_polite`], which bounds global
+//! and per-host concurrency and spaces requests by crawl-delay; domains already at their
+//! limit are skipped when leasing, so a slow host only ties up its own share of the
+//! workers.
+//!
+//! Index writes are committed in batches and a job is acked only after the commit that
+//! contains its document, so a crash or restart re-leases exactly the URLs whose results
+//! were not yet durable. The response's validators are stored at the same point, so the
+//! conditional refetch of a re-leased URL is not answered as unchanged.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gurt_db::PgPool;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::OnceCell;
+
+use crate::crawler::client::ClientResponse;
+use crate::crawler::frontier::{Frontier, FrontierConfig};
+use crate::crawler::pipeline::DynamicReCrawlQueue;
+use crate::crawler::recrawl::{observations, RecrawlPolicy};
+use crate::crawler::redirect::RedirectPolicy;
+use crate::crawler::render::{self, DynamicReason};
+use crate::crawler::scheduler::HostScheduler;
+use crate::link::PageLinks;
+use crate::services;
+use crate::storage::fetches::{self, FetchRecord};
+use crate::storage::links;
+use crate::storage::queue::{self, LeasedUrl, NackOutcome};
+use crate::storage::recrawl;
+
+use super::fetch::{self, FetchAttempt, Fetched, Politeness, Validators};
+use super::robots::{self, DomainRobots};
+use super::{IndexJob, RECRAWL_QUEUE};
+
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_PER_HOST_CONCURRENCY: usize = 2;
+const DEFAULT_LOCK_STALE_SECS: i64 = 300;
+const DEFAULT_POLL_MS: u64 = 1_000;
+const DEFAULT_COMMIT_EVERY: usize = 16;
+const DEFAULT_RETRY_BACKOFF_SECS: i64 = 30;
+const DEFAULT_RERENDER_BUDGET_MS: u64 = 2_000;
+const DEFAULT_RERENDER_BACKOFF_SECS: i64 = 300;
+const DEFAULT_RERENDER_MAX_ATTEMPTS: i32 = 3;
+/// How often the deferred re-render pass looks for due pages, and how many it takes.
+const RERENDER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const RERENDER_BATCH: i64 = 8;
+/// How often due recrawls are moved into crawl_queue, and how many at most.
+const RECRAWL_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const RECRAWL_BATCH: i64 = 256;
+/// Successful fetches the change-rate estimate looks at.
+const RECRAWL_HISTORY: i64 = 10;
+/// How often leases abandoned by other (dead) workers are released.
+const STALE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+struct WorkerConfig {
+    worker_id: String,
+    concurrency: usize,
+    per_host: usize,
+    lock_stale_secs: i64,
+    poll_interval: Duration,
+    commit_every: usize,
+    retry_backoff_secs: i64,
+    rerender_budget: Duration,
+    rerender_backoff_secs: i64,
+    rerender_max_attempts: i32,
+    frontier: FrontierConfig,
+    recrawl: RecrawlPolicy,
+    redirects: RedirectPolicy,
+}
+
+impl WorkerConfig {
+    /// - GURT_WORKER_ID (default `gurtd-$HOSTNAME`; must be unique per process)
+    /// - GURT_INDEX_CONCURRENCY (default 8): fetch workers, i.e. global fetch concurrency
+    /// - GURT_INDEX_PER_HOST_CONCURRENCY (default 2; hosts with a crawl-delay get 1)
+    /// - GURT_QUEUE_LOCK_STALE_SECS (default 300)
+    /// - GURT_QUEUE_POLL_MS (default 1000)
+    /// - GURT_INDEX_COMMIT_EVERY (default 16)
+    /// - GURT_QUEUE_RETRY_BACKOFF_SECS (default 30, doubled per failed attempt)
+    /// - GURT_RERENDER_BUDGET_MS (default 2000): render budget of the deferred pass
+    /// - GURT_RERENDER_BACKOFF_SECS (default 300): delay before the first re-render,
+    ///   doubled per failed attempt
+    /// - GURT_RERENDER_MAX_ATTEMPTS (default 3)
+    fn from_env() -> Self {
+        let worker_id = std::env::var("GURT_WORKER_ID")
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| {
+                let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "local".to_string());
+                format!("gurtd-{}", host)
+            });
+        Self {
+            worker_id,
+            concurrency: env_parse("GURT_INDEX_CONCURRENCY")
+                .map(|v: usize| v.clamp(1, 256))
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            per_host: env_parse("GURT_INDEX_PER_HOST_CONCURRENCY")
+                .map(|v: usize| v.clamp(1, 64))
+                .unwrap_or(DEFAULT_PER_HOST_CONCURRENCY),
+            lock_stale_secs: env_parse("GURT_QUEUE_LOCK_STALE_SECS")
+                .unwrap_or(DEFAULT_LOCK_STALE_SECS),
+            poll_interval: Duration::from_millis(
+                env_parse("GURT_QUEUE_POLL_MS")
+                    .map(|v: u64| v.clamp(50, 60_000))
+                    .unwrap_or(DEFAULT_POLL_MS),
+            ),
+            commit_every: env_parse("GURT_INDEX_COMMIT_EVERY")
+                .map(|v: usize| v.max(1))
+                .unwrap_or(DEFAULT_COMMIT_EVERY),
+            retry_backoff_secs: env_parse("GURT_QUEUE_RETRY_BACKOFF_SECS")
+                .unwrap_or(DEFAULT_RETRY_BACKOFF_SECS),
+            rerender_budget: Duration::from_millis(
+                env_parse("GURT_RERENDER_BUDGET_MS").unwrap_or(DEFAULT_RERENDER_BUDGET_MS),
+            ),
+            rerender_backoff_secs: env_parse("GURT_RERENDER_BACKOFF_SECS")
+                .unwrap_or(DEFAULT_RERENDER_BACKOFF_SECS),
+            rerender_max_attempts: env_parse("GURT_RERENDER_MAX_ATTEMPTS")
+                .map(|v: i32| v.max(1))
+                .unwrap_or(DEFAULT_RERENDER_MAX_ATTEMPTS),
+            frontier: FrontierConfig::from_env(),
+            recrawl: RecrawlPolicy::from_env(),
+            redirects: RedirectPolicy::from_env(),
+        }
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok().and_then(|s| s.trim().parse().ok())
+}
+
+pub(super) async fn run(
+    mut rx: UnboundedReceiver<IndexJob>,
+    in_flight: Arc<Mutex<HashSet<String>>>,
+) {
+    let Some(pool) = services::try_db() else {
+        eprintln!("[indexing] database unavailable; indexing worker not started");
+        return;
+    };
+    tokio::spawn(super::authority::run(pool.clone()));
+    let indexer = Arc::new(Indexer::new(pool.clone(), WorkerConfig::from_env()));
+    let scripts = indexer.clone();
+    render::set_script_fetcher(Arc::new(move |url: String| {
+        let indexer = scripts.clone();
+        Box::pin(async move { indexer.script_fetch(&url).await })
+    }));
+    indexer.recover().await;
+    eprintln!(
+        "[indexing] starting workers={} per_host={} worker_id={}",
+        indexer.cfg.concurrency, indexer.cfg.per_host, indexer.cfg.worker_id
+    );
+    for n in 0..indexer.cfg.concurrency {
+        let indexer = indexer.clone();
+        let worker_id = format!("{}/{}", indexer.cfg.worker_id, n);
+        tokio::spawn(async move { indexer.fetch_loop(worker_id).await });
+    }
+    let rerender = indexer.clone();
+    tokio::spawn(async move { rerender.rerender_loop().await });
+
+    // Seeding runs here, off the fetch workers, so slow robots/sitemap fetches of a new
+    // domain never stall the queue.
+    let mut last_sweep = Instant::now();
+    let mut last_recrawl = Instant::now();
+    loop {
+        match tokio::time::timeout(indexer.cfg.poll_interval, rx.recv()).await {
+            Ok(Some(job)) => indexer.seed(&job.domain, &in_flight).await,
+            Ok(None) => break,
+            Err(_) => {}
+        }
+        if last_sweep.elapsed() >= STALE_SWEEP_INTERVAL {
+            indexer.clear_stale().await;
+            last_sweep = Instant::now();
+        }
+        if last_recrawl.elapsed() >= RECRAWL_SWEEP_INTERVAL {
+            indexer.promote_recrawls().await;
+            last_recrawl = Instant::now();
+        }
+    }
+    indexer.flush().await;
+}
+
+/// Per-domain state kept while a domain has queued work.
+struct DomainState {
+    robots: DomainRobots,
+    blocked: Mutex<HashSet<String>>,
+    fetched: AtomicUsize,
+}
+
+impl DomainState {
+    fn new(robots: DomainRobots) -> Self {
+        Self {
+            robots,
+            blocked: Mutex::new(HashSet::new()),
+            fetched: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// A fetched job waiting for the index commit that covers it.
+struct PendingAck {
+    worker_id: String,
+    job: LeasedUrl,
+    /// The response, whose validators are stored once the commit lands.
+    attempt: FetchAttempt,
+}
+
+struct Indexer {
+    pool: PgPool,
+    cfg: WorkerConfig,
+    scheduler: HostScheduler,
+    domains: Mutex<HashMap<String, Arc<OnceCell<Arc<DomainState>>>>>,
+    /// Jobs being fetched per domain id, with that domain's concurrency limit.
+    active: Mutex<HashMap<i64, (usize, usize)>>,
+    pending_acks: Mutex<Vec<PendingAck>>,
+    /// Domains (id -> name) with finished jobs since the last flush.
+    touched: Mutex<HashMap<i64, String>>,
+    /// Index deletions not yet covered by a commit.
+    uncommitted: AtomicBool,
+    flush_lock: tokio::sync::Mutex<()>,
+}
+
+impl Indexer {
+    fn new(pool: PgPool, cfg: WorkerConfig) -> Self {
+        Self {
+            pool,
+            scheduler: HostScheduler::new(cfg.concurrency, cfg.per_host),
+            cfg,
+            domains: Mutex::new(HashMap::new()),
+            active: Mutex::new(HashMap::new()),
+            pending_acks: Mutex::new(Vec::new()),
+            touched: Mutex::new(HashMap::new()),
+            uncommitted: AtomicBool::new(false),
+            flush_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Release leases left behind by a previous run of this worker, then stale ones.
+    async fn recover(&self) {
+        match queue::release_worker_locks(&self.pool, &self.cfg.worker_id).await {
+            Ok(n) if n > 0 => eprintln!(
+                "[indexing] resumed {} leased urls from previous run of worker={}",
+                n, self.cfg.worker_id
+            ),
+            Ok(_) => {}
+            Err(err) => eprintln!("[indexing] release locks error={:?}", err),
+        }
+        match recrawl::release_worker_locks(&self.pool, &self.cfg.worker_id).await {
+            Ok(n) if n > 0 => eprintln!("[indexing] resumed {} leased re-renders", n),
+            Ok(_) => {}
+            Err(err) => eprintln!("[indexing] release re-render locks error={:?}", err),
+        }
+        self.clear_stale().await;
+        if services::index_rebuilt() {
+            match recrawl::reindex_all(&self.pool).await {
+                Ok(n) => eprintln!("[indexing] index rebuilt; {} recrawls made due", n),
+                Err(err) => eprintln!("[indexing] reindex error={:?}", err),
+            }
+        }
+    }
+
+    async fn clear_stale(&self) {
+        match queue::clear_stale_locks(&self.pool, self.cfg.lock_stale_secs).await {
+            Ok(n) if n > 0 => eprintln!("[indexing] released {} stale leases", n),
+            Ok(_) => {}
+            Err(err) => eprintln!("[indexing] clear stale locks error={:?}", err),
+        }
+        match recrawl::clear_stale_locks(&self.pool, self.cfg.lock_stale_secs).await {
+            Ok(n) if n > 0 => eprintln!("[indexing] released {} stale re-render leases", n),
+            Ok(_) => {}
+            Err(err) => eprintln!("[indexing] clear stale re-render locks error={:?}", err),
+        }
+    }
+
+    async fn fetch_loop(&self, worker_id: String) {
+        loop {
+            let skip = self.saturated_domains();
+            match queue::lease_next(&self.pool, &worker_id, &skip).await {
+                Ok(Some(job)) if self.reserve(&job) => self.process(&worker_id, job).await,
+                Ok(Some(job)) => {
+                    // Another worker took the domain's last slot since `skip` was read.
+                    if let Err(err) = queue::release(&self.pool, job.queue_id, &worker_id).await {
+                        eprintln!("[indexing] worker={} release error={:?}", worker_id, err);
+                    }
+                }
+                Ok(None) => {
+                    self.flush().await;
+                    tokio::time::sleep(self.cfg.poll_interval).await;
+                }
+                Err(err) => {
+                    eprintln!("[indexing] worker={} lease error={:?}", worker_id, err);
+                    tokio::time::sleep(self.cfg.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Start a crawl of `domain`: fetch robots.txt and queue the root and sitemap URLs.
+    /// A domain that still has queued work is resumed as is.
+    async fn seed(&self, domain: &str, in_flight: &Mutex<HashSet<String>>) {
+        if let Err(err) = self.seed_inner(domain).await {
+            eprintln!("[indexing] domain={} error={:?}", domain, err);
+        }
+        in_flight.lock().unwrap().remove(domain);
+    }
+
+    async fn seed_inner(&self, domain: &str) -> anyhow::Result<()> {
+        let domain_id =
+            crate::storage::domains::upsert_domain_submission(&self.pool, domain, None).await?;
+        if queue::domain_has_pending(&self.pool, domain_id).await? {
+            eprintln!("[indexing] resume domain={} (queued urls remain)", domain);
+            return Ok(());
+        }
+        eprintln!("[indexing] enqueue domain={}", domain);
+        let robots = DomainRobots::fetch(domain).await;
+        robots.record().await;
+        if robots.disallows_all() {
+            self.block_domain(domain).await;
+            return Ok(());
+        }
+        let state = Arc::new(DomainState::new(robots));
+
+        let frontier = Frontier::new(domain, self.cfg.frontier.clone());
+        let urls = super::collect_candidate_urls(
+            domain,
+            self.cfg.frontier.max_pages,
+            &self.politeness(domain, &state),
+        )
+        .await;
+        let now = unix_now();
+        let (mut queued, mut current) = (0usize, 0usize);
+        for (raw, hint) in &urls {
+            let Some(url) = frontier.seed(raw) else {
+                continue;
+            };
+            if !state.robots.allows(&url) {
+                self.block(domain, &state, &url).await;
+                continue;
+            }
+            let (url_id, is_queued) = queue::enqueue_seed_url(
+                &self.pool,
+                domain_id,
+                &url,
+                hint.fetch_priority(),
+                hint.lastmod,
+            )
+            .await?;
+            if is_queued {
+                queued += 1;
+            } else {
+                current += 1;
+            }
+            if let Some(interval) = self.cfg.recrawl.initial_interval(hint, now) {
+                recrawl::seed_interval(&self.pool, url_id, domain_id, interval_secs(interval))
+                    .await?;
+            }
+        }
+        crate::storage::domains::set_domain_status(&self.pool, domain, "pending").await?;
+        eprintln!(
+            "[indexing] seeded domain={} urls={} unchanged={}",
+            domain, queued, current
+        );
+        self.domains.lock().unwrap().insert(
+            domain.to_string(),
+            Arc::new(OnceCell::new_with(Some(state))),
+        );
+        self.touched
+            .lock()
+            .unwrap()
+            .insert(domain_id, domain.to_string());
+        Ok(())
+    }
+
+    /// Robots state for `domain`, fetching robots.txt once if this process has not seen
+    /// the domain yet (e.g. after a restart). Concurrent callers share one fetch.
+    async fn domain_state(&self, domain: &str) -> Arc<DomainState> {
+        let cell = self
+            .domains
+            .lock()
+            .unwrap()
+            .entry(domain.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_init(|| async {
+            let robots = DomainRobots::fetch(domain).await;
+            robots.record().await;
+            Arc::new(DomainState::new(robots))
+        })
+        .await
+        .clone()
+    }
+
+    fn politeness<'a>(&'a self, domain: &'a str, state: &'a DomainState) -> Politeness<'a> {
+        Politeness {
+            domain,
+            robots: &state.robots,
+            scheduler: &self.scheduler,
+        }
+    }
+
+    /// Fetch for a page script, under the robots.txt and scheduler of the URL's domain
+    /// like any crawler request.
+    async fn script_fetch(&self, url: &str) -> anyhow::Result<ClientResponse> {
+        let domain = url::Url::parse(url)?
+            .host_str()
+            .map(str::to_ascii_lowercase)
+            .ok_or_else(|| anyhow::anyhow!("{url} has no host"))?;
+        let state = self.domain_state(&domain).await;
+        self.politeness(&domain, &state).fetch_allowed(url).await
+    }
+
+    /// Domains whose in-flight jobs already reach their concurrency limit.
+    fn saturated_domains(&self) -> Vec<i64> {
+        self.active
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, (n, limit))| n >= limit)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Concurrency limit of a domain: one fetch at a time under a crawl-delay.
+    fn domain_limit(&self, state: &DomainState) -> usize {
+        if state.robots.crawl_delay().is_some() {
+            1
+        } else {
+            self.cfg.per_host
+        }
+    }
+
+    /// Take a fetch slot of the leased job's domain, or refuse if it has none left.
+    /// Runs right after the lease, under the lock `saturated_domains` reads, so workers
+    /// leasing at the same time cannot overshoot the limit. A domain whose robots.txt
+    /// is not loaded yet counts as limited to one fetch.
+    fn reserve(&self, job: &LeasedUrl) -> bool {
+        let known = self
+            .domains
+            .lock()
+            .unwrap()
+            .get(&job.domain)
+            .and_then(|cell| cell.get().cloned());
+        let limit = known.map_or(1, |state| self.domain_limit(&state));
+        let mut active = self.active.lock().unwrap();
+        let entry = active.entry(job.domain_id).or_insert((0, limit));
+        entry.1 = limit;
+        if entry.0 >= entry.1 {
+            return false;
+        }
+        entry.0 += 1;
+        true
+    }
+
+    fn set_limit(&self, domain_id: i64, limit: usize) {
+        if let Some(entry) = self.active.lock().unwrap().get_mut(&domain_id) {
+            entry.1 = limit;
+        }
+    }
+
+    fn end(&self, domain_id: i64) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(entry) = active.get_mut(&domain_id) {
+            entry.0 = entry.0.saturating_sub(1);
+            if entry.0 == 0 {
+                active.remove(&domain_id);
+            }
+        }
+    }
+
+    fn touch(&self, job: &LeasedUrl) {
+        self.touched
+            .lock()
+            .unwrap()
+            .insert(job.domain_id, job.domain.clone());
+    }
+
+    async fn process(&self, worker_id: &str, job: LeasedUrl) {
+        // The slot was taken by `reserve` and is given back once the fetch is done.
+        let state = self.domain_state(&job.domain).await;
+        self.set_limit(job.domain_id, self.domain_limit(&state));
+        if !state.robots.allows(&job.url) {
+            // robots.txt changed since the URL was queued.
+            self.end(job.domain_id);
+            self.block(&job.domain, &state, &job.url).await;
+            self.ack(worker_id, &job).await;
+            self.touch(&job);
+            return;
+        }
+        state.fetched.fetch_add(1, Ordering::Relaxed);
+
+        if job.robots_blocked {
+            // Left over from an earlier, stricter robots.txt.
+            robots::mark_blocked(&job.domain, &job.url, false).await;
+        }
+        let validators = Validators {
+            etag: job.etag.clone(),
+            last_modified: job
+                .last_modified
+                .and_then(|t| u64::try_from(t).ok())
+                .map(|t| std::time::UNIX_EPOCH + Duration::from_secs(t)),
+            content_hash: job.content_hash.clone(),
+        };
+        let (attempt, result) = fetch::index_single_url(
+            &job.url,
+            &self.politeness(&job.domain, &state),
+            &validators,
+            self.cfg.redirects,
+            &RECRAWL_QUEUE,
+            super::RENDER_BUDGET,
+        )
+        .await;
+        self.end(job.domain_id);
+        self.record_fetch(worker_id, &job, &attempt, &result).await;
+
+        match result {
+            Ok(Fetched::Unchanged) => {
+                // Nothing to index, so nothing for a commit to cover.
+                eprintln!("[indexing] unchanged url={}", job.url);
+                self.reschedule(&job).await;
+                self.ack(worker_id, &job).await;
+                self.touch(&job);
+            }
+            Ok(Fetched::Redirected(target)) => {
+                eprintln!("[indexing] redirect url={} -> {}", job.url, target);
+                if let Some(host) = url::Url::parse(&target)
+                    .ok()
+                    .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+                {
+                    super::discover_domain(host).await;
+                }
+                self.reschedule(&job).await;
+                self.ack(worker_id, &job).await;
+                self.touch(&job);
+            }
+            Ok(Fetched::Blocked(target)) => {
+                eprintln!("[indexing] redirect url={} -> {}", job.url, target);
+                self.block(&job.domain, &state, &target).await;
+                self.reschedule(&job).await;
+                self.ack(worker_id, &job).await;
+                self.touch(&job);
+            }
+            Ok(Fetched::Gone) => {
+                // Acked once the commit covering the deletion lands, like an indexed page.
+                self.reschedule(&job).await;
+                self.ack_after_commit(worker_id, job, attempt).await;
+            }
+            Ok(Fetched::Indexed(page)) => {
+                self.reschedule(&job).await;
+                self.follow(&job, &state, &page).await;
+                self.ack_after_commit(worker_id, job, attempt).await;
+            }
+            Err(err) => {
+                eprintln!("[indexing] url={} error={:?}", job.url, err);
+                self.nack(worker_id, &job, &format!("{err:#}")).await;
+            }
+        }
+    }
+
+    async fn ack_after_commit(&self, worker_id: &str, job: LeasedUrl, attempt: FetchAttempt) {
+        let due = {
+            let mut pending = self.pending_acks.lock().unwrap();
+            pending.push(PendingAck {
+                worker_id: worker_id.to_string(),
+                job,
+                attempt,
+            });
+            pending.len() >= self.cfg.commit_every
+        };
+        if due {
+            self.flush().await;
+        }
+    }
+
+    async fn record_fetch(
+        &self,
+        worker_id: &str,
+        job: &LeasedUrl,
+        attempt: &FetchAttempt,
+        result: &anyhow::Result<Fetched>,
+    ) {
+        // One row per redirect of the chain, then the response it ended with.
+        for hop in &attempt.redirects {
+            self.record_attempt(worker_id, job, hop, "redirect", None, false)
+                .await;
+        }
+        // What changed the index is only durable after the next commit; its validators
+        // are stored then (`record_content`).
+        let deferred = matches!(result, Ok(Fetched::Indexed(_) | Fetched::Gone));
+        let (outcome, error) = match result {
+            Ok(Fetched::Redirected(_) | Fetched::Blocked(_)) => ("redirect", None),
+            Ok(Fetched::Gone) => ("error", attempt.status.map(|s| format!("fetch status {s}"))),
+            Ok(_) => ("success", None),
+            Err(err) => ("error", Some(format!("{err:#}"))),
+        };
+        self.record_attempt(worker_id, job, attempt, outcome, error.as_deref(), deferred)
+            .await;
+    }
+
+    async fn record_attempt(
+        &self,
+        worker_id: &str,
+        job: &LeasedUrl,
+        attempt: &FetchAttempt,
+        outcome: &str,
+        error: Option<&str>,
+        defer_content: bool,
+    ) {
+        let headers = attempt.headers_json();
+        let rec = FetchRecord {
+            url_id: job.url_id,
+            domain_id: job.domain_id,
+            status_code: attempt.status.map(i32::from),
+            outcome,
+            content_length: attempt
+                .content_length
+                .map(|n| n.min(i64::MAX as u64) as i64),
+            content_hash: attempt.content_hash.as_deref(),
+            content_type: attempt.header("content-type"),
+            etag: attempt.header("etag"),
+            last_modified: attempt.last_modified(),
+            error,
+            latency_ms: Some(attempt.latency.as_millis().min(i32::MAX as u128) as i32),
+            response_headers: headers.as_ref(),
+            worker_id: Some(worker_id),
+            retry_count: job.attempts,
+            defer_content,
+        };
+        if let Err(err) = fetches::record_fetch(&self.pool, &rec).await {
+            eprintln!("[indexing] record fetch url={} error={:?}", job.url, err);
+        }
+    }
+
+    /// Store the validators of a response once the commit covering it has landed.
+    async fn record_content(&self, job: &LeasedUrl, attempt: &FetchAttempt) {
+        let rec = FetchRecord {
+            url_id: job.url_id,
+            content_length: attempt
+                .content_length
+                .map(|n| n.min(i64::MAX as u64) as i64),
+            content_hash: attempt.content_hash.as_deref(),
+            content_type: attempt.header("content-type"),
+            etag: attempt.header("etag"),
+            last_modified: attempt.last_modified(),
+            ..FetchRecord::default()
+        };
+        if let Err(err) = fetches::record_content(&self.pool, &rec).await {
+            eprintln!("[indexing] record content url={} error={:?}", job.url, err);
+        }
+    }
+
+    /// Queue same-domain outlinks one level deeper, record newly linked domains and
+    /// store the page's edges in the link graph.
+    async fn follow(&self, job: &LeasedUrl, state: &DomainState, page: &PageLinks) {
+        let frontier = Frontier::new(&job.domain, self.cfg.frontier.clone());
+        let out = frontier.outlinks(job.depth.max(0) as usize, &page.links);
+        let max_urls = self.cfg.frontier.max_pages as i64;
+        for url in out.same_domain {
+            if !state.robots.allows(&url) {
+                self.block(&job.domain, state, &url).await;
+                continue;
+            }
+            if let Err(err) = queue::enqueue_discovered_url(
+                &self.pool,
+                job.domain_id,
+                &url,
+                job.depth + 1,
+                max_urls,
+            )
+            .await
+            {
+                eprintln!("[indexing] enqueue url={} error={:?}", url, err);
+            }
+        }
+        for other in out.foreign_domains {
+            super::discover_domain(other).await;
+        }
+        // After discovery, so edges into newly found domains have a domain row to hang on.
+        let edges: Vec<(&str, Option<&str>)> = page
+            .links
+            .iter()
+            .map(|l| (l.as_str(), page.anchor(l)))
+            .collect();
+        if let Err(err) = links::replace_outlinks(&self.pool, job.url_id, &edges).await {
+            eprintln!("[indexing] link edges url={} error={:?}", job.url, err);
+        }
+    }
+
+    async fn ack(&self, worker_id: &str, job: &LeasedUrl) {
+        match queue::ack(&self.pool, job.queue_id, worker_id).await {
+            Ok(true) => {}
+            Ok(false) => eprintln!("[indexing] lease lost before ack url={}", job.url),
+            Err(err) => eprintln!("[indexing] ack url={} error={:?}", job.url, err),
+        }
+    }
+
+    async fn nack(&self, worker_id: &str, job: &LeasedUrl, error: &str) {
+        match queue::nack(
+            &self.pool,
+            job.queue_id,
+            worker_id,
+            error,
+            self.cfg.retry_backoff_secs,
+        )
+        .await
+        {
+            Ok(NackOutcome::Retrying) => {}
+            Ok(NackOutcome::GaveUp) => {
+                eprintln!(
+                    "[indexing] giving up url={} after {} attempts",
+                    job.url,
+                    job.attempts + 1
+                );
+                self.touch(job);
+            }
+            Err(err) => eprintln!("[indexing] nack url={} error={:?}", job.url, err),
+        }
+    }
+
+    /// Commit the index, ack the jobs it covers and finish domains with no queued work.
+    async fn flush(&self) {
+        let _guard = self.flush_lock.lock().await;
+        let acks = std::mem::take(&mut *self.pending_acks.lock().unwrap());
+        let deleted = self.uncommitted.swap(false, Ordering::Relaxed);
+        if !acks.is_empty() || deleted {
+            let engine = services::index_engine();
+            match engine.commit().and_then(|_| engine.refresh()) {
+                Ok(()) => {
+                    for a in &acks {
+                        self.record_content(&a.job, &a.attempt).await;
+                        self.ack(&a.worker_id, &a.job).await;
+                        self.touch(&a.job);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("[indexing] commit error: {err:?}");
+                    self.uncommitted.fetch_or(deleted, Ordering::Relaxed);
+                    for a in &acks {
+                        self.nack(
+                            &a.worker_id,
+                            &a.job,
+                            &format!("index commit failed: {err:#}"),
+                        )
+                        .await;
+                    }
+                }
+            }
+        }
+
+        // Pages whose render timed out were indexed statically; queue a deferred re-render.
+        for item in RECRAWL_QUEUE.drain().await {
+            let reason = item
+                .reason
+                .as_ref()
+                .map_or("unknown", DynamicReason::as_str);
+            match recrawl::schedule_render(
+                &self.pool,
+                &item.url,
+                reason,
+                self.cfg.rerender_backoff_secs,
+                self.cfg.rerender_max_attempts,
+            )
+            .await
+            {
+                Ok(true) => eprintln!(
+                    "[indexing] deferred render url={} reason={}",
+                    item.url, reason
+                ),
+                Ok(false) => {}
+                Err(err) => eprintln!("[indexing] defer render url={} error={:?}", item.url, err),
+            }
+        }
+
+        let touched = std::mem::take(&mut *self.touched.lock().unwrap());
+        for (domain_id, domain) in touched {
+            match queue::domain_has_pending(&self.pool, domain_id).await {
+                Ok(false) => self.finish_domain(&domain).await,
+                Ok(true) => {}
+                Err(err) => eprintln!("[indexing] domain={} pending check error={:?}", domain, err),
+            }
+        }
+    }
+
+    /// Next recrawl of a fetched URL, from its current interval and how often its
+    /// recent fetches saw a change.
+    async fn reschedule(&self, job: &LeasedUrl) {
+        let policy = &self.cfg.recrawl;
+        let current = match recrawl::recrawl_interval(&self.pool, job.url_id).await {
+            Ok(secs) => secs.map_or(policy.default, |s| Duration::from_secs(s as u64)),
+            Err(err) => {
+                eprintln!(
+                    "[indexing] recrawl interval url={} error={:?}",
+                    job.url, err
+                );
+                policy.default
+            }
+        };
+        let samples = recrawl::fetch_samples(&self.pool, job.url_id, RECRAWL_HISTORY)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("[indexing] fetch history url={} error={:?}", job.url, err);
+                Vec::new()
+            });
+        let history = observations(samples.iter().filter_map(|s| {
+            match (s.status_code, s.content_hash.as_deref()) {
+                (Some(304), _) => Some((s.fetched_at, None)),
+                (_, Some(hash)) => Some((s.fetched_at, Some(hash))),
+                _ => None,
+            }
+        }));
+        let next = policy.next_interval(current, &history);
+        if let Err(err) = recrawl::schedule_recrawl(
+            &self.pool,
+            job.url_id,
+            job.domain_id,
+            job.depth,
+            interval_secs(next),
+        )
+        .await
+        {
+            eprintln!(
+                "[indexing] schedule recrawl url={} error={:?}",
+                job.url, err
+            );
+        }
+    }
+
+    async fn promote_recrawls(&self) {
+        match recrawl::promote_due(&self.pool, RECRAWL_BATCH).await {
+            Ok(n) if n > 0 => eprintln!("[indexing] queued {} due recrawls", n),
+            Ok(_) => {}
+            Err(err) => eprintln!("[indexing] promote recrawls error={:?}", err),
+        }
+    }
+
+    /// Deferred re-render pass: refetch pages whose render timed out and render them
+    /// with `rerender_budget`. Leases are held as `<worker_id>/rerender`.
+    async fn rerender_loop(&self) {
+        let worker_id = format!("{}/rerender", self.cfg.worker_id);
+        loop {
+            tokio::time::sleep(RERENDER_POLL_INTERVAL).await;
+            match recrawl::lease_renders(&self.pool, &worker_id, RERENDER_BATCH).await {
+                Ok(jobs) => {
+                    if !jobs.is_empty() {
+                        self.rerender(&worker_id, jobs).await;
+                    }
+                }
+                Err(err) => eprintln!("[indexing] re-render lease error={:?}", err),
+            }
+        }
+    }
+
+    async fn rerender(&self, worker_id: &str, jobs: Vec<LeasedUrl>) {
+        let mut rendered = Vec::new();
+        for job in jobs {
+            let state = self.domain_state(&job.domain).await;
+            if !state.robots.allows(&job.url) {
+                self.retry_render(worker_id, &job, "blocked by robots.txt")
+                    .await;
+                continue;
+            }
+            let timeouts = DynamicReCrawlQueue::new();
+            let (attempt, result) = fetch::index_single_url(
+                &job.url,
+                &self.politeness(&job.domain, &state),
+                &Validators::default(),
+                self.cfg.redirects,
+                &timeouts,
+                self.cfg.rerender_budget,
+            )
+            .await;
+            self.record_fetch(worker_id, &job, &attempt, &result).await;
+            let error = match result {
+                Ok(Fetched::Blocked(_)) => "blocked by robots.txt".to_string(),
+                Ok(_) if timeouts.is_empty().await => {
+                    rendered.push((job, attempt));
+                    continue;
+                }
+                // The static version was indexed again.
+                Ok(_) => "render timed out".to_string(),
+                Err(err) => format!("{err:#}"),
+            };
+            self.retry_render(worker_id, &job, &error).await;
+        }
+        if rendered.is_empty() {
+            return;
+        }
+        let committed = {
+            let _guard = self.flush_lock.lock().await;
+            let engine = services::index_engine();
+            engine.commit().and_then(|_| engine.refresh())
+        };
+        for (job, attempt) in rendered {
+            if let Err(err) = &committed {
+                self.retry_render(worker_id, &job, &format!("index commit failed: {err:#}"))
+                    .await;
+                continue;
+            }
+            self.record_content(&job, &attempt).await;
+            match recrawl::mark_rendered(&self.pool, job.queue_id, worker_id).await {
+                Ok(true) => eprintln!(
+                    "[indexing] rendered url={} attempts={}",
+                    job.url,
+                    job.attempts + 1
+                ),
+                Ok(false) => eprintln!("[indexing] re-render lease lost url={}", job.url),
+                Err(err) => eprintln!("[indexing] re-render url={} error={:?}", job.url, err),
+            }
+        }
+    }
+
+    async fn retry_render(&self, worker_id: &str, job: &LeasedUrl, error: &str) {
+        match recrawl::retry_render(
+            &self.pool,
+            job.queue_id,
+            worker_id,
+            error,
+            self.cfg.rerender_backoff_secs,
+        )
+        .await
+        {
+            Ok(NackOutcome::Retrying) => {}
+            Ok(NackOutcome::GaveUp) => eprintln!(
+                "[indexing] render gave up url={} after {} attempts; keeping static version",
+                job.url,
+                job.attempts + 1
+            ),
+            Err(err) => eprintln!("[indexing] re-render retry url={} error={:?}", job.url, err),
+        }
+    }
+
+    /// Record a URL dropped by robots.txt, once per crawl, and drop it from the index.
+    async fn block(&self, domain: &str, state: &DomainState, url: &str) {
+        let first = state.blocked.lock().unwrap().insert(url.to_string());
+        if first {
+            eprintln!(
+                "[indexing] skip url={} reason=\"{}\"",
+                url,
+                robots::BLOCKED_REASON
+            );
+            robots::mark_blocked(domain, url, true).await;
+            match services::index_engine().delete_url(url) {
+                Ok(()) => self.uncommitted.store(true, Ordering::Relaxed),
+                Err(err) => eprintln!("[indexing] delete url={} error={:?}", url, err),
+            }
+        }
+    }
+
+    /// Mark a domain whose robots.txt disallows everything as blocked and drop its pages
+    /// from the index; the next flush commits the deletion.
+    async fn block_domain(&self, domain: &str) {
+        eprintln!(
+            "[indexing] blocked domain={} reason=\"{}\"",
+            domain,
+            robots::BLOCKED_REASON
+        );
+        if let Err(err) =
+            crate::storage::domains::set_domain_status(&self.pool, domain, "blocked").await
+        {
+            eprintln!("[indexing] domain={} block error={:?}", domain, err);
+        }
+        match services::index_engine().delete_domain(domain) {
+            Ok(()) => self.uncommitted.store(true, Ordering::Relaxed),
+            Err(err) => eprintln!("[indexing] delete domain={} error={:?}", domain, err),
+        }
+    }
+
+    async fn finish_domain(&self, domain: &str) {
+        let cell = self.domains.lock().unwrap().remove(domain);
+        if let Some(state) = cell.as_ref().and_then(|c| c.get()) {
+            eprintln!(
+                "[indexing] crawled domain={} pages={} robots_blocked={}",
+                domain,
+                state.fetched.load(Ordering::Relaxed),
+                state.blocked.lock().unwrap().len()
+            );
+            if state.robots.disallows_all() {
+                self.block_domain(domain).await;
+                return;
+            }
+        }
+
+        // mark domain as ready in DB reliably with retries
+        let mut attempts = 0;
+        const MAX_ATTEMPTS: usize = 3;
+        let mut backoff = std::time::Duration::from_millis(100);
+        loop {
+            attempts += 1;
+            match crate::storage::domains::set_domain_status(&self.pool, domain, "ready").await {
+                Ok(_) => break,
+                Err(err) => {
+                    if attempts >= MAX_ATTEMPTS {
+                        tracing::error!(
+                            "[indexing] failed to set domain={} to ready after {} attempts: {:?}",
+                            domain,
+                            attempts,
+                            err
+                        );
+                        break;
+                    }
+                    tracing::warn!("[indexing] retrying set_domain_status for domain={} (attempt {}/{}) due to: {:?}", domain, attempts, MAX_ATTEMPTS, err);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        }
+    }
+}
+
+fn interval_secs(interval: Duration) -> i32 {
+    interval.as_secs().clamp(1, i32::MAX as u64) as i32
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
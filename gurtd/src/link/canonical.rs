@@ -0,0 +1,137 @@
+//! URL resolution and canonicalization.
+//!
+//! Every URL that enters the frontier, the index or the link graph goes through
+//! [`canonicalize_url`], so that trivially different spellings of the same page
+//! (`gurt://Example.real:4878/a/./b/#top` vs `gurt://example.real/a/b`) collapse
+//! into one key.
+
+use url::Url;
+
+/// Default GURT port; it is implied and therefore dropped from canonical URLs.
+pub const DEFAULT_GURT_PORT: u16 = 4878;
+
+/// Canonical form of an absolute `gurt://` URL:
+/// - host lowercased (and a trailing root dot dropped), default port 4878 removed
+/// - userinfo and fragment stripped
+/// - `.`/`..` segments resolved, repeated slashes collapsed, trailing slash removed
+///   (except for the root path)
+/// - query parameters sorted by name, then value; an empty query is dropped
+///
+/// Returns None for other schemes and unparsable input.
+pub fn canonicalize_url(raw: &str) -> Option<String> {
+    let mut u = Url::parse(raw.trim()).ok()?;
+    if u.scheme() != "gurt" {
+        return None;
+    }
+    let host = u.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() {
+        return None;
+    }
+    u.set_host(Some(&host)).ok()?;
+    if u.port() == Some(DEFAULT_GURT_PORT) {
+        u.set_port(None).ok()?;
+    }
+    u.set_username("").ok()?;
+    u.set_password(None).ok()?;
+    u.set_fragment(None);
+
+    let path = normalize_path(u.path());
+    u.set_path(&path);
+
+    let query = u.query().map(sort_query);
+    match query {
+        Some(q) if !q.is_empty() => u.set_query(Some(&q)),
+        _ => u.set_query(None),
+    }
+    Some(u.to_string())
+}
+
+/// Resolve `href` (absolute or relative) against `base` and canonicalize the result.
+/// Fragment-only, `javascript:`, `mailto:` and other non-gurt links yield None.
+pub fn resolve_url(base: &Url, href: &str) -> Option<String> {
+    let href = href.trim();
+    if href.is_empty() || href.starts_with('#') {
+        return None;
+    }
+    let joined = base.join(href).ok()?;
+    canonicalize_url(joined.as_str())
+}
+
+fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+fn sort_query(query: &str) -> String {
+    let mut pairs: Vec<(&str, &str)> = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| {
+            if v.is_empty() {
+                (*k).to_string()
+            } else {
+                format!("{k}={v}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_form() {
+        assert_eq!(
+            canonicalize_url("gurt://Example.REAL:4878/a/./b/../c//d/?z=1&a=2&a=1#top").as_deref(),
+            Some("gurt://example.real/a/c/d?a=1&a=2&z=1")
+        );
+        assert_eq!(
+            canonicalize_url("gurt://example.real").as_deref(),
+            Some("gurt://example.real/")
+        );
+        assert_eq!(
+            canonicalize_url("gurt://example.real:9000/?").as_deref(),
+            Some("gurt://example.real:9000/")
+        );
+        assert!(canonicalize_url("https://example.real/").is_none());
+        assert!(canonicalize_url("not a url").is_none());
+    }
+
+    #[test]
+    fn resolves_relative_links() {
+        let base = Url::parse("gurt://example.real/docs/intro").unwrap();
+        let r = |h| resolve_url(&base, h);
+        assert_eq!(
+            r("setup").as_deref(),
+            Some("gurt://example.real/docs/setup")
+        );
+        assert_eq!(r("../about/").as_deref(), Some("gurt://example.real/about"));
+        assert_eq!(
+            r("/x?b=1&a=2").as_deref(),
+            Some("gurt://example.real/x?a=2&b=1")
+        );
+        assert_eq!(r("//Other.real/y").as_deref(), Some("gurt://other.real/y"));
+        assert_eq!(
+            r("?q=1").as_deref(),
+            Some("gurt://example.real/docs/intro?q=1")
+        );
+        assert!(r("#section").is_none());
+        assert!(r("mailto:a@b.real").is_none());
+    }
+}
@@ -1,64 +1,140 @@
 use std::collections::HashMap;
+use std::sync::RwLock;
 
-/// Extract links from HTML by scanning for <a ... href="..."> occurrences.
-/// - Only returns absolute gurt:// URLs; relative URLs are ignored for simplicity in v1.
-pub fn extract_links(html: &str) -> Vec<String> {
-    let mut out = Vec::new();
-    let bytes = html.as_bytes();
-    let lower = html.to_ascii_lowercase();
-    let mut i = 0;
-    while let Some(href_rel) = lower[i..].find("href") {
-        let href_pos = i + href_rel;
-        // find '=' after href
-        let eq_opt = lower[href_pos..].find('=');
-        if eq_opt.is_none() {
-            break;
-        }
-        let mut j = href_pos + eq_opt.unwrap() + 1;
-        // skip whitespace
-        while j < html.len() && bytes[j].is_ascii_whitespace() {
-            j += 1;
-        }
-        if j >= html.len() {
-            break;
-        }
-        // Some inputs may include literal backslashes before quotes (e.g., raw strings); skip them
-        while j < html.len() && bytes[j] == b'\\' {
-            j += 1;
+use once_cell::sync::Lazy;
+
+pub mod canonical;
+
+pub use canonical::{canonicalize_url, resolve_url};
+
+use crate::crawler::html::{tokenize, Token};
+
+/// Links discovered in one HTML page.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageLinks {
+    /// Canonical gurt:// outlinks, de-duplicated, in document order.
+    pub links: Vec<String>,
+    /// Target of `<link rel="canonical">`, resolved and canonicalized.
+    pub canonical: Option<String>,
+    /// Anchor text per link (`alt` for `<area>`): the first non-empty text the page
+    /// gives it, whitespace-collapsed and capped at [`MAX_ANCHOR_CHARS`].
+    pub anchors: HashMap<String, String>,
+}
+
+/// Longest anchor text kept per link.
+pub const MAX_ANCHOR_CHARS: usize = 256;
+
+impl PageLinks {
+    pub fn anchor(&self, link: &str) -> Option<&str> {
+        self.anchors.get(link).map(String::as_str)
+    }
+
+    fn add_anchor(&mut self, link: &str, text: &str) {
+        if self.anchors.contains_key(link) {
+            return;
         }
-        if j >= html.len() {
-            break;
+        let text: String = text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(MAX_ANCHOR_CHARS)
+            .collect();
+        if !text.is_empty() {
+            self.anchors.insert(link.to_string(), text);
         }
-        let quote = bytes[j];
-        if quote == b'"' || quote == b'\'' {
-            j += 1;
-            let mut k = j;
-            while k < html.len() && bytes[k] != quote {
-                k += 1;
+    }
+}
+
+/// Extract links from an HTML page fetched from `page_url`.
+/// - `<a href>` and `<area href>` values are resolved against the first `<base href>`,
+///   falling back to the page URL.
+/// - Only gurt:// links are kept, in canonical form (see [`canonicalize_url`]).
+pub fn extract_page_links(page_url: &str, html: &str) -> PageLinks {
+    let mut out = PageLinks::default();
+    let Ok(page) = url::Url::parse(page_url) else {
+        return out;
+    };
+    let tokens = tokenize(html);
+    let base = tokens
+        .iter()
+        .find(|t| {
+            matches!(t, Token::StartTag { name, .. } if name == "base") && t.attr("href").is_some()
+        })
+        .and_then(|t| page.join(t.attr("href")?.trim()).ok())
+        .unwrap_or(page);
+
+    let mut seen = std::collections::HashSet::new();
+    // Link of the enclosing <a> and the text collected so far.
+    let mut open: Option<(String, String)> = None;
+    for t in &tokens {
+        let name = match t {
+            Token::StartTag { name, .. } => name,
+            Token::Text(text) => {
+                if let Some((_, buf)) = open.as_mut() {
+                    buf.push_str(text);
+                    buf.push(' ');
+                }
+                continue;
             }
-            let mut url = &html[j..k.min(html.len())];
-            if url.starts_with("gurt://") {
-                // Tolerate stray trailing backslashes before closing quote (from raw strings)
-                url = url.trim_end_matches('\\');
-                out.push(url.to_string());
+            Token::EndTag { name } => {
+                if name == "a" {
+                    if let Some((link, text)) = open.take() {
+                        out.add_anchor(&link, &text);
+                    }
+                }
+                continue;
+            }
+        };
+        match name.as_str() {
+            "a" | "area" => {
+                if name == "a" {
+                    if let Some((link, text)) = open.take() {
+                        out.add_anchor(&link, &text);
+                    }
+                }
+                let Some(link) = t.attr("href").and_then(|h| resolve_url(&base, h)) else {
+                    continue;
+                };
+                if seen.insert(link.clone()) {
+                    out.links.push(link.clone());
+                }
+                if name == "area" {
+                    out.add_anchor(&link, t.attr("alt").unwrap_or(""));
+                } else {
+                    open = Some((link, String::new()));
+                }
             }
-            i = k.saturating_add(1);
-        } else {
-            // unquoted value
-            let mut k = j;
-            while k < html.len() && !bytes[k].is_ascii_whitespace() && bytes[k] != b'>' {
-                k += 1;
+            "img" => {
+                // An image link has no text; its alt text stands in.
+                if let (Some((_, buf)), Some(alt)) = (open.as_mut(), t.attr("alt")) {
+                    buf.push_str(alt);
+                    buf.push(' ');
+                }
             }
-            let url = &html[j..k];
-            if url.starts_with("gurt://") {
-                out.push(url.trim_end_matches('\\').to_string());
+            "link" if out.canonical.is_none() => {
+                let is_canonical = t.attr("rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("canonical"))
+                });
+                if is_canonical {
+                    out.canonical = t.attr("href").and_then(|h| resolve_url(&base, h));
+                }
             }
-            i = k;
+            _ => {}
         }
     }
+    if let Some((link, text)) = open {
+        out.add_anchor(&link, &text);
+    }
     out
 }
 
+/// Outlinks of an HTML page as canonical absolute URLs; see [`extract_page_links`].
+pub fn extract_links(page_url: &str, html: &str) -> Vec<String> {
+    extract_page_links(page_url, html).links
+}
+
 /// Directed link graph using adjacency lists.
 #[derive(Default, Debug, Clone)]
 pub struct LinkGraph {
@@ -125,6 +201,29 @@ pub fn domain_trust_from_cname_depth(depth: usize) -> f64 {
     1.0 / (1.0 + depth as f64)
 }
 
+static CNAME_DEPTHS: Lazy<RwLock<HashMap<String, usize>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Record the CNAME chain depth last observed for `domain`.
+pub fn set_cname_depth(domain: &str, depth: usize) {
+    CNAME_DEPTHS
+        .write()
+        .unwrap()
+        .insert(domain.to_ascii_lowercase(), depth);
+}
+
+/// Trust of `domain` from its recorded CNAME depth; domains never resolved through GURT
+/// DNS (IPs, localhost, not yet crawled) count as depth 0.
+pub fn domain_trust(domain: &str) -> f64 {
+    let depth = CNAME_DEPTHS
+        .read()
+        .unwrap()
+        .get(&domain.to_ascii_lowercase())
+        .copied()
+        .unwrap_or(0);
+    domain_trust_from_cname_depth(depth)
+}
+
 /// Combine document authority (PageRank) with domain trust.
 /// Final score = alpha * pr + (1-alpha) * domain_trust
 pub fn combine_authority(pr: f64, domain_trust: f64, alpha: f64) -> f64 {
@@ -132,6 +231,36 @@ pub fn combine_authority(pr: f64, domain_trust: f64, alpha: f64) -> f64 {
     a * pr + (1.0 - a) * domain_trust
 }
 
+/// Turn raw PageRank values into authority scores in `[0, 1]`.
+///
+/// Raw ranks shrink as the graph grows (they sum to about 1), so they are scaled by the
+/// node count and log-compressed relative to the best page: a page at the average rank
+/// scores `ln 2 / ln(1 + max * n)` and the top page scores 1.
+pub fn authority_scores(ranks: &HashMap<String, f64>) -> HashMap<String, f64> {
+    let n = ranks.len() as f64;
+    let max = ranks.values().copied().fold(0.0f64, f64::max) * n;
+    if max <= 0.0 {
+        return ranks.keys().map(|k| (k.clone(), 0.0)).collect();
+    }
+    let denom = max.ln_1p();
+    ranks
+        .iter()
+        .map(|(k, pr)| {
+            (
+                k.clone(),
+                ((pr * n).max(0.0).ln_1p() / denom).clamp(0.0, 1.0),
+            )
+        })
+        .collect()
+}
+
+static AUTHORITY: Lazy<RwLock<AuthorityStore>> = Lazy::new(|| RwLock::new(AuthorityStore::new()));
+
+/// Process-wide authority scores read by the ranker and replaced by the PageRank job.
+pub fn global_authority() -> &'static RwLock<AuthorityStore> {
+    &AUTHORITY
+}
+
 /// In-memory per-document authority score store with simple JSON persistence.
 #[derive(Default, Debug, Clone)]
 pub struct AuthorityStore {
@@ -153,6 +282,9 @@ impl AuthorityStore {
     pub fn len(&self) -> usize {
         self.map.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
 
     pub fn to_json(&self) -> String {
         let mut items: Vec<(&String, &f32)> = self.map.iter().collect();
@@ -170,8 +302,8 @@ impl AuthorityStore {
         let mut out = Self::new();
         for line in s.lines() {
             let line = line.trim();
-            if line.starts_with('"') {
-                if let Some((k, rest)) = line[1..].split_once('"') {
+            if let Some(quoted) = line.strip_prefix('"') {
+                if let Some((k, rest)) = quoted.split_once('"') {
                     if let Some(colon) = rest.find(':') {
                         let val_str = rest[colon + 1..].trim().trim_end_matches(',');
                         if let Ok(val) = val_str.parse::<f32>() {
@@ -191,9 +323,54 @@ mod tests {
 
     #[test]
     fn extract_basic_links() {
-        let html = r#"<a href=\"gurt://example.real/\">home</a> <a class=\"x\" href='gurt://a/b'>b</a> <a href=/rel>rel</a>"#;
-        let links = extract_links(html);
-        assert_eq!(links, vec!["gurt://example.real/", "gurt://a/b"]);
+        let html = r#"<a href="gurt://example.real/">home</a> <a class="x" href='gurt://a/b'>b</a> <a href=/rel>rel</a>"#;
+        let links = extract_links("gurt://example.real/page", html);
+        assert_eq!(
+            links,
+            vec![
+                "gurt://example.real/",
+                "gurt://a/b",
+                "gurt://example.real/rel"
+            ]
+        );
+    }
+
+    #[test]
+    fn base_href_and_canonical_are_honored() {
+        let html = r##"<head><base href="/docs/"><link rel="Canonical" href="intro?b=2&a=1#x"></head>
+            <a href="setup">s</a><a href="setup#again">dup</a><a href="#top">self</a>"##;
+        let page = extract_page_links("gurt://Example.real/other/page", html);
+        assert_eq!(page.links, vec!["gurt://example.real/docs/setup"]);
+        assert_eq!(
+            page.canonical.as_deref(),
+            Some("gurt://example.real/docs/intro?a=1&b=2")
+        );
+    }
+
+    #[test]
+    fn anchor_text_is_collected() {
+        let html = r#"<a href="/a"> Read
+            <b>the</b>  docs </a><a href="/a">again</a>
+            <a href="/img"><img src="x.png" alt="Logo"></a><a href="/empty"></a>
+            <map><area href="/area" alt="Region"></map>"#;
+        let page = extract_page_links("gurt://example.real/", html);
+        assert_eq!(page.anchor("gurt://example.real/a"), Some("Read the docs"));
+        assert_eq!(page.anchor("gurt://example.real/img"), Some("Logo"));
+        assert_eq!(page.anchor("gurt://example.real/area"), Some("Region"));
+        assert_eq!(page.anchor("gurt://example.real/empty"), None);
+        assert_eq!(page.links.len(), 4);
+    }
+
+    #[test]
+    fn authority_scores_are_normalized() {
+        let mut g = LinkGraph::new();
+        g.add_edge("A", "C");
+        g.add_edge("B", "C");
+        g.add_edge("C", "A");
+        let scores = authority_scores(&g.pagerank(0.85, 30));
+        assert_eq!(scores["C"], 1.0);
+        assert!(scores["A"] > scores["B"] && scores["B"] > 0.0);
+        assert!(authority_scores(&HashMap::new()).is_empty());
     }
 
     #[test]
@@ -218,6 +395,15 @@ mod tests {
         assert_eq!(domain_trust_from_cname_depth(6), 0.0);
     }
 
+    #[test]
+    fn domain_trust_uses_recorded_depth() {
+        set_cname_depth("Alias.trust-test.real", 2);
+        assert_eq!(domain_trust("alias.trust-test.real"), 1.0 / 3.0);
+        set_cname_depth("deep.trust-test.real", 6);
+        assert_eq!(domain_trust("deep.trust-test.real"), 0.0);
+        assert_eq!(domain_trust("unknown.trust-test.real"), 1.0);
+    }
+
     #[test]
     fn authority_store_json_roundtrip() {
         let mut s = AuthorityStore::new();
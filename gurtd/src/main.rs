@@ -15,6 +15,9 @@ async fn main() -> Result<()> {
     let cert_path = std::env::var("GURT_CERT").unwrap_or_else(|_| "gurt-server.crt".to_string());
     let key_path = std::env::var("GURT_KEY").unwrap_or_else(|_| "gurt-server.key".to_string());
     let addr = std::env::var("GURT_ADDR").unwrap_or_else(|_| "127.0.0.1:4878".to_string());
+    gurtd::crawler::tls::TlsSettings::from_env()
+        .check()
+        .context("crawler TLS settings")?;
 
     let pool = {
         let db_cfg = DbConfig::from_env();
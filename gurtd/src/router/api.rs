@@ -12,13 +12,13 @@ use crate::query::parse_query;
 use crate::search::{normalize_key, HotQueryCache};
 
 use super::search_utils::rescore_and_convert;
-use super::util::{json_response, percent_decode};
+use super::util::{json_response, parse_paging, percent_decode};
 
 static HOT_CACHE: Lazy<HotQueryCache> =
     Lazy::new(|| HotQueryCache::new(std::time::Duration::from_secs(20)));
 
 pub fn handle_search(req: Request) -> Result<Response> {
-    // Minimal parse for q param; page/size defaults
+    // Minimal parse for q param; page/size are clamped by parse_paging
     let mut q = None;
     if let Some(query) = req.query() {
         for pair in query.split('&') {
@@ -60,25 +60,28 @@ pub fn handle_search(req: Request) -> Result<Response> {
             body: vec![],
         });
     }
-    // Query cache: normalize q+filters
+    // Query cache: normalize q+filters, keyed per page
+    let (page, size) = parse_paging(req.query());
     let pq = parse_query(&q);
-    let key = normalize_key(&pq);
+    let key = format!(
+        "{}\u{1f}page={}\u{1f}size={}",
+        normalize_key(&pq),
+        page,
+        size
+    );
     if let Some(hit) = HOT_CACHE.get(&key) {
         let body = serde_json::to_vec(&hit).unwrap_or_else(|_| b"{}".to_vec());
         return Ok(json_response(StatusCode::Ok, body));
     }
 
     // Execute query on the default engine.
-    // TODO: thread pagination from the client once the UI grows controls.
-    let page = 1usize;
-    let size = 10usize;
     let engine = crate::services::index_engine();
-    let hits = engine.search(&pq, page, size).unwrap_or_default();
+    let found = engine.search(&pq, page, size).unwrap_or_default();
     // Rescore BM25 -> link -> trust -> recency
-    let results = rescore_and_convert(hits, size as usize);
+    let results = rescore_and_convert(found.hits, size);
     let resp = SearchResponse {
         query: pq.terms.join(" "),
-        total: results.len() as u64,
+        total: found.total,
         page: page as u32,
         size: size as u32,
         results,
@@ -113,9 +116,7 @@ impl IpRateLimiter {
     fn allow(&self, ip: IpAddr) -> bool {
         let now = std::time::Instant::now();
         let mut map = self.map.lock().unwrap();
-        let q = map
-            .entry(ip)
-            .or_insert_with(|| std::collections::VecDeque::new());
+        let q = map.entry(ip).or_default();
         while let Some(&t) = q.front() {
             if now.duration_since(t) > self.window {
                 q.pop_front();
@@ -177,8 +178,8 @@ pub fn handle_add_site(req: Request, peer: Option<SocketAddr>) -> Result<Respons
     }
 
     // persist submission asynchronously to DB (fire-and-forget to keep latency low)
-    {
-        let pool = crate::services::db().clone();
+    if let Some(pool) = crate::services::try_db() {
+        let pool = pool.clone();
         let d = domain.clone();
         tokio::spawn(async move {
             let _ = crate::storage::domains::upsert_domain_submission(&pool, &d, Some("api")).await;
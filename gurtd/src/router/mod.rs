@@ -26,7 +26,8 @@ pub fn handle_with_peer(req: Request, peer: Option<SocketAddr>) -> Result<Respon
                 for pair in query.split('&') {
                     if let Some((k, v)) = pair.split_once('=') {
                         if k == "q" {
-                            return ui::render_search_ssr(&util::percent_decode(v));
+                            let (page, size) = util::parse_paging(Some(query));
+                            return ui::render_search_ssr(&util::percent_decode(v), page, size);
                         }
                     }
                 }
@@ -120,7 +121,8 @@ fn web_search(req: Request, _peer: Option<SocketAddr>) -> Result<Response> {
         for pair in query.split('&') {
             if let Some((k, v)) = pair.split_once('=') {
                 if k == "q" {
-                    return ui::render_search_ssr(&util::percent_decode(v));
+                    let (page, size) = util::parse_paging(Some(query));
+                    return ui::render_search_ssr(&util::percent_decode(v), page, size);
                 }
             }
         }
@@ -1,15 +1,10 @@
 pub use super::util::escape_html;
 
-use once_cell::sync::Lazy;
-
 use crate::index::SearchHit;
-use crate::link::{domain_trust_from_cname_depth, AuthorityStore};
+use crate::link::{domain_trust, global_authority};
 use crate::search::merge_topk;
 use gurt_api::response::SearchResultItem;
 
-static AUTH_STORE: Lazy<std::sync::Mutex<AuthorityStore>> =
-    Lazy::new(|| std::sync::Mutex::new(AuthorityStore::new()));
-
 pub(crate) fn rescore_and_convert(hits: Vec<SearchHit>, k: usize) -> Vec<SearchResultItem> {
     if hits.is_empty() {
         return Vec::new();
@@ -25,13 +20,13 @@ pub(crate) fn rescore_and_convert(hits: Vec<SearchHit>, k: usize) -> Vec<SearchR
         .unwrap_or(0);
     let half_life_secs = 7 * 24 * 3600i64; // 7 days
     let weights = (0.6f64, 0.2f64, 0.1f64, 0.1f64); // (bm25, authority, trust, recency)
-    let store = AUTH_STORE.lock().unwrap();
+    let store = global_authority().read().unwrap();
     let mut rescored: Vec<SearchResultItem> = hits
         .into_iter()
         .map(|h| {
             let bm25 = (h.score / max_bm) as f64;
             let auth = store.get(&h.url).unwrap_or(0.0) as f64;
-            let trust = domain_trust_from_cname_depth(0);
+            let trust = domain_trust(&h.domain);
             let age = (now - h.fetch_time).max(0) as f64;
             let recency = if half_life_secs > 0 {
                 (0.5f64).powf(age / (half_life_secs as f64))
@@ -44,6 +39,7 @@ pub(crate) fn rescore_and_convert(hits: Vec<SearchHit>, k: usize) -> Vec<SearchR
                 title: h.title,
                 url: h.url,
                 score: score as f32,
+                snippet: highlight_snippet(&h.snippet, &h.snippet_highlights),
             }
         })
         .collect();
@@ -54,3 +50,44 @@ pub(crate) fn rescore_and_convert(hits: Vec<SearchHit>, k: usize) -> Vec<SearchR
     });
     merge_topk(vec![rescored], k)
 }
+
+/// Escape a plain-text snippet and wrap the highlighted byte ranges in `<b>`.
+/// Ranges that are out of bounds, overlapping or not on char boundaries are skipped.
+pub(crate) fn highlight_snippet(text: &str, ranges: &[std::ops::Range<usize>]) -> String {
+    let mut out = String::with_capacity(text.len() + ranges.len() * 7);
+    let mut pos = 0;
+    for r in ranges {
+        if r.start < pos
+            || r.end > text.len()
+            || r.start >= r.end
+            || !text.is_char_boundary(r.start)
+            || !text.is_char_boundary(r.end)
+        {
+            continue;
+        }
+        out.push_str(&escape_html(&text[pos..r.start]));
+        out.push_str("<b>");
+        out.push_str(&escape_html(&text[r.start..r.end]));
+        out.push_str("</b>");
+        pos = r.end;
+    }
+    out.push_str(&escape_html(&text[pos..]));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight_snippet;
+
+    #[test]
+    fn highlights_are_wrapped_and_text_escaped() {
+        let text = "<rust> & rust";
+        let html = highlight_snippet(text, &[1..5, 9..13]);
+        assert_eq!(html, "&lt;<b>rust</b>&gt; &amp; <b>rust</b>");
+    }
+
+    #[test]
+    fn invalid_ranges_are_ignored() {
+        assert_eq!(highlight_snippet("héllo", &[2..3, 4..99]), "héllo");
+    }
+}
@@ -169,13 +169,12 @@ fn html_response(code: StatusCode, body: Vec<u8>) -> Response {
     }
 }
 
-pub fn render_search_ssr(q: &str) -> anyhow::Result<Response> {
+pub fn render_search_ssr(q: &str, page: usize, size: usize) -> anyhow::Result<Response> {
     let pq = parse_query(q);
-    let page = 1usize;
-    let size = 10usize;
     let engine = services::index_engine();
-    let hits = engine.search(&pq, page, size).unwrap_or_default();
-    let results = rescore_and_convert(hits, size as usize);
+    let found = engine.search(&pq, page, size).unwrap_or_default();
+    let total = found.total;
+    let results = rescore_and_convert(found.hits, size);
 
     let mut items = String::new();
     for r in &results {
@@ -186,14 +185,18 @@ pub fn render_search_ssr(q: &str) -> anyhow::Result<Response> {
         };
         let url = escape_html(&r.url);
         let etitle = escape_html(&title);
+        // r.snippet is already escaped by rescore_and_convert; only <b> marks are markup.
+        let snippet = &r.snippet;
         items.push_str(&format!(
             "<li style=\"w-full rounded border border-[#202637] bg-[#0f1526] hover:bg-[#111a2e] p-3 flex flex-col\">
                 <a href=\"{url}\" style=\"text-[#e6e6f0] hover:text-[#6366f1] font-bold\">{etitle}</a>
                 <p style=\"text-sm text-[#808080] mt-1\">{url}</p>
+                <p style=\"text-sm text-[#d9d9d9] mt-1\">{snippet}</p>
             </li>"
         ));
     }
 
+    let pager = render_pager(q, page, size, total);
     let sq = super::util::escape_html(q);
     let body = format!(
         "<head><meta charset=\"utf-8\"/>
@@ -207,6 +210,7 @@ pub fn render_search_ssr(q: &str) -> anyhow::Result<Response> {
       <button type=\"submit\" id=\"submit\" style=\"bg-[#a0a0a0] text-[#1a1a1a] rounded px-5 py-3\">Search</button>
     </form>
     <ul id=\"results\" style=\"mt-4 flex flex-col gap-2 items-stretch w-full list-none m-0 p-0\">{items}</ul>
+    {pager}
     <div style=\"inline-flex gap-4 text-xs text-[#808080] mt-40\">
       <a href=\"/domains\" style=\"hover:text-[#6366f1] text-xs text-[#808080]\">Submit a domain</a>
       <span style=\"text-xs text-[#808080]\">•</span>
@@ -227,6 +231,37 @@ pub fn render_search_ssr(q: &str) -> anyhow::Result<Response> {
     Ok(html_response(StatusCode::Ok, body.into_bytes()))
 }
 
+/// Result count plus prev/next links; links keep the query and page size.
+fn render_pager(q: &str, page: usize, size: usize, total: u64) -> String {
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+    let pages = (total as usize).div_ceil(size).min(super::util::MAX_PAGE);
+    let eq = utf8_percent_encode(q, NON_ALPHANUMERIC).to_string();
+    let link = |p: usize, label: &str| {
+        format!(
+            "<a href=\"/search?q={eq}&page={p}&size={size}\" style=\"hover:text-[#6366f1] text-[#d9d9d9]\">{label}</a>"
+        )
+    };
+    let mut out = String::from(
+        "<div id=\"pager\" style=\"inline-flex gap-4 items-center text-sm text-[#808080]\">",
+    );
+    if page > 1 {
+        out.push_str(&link(page - 1, "&larr; Prev"));
+    }
+    if pages > 0 {
+        out.push_str(&format!(
+            "<span>Page {page} of {pages} &middot; {total} results</span>"
+        ));
+    } else {
+        out.push_str("<span>No results</span>");
+    }
+    if page < pages {
+        out.push_str(&link(page + 1, "Next &rarr;"));
+    }
+    out.push_str("</div>");
+    out
+}
+
 // Fallback inline UI if disk files are missing
 static DEFAULT_INDEX_HTML: &str = r#"<head>
   <meta charset=\"utf-8\" />
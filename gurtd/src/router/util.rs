@@ -8,6 +8,34 @@ pub fn percent_decode(s: &str) -> String {
         .to_string()
 }
 
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+pub const MAX_PAGE_SIZE: usize = 50;
+/// Deep pagination is expensive (TopDocs collects offset + size) and rarely useful.
+pub const MAX_PAGE: usize = 100;
+
+/// Look up a raw (still percent-encoded) query-string parameter.
+pub fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+/// Parse `page` and `size` from a query string, clamping to sane bounds.
+/// Missing or malformed values fall back to page 1 and the default size.
+pub fn parse_paging(query: Option<&str>) -> (usize, usize) {
+    let page = query_param(query, "page")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_PAGE);
+    let size = query_param(query, "size")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    (page, size)
+}
+
 pub fn json_response(code: StatusCode, body: Vec<u8>) -> Response {
     if code == StatusCode::Ok
         && std::env::var("GURT_DEBUG_RESULTS")
@@ -13,7 +13,8 @@ pub fn normalize_key(pq: &ParsedQuery) -> String {
         parts.push(t.to_ascii_lowercase());
     }
     if let Some(site) = &pq.filters.site {
-        parts.push(format!("site={}", site.to_ascii_lowercase()));
+        let prefix = if pq.filters.site_subdomains { "*." } else { "" };
+        parts.push(format!("site={}{}", prefix, site.to_ascii_lowercase()));
     }
     if let Some(ft) = &pq.filters.filetype {
         parts.push(format!("filetype={}", ft.to_ascii_lowercase()));
@@ -142,17 +143,20 @@ mod tests {
                 title: "t1".into(),
                 url: "u1".into(),
                 score: 0.2,
+                snippet: String::new(),
             },
             SearchResultItem {
                 title: "t2".into(),
                 url: "u2".into(),
                 score: 0.1,
+                snippet: String::new(),
             },
         ];
         let s2 = vec![SearchResultItem {
             title: "t3".into(),
             url: "u3".into(),
             score: 0.5,
+            snippet: String::new(),
         }];
         let merged = merge_topk(vec![s1, s2], 2);
         assert_eq!(merged[0].url, "u3");
@@ -166,6 +170,7 @@ mod tests {
                 title: "a".into(),
                 url: "a".into(),
                 score: 1.0,
+                snippet: String::new(),
             }]
         });
         let f2 = Box::pin(async {
@@ -174,6 +179,7 @@ mod tests {
                 title: "b".into(),
                 url: "b".into(),
                 score: 2.0,
+                snippet: String::new(),
             }]
         });
         let shards = gather_with_timeout(vec![f1, f2], Duration::from_millis(10)).await;
@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use once_cell::sync::{Lazy, OnceCell};
 
 use gurt_db::PgPool;
@@ -28,6 +30,10 @@ static INDEX_ENGINE: Lazy<Box<dyn IndexEngine>> = Lazy::new(|| {
             match crate::index::tantivy::TantivyIndexEngine::open_or_create_in_dir(path) {
                 Ok(engine) => {
                     eprintln!("[index] using Tantivy on-disk index at {}", path);
+                    if engine.rebuilt() {
+                        eprintln!("[index] schema changed; rebuilt empty index at {}", path);
+                        INDEX_REBUILT.store(true, Ordering::Relaxed);
+                    }
                     return Box::new(engine);
                 }
                 Err(e) => {
@@ -44,6 +50,9 @@ static INDEX_ENGINE: Lazy<Box<dyn IndexEngine>> = Lazy::new(|| {
         .expect("index engine")
 });
 
+/// Set when opening the index replaced one of an older schema.
+static INDEX_REBUILT: AtomicBool = AtomicBool::new(false);
+
 static SERVICES: OnceCell<Services> = OnceCell::new();
 
 pub fn init(db_pool: PgPool) {
@@ -60,7 +69,19 @@ pub fn db() -> &'static PgPool {
     services().db()
 }
 
+/// Database pool if services were initialized (None in tests and tools).
+pub fn try_db() -> Option<&'static PgPool> {
+    SERVICES.get().map(|s| s.db())
+}
+
 /// Obtain a reference to the global index engine.
 pub fn index_engine() -> &'static dyn IndexEngine {
     &**INDEX_ENGINE
 }
+
+/// Whether the on-disk index was rebuilt empty at startup, so the crawled pages have to
+/// be indexed again.
+pub fn index_rebuilt() -> bool {
+    Lazy::force(&INDEX_ENGINE);
+    INDEX_REBUILT.load(Ordering::Relaxed)
+}
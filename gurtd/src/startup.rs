@@ -1,10 +1,6 @@
 // TODO bootstrap v2 notes:
-// - when enabling a DB-backed crawl queue (env GURT_USE_DB_QUEUE=1), clear stale locks:
-//   UPDATE crawl_queue SET locked_by = NULL, locked_at = NULL
-//   WHERE locked_at IS NOT NULL AND locked_at &lt; NOW() - interval '5 minutes';
-//   consider a tunable window via GURT_QUEUE_LOCK_STALE_SECS
-// - apply the same policy to recrawl_queue if leasing is added there
-// - prefer leasing URL-level jobs from DB rather than re-enqueuing whole domains to avoid refetch churn
+// - the indexing worker releases its own and stale crawl_queue/recrawl_queue leases on
+//   start (GURT_QUEUE_LOCK_STALE_SECS)
 // - in multi-server mode, run bootstrap in a single coordinator and shard work by domain hash
 // - keep bootstrap bounded and non-blocking; always cap with GURT_BOOTSTRAP_LIMIT and sparse progress logs
 use anyhow::Result;
@@ -38,6 +34,8 @@ pub async fn bootstrap_resume() -> Result<()> {
     }
 
     let start = Instant::now();
+    // URLs already in crawl_queue resume first; pending domains without queued work are re-seeded.
+    crate::indexing::resume_queue();
     let pool = crate::services::db().clone();
     let limit = env_usize("GURT_BOOTSTRAP_LIMIT", 200);
     let log_every = env_usize("GURT_BOOTSTRAP_LOG_EVERY", 50);
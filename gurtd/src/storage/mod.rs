@@ -1,5 +1,5 @@
 // NOTE: keep operations minimal and async to avoid blocking request paths.
-// TODO: evolve toward DB-backed crawl queue and multi-server operation.
+// TODO: evolve toward multi-server operation (shard domains across workers).
 
 use anyhow::Result;
 use gurt_db::PgPool;
@@ -40,6 +40,27 @@ pub mod domains {
         Ok(id)
     }
 
+    // Record a domain found through a cross-domain link.
+    // - Inserts it as 'pending' with submission_source 'crawl'.
+    // - Returns true only when the domain was not known before, so callers enqueue each
+    //   discovered domain once instead of re-crawling domains that link to each other.
+    pub async fn insert_discovered_domain(pool: &PgPool, name: &str) -> Result<bool> {
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() {
+            return Ok(false);
+        }
+        let row = sqlx::query(
+            "INSERT INTO domains (name, submission_source, status)
+             VALUES ($1, 'crawl', 'pending')
+             ON CONFLICT DO NOTHING
+             RETURNING id",
+        )
+        .bind(&name)
+        .fetch_optional(pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
     // FIFO set of pending domains on submitted_at.
     pub async fn list_pending_domains(pool: &PgPool, limit: i64) -> Result<Vec<String>> {
         let limit = if limit <= 0 { 0 } else { limit.min(10_000) }; // cap hard to avoid surprise load
@@ -83,30 +104,1133 @@ pub mod domains {
         .await?;
         Ok(())
     }
+
+    // Store the CNAME chain observed when resolving a domain through GURT DNS.
+    // - chain lists the targets in order; depth is its length (0 for a direct record).
+    pub async fn record_cname_chain(pool: &PgPool, name: &str, chain: &[String]) -> Result<()> {
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() {
+            return Ok(());
+        }
+        let depth = chain.len().min(i16::MAX as usize) as i16;
+        sqlx::query(
+            "UPDATE domains
+                SET cname_chain = $2, cname_depth = $3, dns_checked_at = CURRENT_TIMESTAMP,
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE LOWER(name) = LOWER($1)",
+        )
+        .bind(&name)
+        .bind(chain)
+        .bind(depth)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // Recorded CNAME depth per domain, for domains resolved at least once.
+    pub async fn load_cname_depths(pool: &PgPool) -> Result<Vec<(String, i16)>> {
+        let rows =
+            sqlx::query("SELECT name, cname_depth FROM domains WHERE cname_depth IS NOT NULL")
+                .fetch_all(pool)
+                .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for r in rows {
+            out.push((r.try_get("name")?, r.try_get("cname_depth")?));
+        }
+        Ok(out)
+    }
+
+    // Store the outcome of the latest robots.txt fetch for a domain.
+    // - status is None when the fetch failed at the network level.
+    // - checksum/etag are None unless robots.txt was served successfully.
+    pub async fn record_robots(
+        pool: &PgPool,
+        name: &str,
+        status: Option<i16>,
+        checksum: Option<&[u8]>,
+        crawl_delay_ms: Option<i32>,
+        etag: Option<&str>,
+    ) -> Result<()> {
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() {
+            return Ok(());
+        }
+        sqlx::query(
+            "UPDATE domains
+                SET robots_last_status = $2,
+                    robots_checksum = $3,
+                    robots_crawl_delay_ms = $4,
+                    robots_etag = $5,
+                    robots_checked_at = CURRENT_TIMESTAMP,
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE LOWER(name) = LOWER($1)",
+        )
+        .bind(&name)
+        .bind(status)
+        .bind(checksum)
+        .bind(crawl_delay_ms)
+        .bind(etag)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod urls {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    // Stable key for urls.normalized_hash: SHA-256 of the canonical URL.
+    pub fn url_hash(canonical_url: &str) -> Vec<u8> {
+        Sha256::digest(canonical_url.as_bytes()).to_vec()
+    }
+
+    // Flag a URL as blocked (or no longer blocked) by robots.txt.
+    // - Blocking inserts the URL row if needed and stores `reason` in last_fetch_error.
+    // - Unblocking only touches existing rows and clears the robots reason.
+    // Does nothing if the domain row does not exist.
+    pub async fn set_robots_blocked(
+        pool: &PgPool,
+        domain: &str,
+        canonical_url: &str,
+        blocked: bool,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        if !blocked {
+            sqlx::query(
+                "UPDATE urls
+                    SET robots_blocked = FALSE, last_fetch_error = NULL,
+                        updated_at = CURRENT_TIMESTAMP
+                  WHERE canonical_url = $1 AND robots_blocked",
+            )
+            .bind(canonical_url)
+            .execute(pool)
+            .await?;
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO urls (domain_id, canonical_url, normalized_hash, robots_blocked, last_fetch_error)
+             SELECT id, $2, $3, TRUE, $4 FROM domains WHERE LOWER(name) = LOWER($1)
+             ON CONFLICT (canonical_url)
+             DO UPDATE SET
+               robots_blocked = TRUE,
+               last_fetch_error = EXCLUDED.last_fetch_error,
+               updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(domain.trim())
+        .bind(canonical_url)
+        .bind(url_hash(canonical_url))
+        .bind(reason)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod fetches {
+    // Fetch log: one fetch_history row per attempt, plus the latest outcome on urls.
+    use super::*;
+
+    #[derive(Debug, Clone, Default)]
+    pub struct FetchRecord<'a> {
+        pub url_id: i64,
+        pub domain_id: i64,
+        // None when no response was received (connect/TLS/timeout errors).
+        pub status_code: Option<i32>,
+        // One of the fetch_outcome enum labels: 'success', 'redirect' or 'error'.
+        pub outcome: &'a str,
+        pub content_length: Option<i64>,
+        pub content_hash: Option<&'a [u8]>,
+        pub content_type: Option<&'a str>,
+        pub etag: Option<&'a str>,
+        // Last-Modified of the response, unix seconds.
+        pub last_modified: Option<i64>,
+        pub error: Option<&'a str>,
+        pub latency_ms: Option<i32>,
+        pub response_headers: Option<&'a serde_json::Value>,
+        pub worker_id: Option<&'a str>,
+        pub retry_count: i32,
+        // Leave the content metadata to `record_content`, called once the index commit
+        // covering the response lands.
+        pub defer_content: bool,
+    }
+
+    // Append a fetch_history row and update the URL's last_* columns in one transaction.
+    // - content metadata (hash, validators, content type/length) is only overwritten by
+    //   attempts that got a full response, so a timeout, a 304 or a redirect keeps what
+    //   the last response said.
+    // - with `defer_content` it is not written here at all; see `record_content`.
+    pub async fn record_fetch(pool: &PgPool, rec: &FetchRecord<'_>) -> Result<()> {
+        let headers = rec.response_headers.map(|h| h.to_string());
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO fetch_history
+               (url_id, domain_id, status_code, outcome, content_length, content_hash,
+                error, latency_ms, response_headers, worker_id, retry_count)
+             VALUES ($1, $2, $3, $4::fetch_outcome, $5, $6, $7, $8, $9::jsonb, $10, $11)",
+        )
+        .bind(rec.url_id)
+        .bind(rec.domain_id)
+        .bind(rec.status_code)
+        .bind(rec.outcome)
+        .bind(rec.content_length)
+        .bind(rec.content_hash)
+        .bind(rec.error)
+        .bind(rec.latency_ms)
+        .bind(headers)
+        .bind(rec.worker_id)
+        .bind(rec.retry_count.max(0))
+        .execute(&mut *tx)
+        .await?;
+        let responded = !rec.defer_content
+            && rec.outcome != "redirect"
+            && rec.status_code.is_some_and(|c| c != 304);
+        sqlx::query(
+            "UPDATE urls
+                SET last_crawled_at = CURRENT_TIMESTAMP,
+                    last_fetch_status = $2,
+                    last_fetch_outcome = $3::fetch_outcome,
+                    last_fetch_error = $4,
+                    last_crawl_duration_ms = $5,
+                    last_content_hash = CASE WHEN $6 THEN $7 ELSE last_content_hash END,
+                    etag = CASE WHEN $6 THEN $8 ELSE etag END,
+                    content_type = CASE WHEN $6 THEN $9 ELSE content_type END,
+                    content_length = CASE WHEN $6 THEN $10 ELSE content_length END,
+                    last_modified = CASE WHEN $6 THEN to_timestamp($11) ELSE last_modified END,
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE id = $1",
+        )
+        .bind(rec.url_id)
+        .bind(rec.status_code.map(|c| c.clamp(0, i16::MAX as i32) as i16))
+        .bind(rec.outcome)
+        .bind(rec.error)
+        .bind(rec.latency_ms)
+        .bind(responded)
+        .bind(rec.content_hash)
+        .bind(rec.etag)
+        .bind(rec.content_type)
+        .bind(rec.content_length)
+        .bind(rec.last_modified.map(|t| t as f64))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Store the content metadata of a response whose page is now durable in the index.
+    // Written only after the commit: validators stored before it would make the
+    // conditional refetch after a crash see the page as unchanged, and it would never
+    // reach the index.
+    pub async fn record_content(pool: &PgPool, rec: &FetchRecord<'_>) -> Result<()> {
+        sqlx::query(
+            "UPDATE urls
+                SET last_content_hash = $2,
+                    etag = $3,
+                    content_type = $4,
+                    content_length = $5,
+                    last_modified = to_timestamp($6),
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE id = $1",
+        )
+        .bind(rec.url_id)
+        .bind(rec.content_hash)
+        .bind(rec.etag)
+        .bind(rec.content_type)
+        .bind(rec.content_length)
+        .bind(rec.last_modified.map(|t| t as f64))
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+pub mod links {
+    // Link graph (link_edges) and PageRank results (link_authority).
+    use super::*;
+    use sqlx::Row;
+
+    use super::urls::url_hash;
+
+    // Replace the outgoing edges of a page with `links` (canonical URL, anchor text).
+    // - Targets get a urls row if they have none; targets on domains without a domains
+    //   row are skipped, since urls.domain_id is required.
+    // - Self-links are dropped.
+    // Returns the number of edges stored.
+    pub async fn replace_outlinks(
+        pool: &PgPool,
+        src_url_id: i64,
+        links: &[(&str, Option<&str>)],
+    ) -> Result<u64> {
+        let mut urls = Vec::with_capacity(links.len());
+        let mut hosts = Vec::with_capacity(links.len());
+        let mut hashes = Vec::with_capacity(links.len());
+        let mut anchors = Vec::with_capacity(links.len());
+        for (link, anchor) in links {
+            let Some(host) = url::Url::parse(link)
+                .ok()
+                .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+            else {
+                continue;
+            };
+            urls.push(link.to_string());
+            hosts.push(host);
+            hashes.push(url_hash(link));
+            anchors.push(anchor.map(str::to_owned));
+        }
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM link_edges WHERE src_url_id = $1 AND edge_type = 0")
+            .bind(src_url_id)
+            .execute(&mut *tx)
+            .await?;
+        let res = sqlx::query(
+            "WITH v AS (
+                SELECT * FROM UNNEST($2::TEXT[], $3::TEXT[], $4::BYTEA[], $5::TEXT[])
+                    AS v(url, host, hash, anchor)
+             ), ins AS (
+                INSERT INTO urls (domain_id, canonical_url, normalized_hash)
+                SELECT d.id, v.url, v.hash FROM v JOIN domains d ON LOWER(d.name) = v.host
+                ON CONFLICT DO NOTHING
+                RETURNING id, canonical_url
+             ), dst AS (
+                SELECT id, canonical_url FROM ins
+                UNION ALL
+                SELECT u.id, u.canonical_url FROM urls u JOIN v ON u.canonical_url = v.url
+             )
+             INSERT INTO link_edges (src_url_id, dst_url_id, anchor_text)
+             SELECT DISTINCT ON (dst.id) $1, dst.id, v.anchor
+               FROM dst JOIN v ON v.url = dst.canonical_url
+              WHERE dst.id <> $1
+             ON CONFLICT (src_url_id, dst_url_id, edge_type)
+             DO UPDATE SET anchor_text = EXCLUDED.anchor_text",
+        )
+        .bind(src_url_id)
+        .bind(&urls)
+        .bind(&hosts)
+        .bind(&hashes)
+        .bind(&anchors)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(res.rows_affected())
+    }
+
+    // All edges as (source URL, target URL).
+    pub async fn load_edges(pool: &PgPool) -> Result<Vec<(String, String)>> {
+        let rows = sqlx::query(
+            "SELECT s.canonical_url AS src, d.canonical_url AS dst
+               FROM link_edges e
+               JOIN urls s ON s.id = e.src_url_id
+               JOIN urls d ON d.id = e.dst_url_id",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for r in rows {
+            out.push((r.try_get("src")?, r.try_get("dst")?));
+        }
+        Ok(out)
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Authority {
+        pub url: String,
+        pub page_rank: f64,
+        pub inbound_links: i32,
+        pub outbound_links: i32,
+        // Normalized authority in [0, 1], as used by the ranker.
+        pub score: f64,
+    }
+
+    // Upsert link_authority rows, in chunks. Rows for URLs that are not in `rows` keep
+    // their previous values. Returns the number of rows written.
+    pub async fn store_authority(pool: &PgPool, rows: &[Authority]) -> Result<u64> {
+        let mut written = 0;
+        for chunk in rows.chunks(1_000) {
+            let urls: Vec<&str> = chunk.iter().map(|a| a.url.as_str()).collect();
+            let ranks: Vec<f64> = chunk.iter().map(|a| a.page_rank).collect();
+            let inbound: Vec<i32> = chunk.iter().map(|a| a.inbound_links.max(0)).collect();
+            let outbound: Vec<i32> = chunk.iter().map(|a| a.outbound_links.max(0)).collect();
+            let scores: Vec<f64> = chunk.iter().map(|a| a.score).collect();
+            let res = sqlx::query(
+                "INSERT INTO link_authority
+                   (url_id, domain_id, page_rank, inbound_links, outbound_links, score)
+                 SELECT u.id, u.domain_id, v.page_rank, v.inbound, v.outbound, v.score
+                   FROM UNNEST($1::TEXT[], $2::FLOAT8[], $3::INT4[], $4::INT4[], $5::FLOAT8[])
+                        AS v(url, page_rank, inbound, outbound, score)
+                   JOIN urls u ON u.canonical_url = v.url
+                 ON CONFLICT (url_id)
+                 DO UPDATE SET
+                   page_rank = EXCLUDED.page_rank,
+                   inbound_links = EXCLUDED.inbound_links,
+                   outbound_links = EXCLUDED.outbound_links,
+                   score = EXCLUDED.score,
+                   updated_at = CURRENT_TIMESTAMP",
+            )
+            .bind(&urls)
+            .bind(&ranks)
+            .bind(&inbound)
+            .bind(&outbound)
+            .bind(&scores)
+            .execute(pool)
+            .await?;
+            written += res.rows_affected();
+        }
+        Ok(written)
+    }
+
+    // Stored authority scores as (URL, score).
+    pub async fn load_authority(pool: &PgPool) -> Result<Vec<(String, f64)>> {
+        let rows = sqlx::query(
+            "SELECT u.canonical_url, a.score
+               FROM link_authority a
+               JOIN urls u ON u.id = a.url_id",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for r in rows {
+            out.push((r.try_get("canonical_url")?, r.try_get("score")?));
+        }
+        Ok(out)
+    }
 }
 
 pub mod queue {
-    // scaffolding for v2 DB-backed crawl queue kept here with TODOs for future work
-    // current indexer enqueues in-memory and commits to Tantivy directly
-    // TODO: implement urls + crawl_queue population and leasing
-
-    #![allow(dead_code)]
-    // use sqlx::Row;
-
-    // Example signature for future use:
-    // pub async fn enqueue_url(pool: &PgPool, domain_id: i64, canonical_url: &str, priority: i32) -> Result<i64> {
-    //     // TODO: compute normalized_hash, insert into urls, then into crawl_queue (ON CONFLICT DO NOTHING)
-    //     // Return url_id
-    //     unimplemented!()
-    // }
-
-    // pub async fn lease_next(pool: &PgPool, worker_id: &str) -> Result<Option<(i64 /*url_id*/, String /*url*/)>> {
-    //     // TODO: SELECT ... FOR UPDATE SKIP LOCKED
-    //     unimplemented!()
-    // }
-
-    // pub async fn clear_stale_locks(pool: &PgPool, older_than_seconds: i64) -> Result<u64> {
-    //     // TODO: UPDATE crawl_queue SET locked_by = NULL, locked_at = NULL WHERE ... RETURNING count
-    //     unimplemented!()
-    // }
-}
\ No newline at end of file
+    // DB-backed crawl queue over urls + crawl_queue.
+    // - Workers lease one row at a time with FOR UPDATE SKIP LOCKED, so several workers
+    //   (or servers) can share the queue without handing out the same URL twice.
+    // - A leased row stays in the table until it is acked; after a crash the lock is
+    //   released (release_worker_locks / clear_stale_locks) and the URL is leased again.
+    use super::*;
+    use sqlx::Row;
+
+    use super::urls::url_hash;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LeasedUrl {
+        pub queue_id: i64,
+        pub url_id: i64,
+        pub domain_id: i64,
+        pub domain: String,
+        pub url: String,
+        pub depth: i32,
+        // Failed attempts before this lease.
+        pub attempts: i32,
+        // urls.robots_blocked, set when an earlier robots.txt disallowed the URL.
+        pub robots_blocked: bool,
+        // Validators of the last successful fetch, for a conditional request. Only set
+        // on a first attempt, so a retry after a failed index commit refetches the page.
+        pub etag: Option<String>,
+        pub last_modified: Option<i64>,
+        pub content_hash: Option<Vec<u8>>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum NackOutcome {
+        // Requeued; available again after the backoff.
+        Retrying,
+        // max_attempts reached; the row was dropped and the URL marked as errored.
+        GaveUp,
+    }
+
+    // Insert (or find) the URL row and queue it. A URL that is already queued keeps its
+    // existing row. Returns the url id.
+    pub async fn enqueue_url(
+        pool: &PgPool,
+        domain_id: i64,
+        canonical_url: &str,
+        priority: i32,
+        depth: i32,
+    ) -> Result<i64> {
+        let row = sqlx::query(
+            "INSERT INTO urls (domain_id, canonical_url, normalized_hash)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (canonical_url)
+             DO UPDATE SET updated_at = CURRENT_TIMESTAMP
+             RETURNING id",
+        )
+        .bind(domain_id)
+        .bind(canonical_url)
+        .bind(url_hash(canonical_url))
+        .fetch_one(pool)
+        .await?;
+        let url_id: i64 = row.try_get("id")?;
+        sqlx::query(
+            "INSERT INTO crawl_queue (url_id, domain_id, priority, depth)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (url_id) DO NOTHING",
+        )
+        .bind(url_id)
+        .bind(domain_id)
+        .bind(priority.max(0))
+        .bind(depth.max(0))
+        .execute(pool)
+        .await?;
+        Ok(url_id)
+    }
+
+    // Queue a seed URL (the root or a sitemap entry) with its sitemap priority, which
+    // is also stored as urls.fetch_priority. A URL crawled before is only queued again
+    // when `lastmod` (unix seconds) says it changed since; otherwise its recrawl
+    // schedule decides. Returns the url id and whether the URL is queued.
+    pub async fn enqueue_seed_url(
+        pool: &PgPool,
+        domain_id: i64,
+        canonical_url: &str,
+        priority: i32,
+        lastmod: Option<i64>,
+    ) -> Result<(i64, bool)> {
+        let row = sqlx::query(
+            "WITH url AS (
+                INSERT INTO urls (domain_id, canonical_url, normalized_hash, fetch_priority)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (canonical_url) DO UPDATE
+                   SET fetch_priority = EXCLUDED.fetch_priority,
+                       updated_at = CURRENT_TIMESTAMP
+                RETURNING id, last_crawled_at
+             ), queued AS (
+                INSERT INTO crawl_queue (url_id, domain_id, priority, depth)
+                SELECT url.id, $1, $4, 0
+                  FROM url
+                 WHERE url.last_crawled_at IS NULL
+                    OR $5::BIGINT IS NULL
+                    OR url.last_crawled_at < to_timestamp($5::BIGINT)
+                ON CONFLICT (url_id) DO UPDATE SET priority = EXCLUDED.priority
+                RETURNING id
+             )
+             SELECT url.id, EXISTS (SELECT 1 FROM queued) AS queued FROM url",
+        )
+        .bind(domain_id)
+        .bind(canonical_url)
+        .bind(url_hash(canonical_url))
+        .bind(priority.max(0))
+        .bind(lastmod)
+        .fetch_one(pool)
+        .await?;
+        Ok((row.try_get("id")?, row.try_get("queued")?))
+    }
+
+    // Queue a URL found through a link, but only if it was never crawled, queued or
+    // blocked before and the domain has fewer than `max_urls` crawled or queued URLs.
+    // (The row itself may already exist as a link_edges target.) Returns true when it
+    // was queued.
+    pub async fn enqueue_discovered_url(
+        pool: &PgPool,
+        domain_id: i64,
+        canonical_url: &str,
+        depth: i32,
+        max_urls: i64,
+    ) -> Result<bool> {
+        let row = sqlx::query(
+            "WITH budget AS (
+                SELECT COUNT(*) AS used
+                  FROM urls u
+                 WHERE u.domain_id = $1
+                   AND (u.last_crawled_at IS NOT NULL
+                        OR EXISTS (SELECT 1 FROM crawl_queue q WHERE q.url_id = u.id))
+             ), url AS (
+                INSERT INTO urls (domain_id, canonical_url, normalized_hash)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (canonical_url) DO UPDATE SET updated_at = urls.updated_at
+                RETURNING id, last_crawled_at, robots_blocked
+             )
+             INSERT INTO crawl_queue (url_id, domain_id, depth)
+             SELECT url.id, $1, $4
+               FROM url, budget
+              WHERE url.last_crawled_at IS NULL AND NOT url.robots_blocked
+                AND budget.used < $5
+             ON CONFLICT (url_id) DO NOTHING
+             RETURNING id",
+        )
+        .bind(domain_id)
+        .bind(canonical_url)
+        .bind(url_hash(canonical_url))
+        .bind(depth.max(0))
+        .bind(max_urls)
+        .fetch_optional(pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    // Lease the next available URL: highest priority first, then oldest available_at.
+    // URLs of domains in `skip_domains` (e.g. hosts already at their concurrency limit)
+    // are left for later.
+    pub async fn lease_next(
+        pool: &PgPool,
+        worker_id: &str,
+        skip_domains: &[i64],
+    ) -> Result<Option<LeasedUrl>> {
+        let row = sqlx::query(
+            "WITH next AS (
+                SELECT id
+                  FROM crawl_queue
+                 WHERE locked_by IS NULL AND available_at <= CURRENT_TIMESTAMP
+                   AND NOT (domain_id = ANY($2))
+                 ORDER BY priority DESC, available_at ASC, id ASC
+                 LIMIT 1
+                 FOR UPDATE SKIP LOCKED
+             )
+             UPDATE crawl_queue q
+                SET locked_by = $1, locked_at = CURRENT_TIMESTAMP
+               FROM next, urls u, domains d
+              WHERE q.id = next.id AND u.id = q.url_id AND d.id = q.domain_id
+             RETURNING q.id, q.url_id, q.domain_id, q.depth, q.attempts,
+                       u.canonical_url, d.name, u.robots_blocked,
+                       u.last_fetch_outcome = 'success' AND q.attempts = 0 AS conditional,
+                       u.etag, EXTRACT(EPOCH FROM u.last_modified)::BIGINT AS last_modified,
+                       u.last_content_hash",
+        )
+        .bind(worker_id)
+        .bind(skip_domains)
+        .fetch_optional(pool)
+        .await?;
+        let Some(r) = row else {
+            return Ok(None);
+        };
+        let conditional: bool = r.try_get("conditional")?;
+        let mut job = LeasedUrl {
+            queue_id: r.try_get("id")?,
+            url_id: r.try_get("url_id")?,
+            domain_id: r.try_get("domain_id")?,
+            domain: r.try_get("name")?,
+            url: r.try_get("canonical_url")?,
+            depth: r.try_get("depth")?,
+            attempts: r.try_get("attempts")?,
+            robots_blocked: r.try_get("robots_blocked")?,
+            etag: None,
+            last_modified: None,
+            content_hash: None,
+        };
+        if conditional {
+            job.etag = r.try_get("etag")?;
+            job.last_modified = r.try_get("last_modified")?;
+            job.content_hash = r.try_get("last_content_hash")?;
+        }
+        Ok(Some(job))
+    }
+
+    // Finish a leased job. Only the worker holding the lease can ack it.
+    pub async fn ack(pool: &PgPool, queue_id: i64, worker_id: &str) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM crawl_queue WHERE id = $1 AND locked_by = $2")
+            .bind(queue_id)
+            .bind(worker_id)
+            .execute(pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    // Hand a leased job back untouched (no attempt counted), e.g. when its domain has
+    // no free fetch slot after all.
+    pub async fn release(pool: &PgPool, queue_id: i64, worker_id: &str) -> Result<bool> {
+        let res = sqlx::query(
+            "UPDATE crawl_queue SET locked_by = NULL, locked_at = NULL
+              WHERE id = $1 AND locked_by = $2",
+        )
+        .bind(queue_id)
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    // Return a failed job to the queue with exponential backoff
+    // (base_backoff_secs * 2^attempts, capped at one day). Once max_attempts is reached
+    // the row is dropped and the URL is marked as errored.
+    pub async fn nack(
+        pool: &PgPool,
+        queue_id: i64,
+        worker_id: &str,
+        error: &str,
+        base_backoff_secs: i64,
+    ) -> Result<NackOutcome> {
+        let row = sqlx::query(
+            "UPDATE crawl_queue
+                SET attempts = attempts + 1,
+                    locked_by = NULL,
+                    locked_at = NULL,
+                    last_error = $3,
+                    available_at = CURRENT_TIMESTAMP
+                        + LEAST($4::BIGINT * POWER(2, LEAST(attempts, 20))::BIGINT, 86400)
+                          * INTERVAL '1 second'
+              WHERE id = $1 AND locked_by = $2
+             RETURNING url_id, attempts, max_attempts",
+        )
+        .bind(queue_id)
+        .bind(worker_id)
+        .bind(error)
+        .bind(base_backoff_secs.max(1))
+        .fetch_optional(pool)
+        .await?;
+        let Some(r) = row else {
+            // Lease lost (e.g. cleared as stale); whoever holds it now decides.
+            return Ok(NackOutcome::Retrying);
+        };
+        let attempts: i32 = r.try_get("attempts")?;
+        let max_attempts: i32 = r.try_get("max_attempts")?;
+        if attempts < max_attempts {
+            return Ok(NackOutcome::Retrying);
+        }
+        let url_id: i64 = r.try_get("url_id")?;
+        sqlx::query("DELETE FROM crawl_queue WHERE id = $1")
+            .bind(queue_id)
+            .execute(pool)
+            .await?;
+        sqlx::query(
+            "UPDATE urls
+                SET last_fetch_outcome = 'error', last_fetch_error = $2,
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE id = $1",
+        )
+        .bind(url_id)
+        .bind(error)
+        .execute(pool)
+        .await?;
+        Ok(NackOutcome::GaveUp)
+    }
+
+    // Release leases older than `older_than_seconds` (their worker is presumed dead).
+    // Returns the number of rows released.
+    pub async fn clear_stale_locks(pool: &PgPool, older_than_seconds: i64) -> Result<u64> {
+        let res = sqlx::query(
+            "UPDATE crawl_queue
+                SET locked_by = NULL, locked_at = NULL
+              WHERE locked_at IS NOT NULL
+                AND locked_at < CURRENT_TIMESTAMP - $1::BIGINT * INTERVAL '1 second'",
+        )
+        .bind(older_than_seconds.max(0))
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+
+    // Release every lease held by `worker_id` or one of its fetch workers
+    // (`<worker_id>/<n>`), e.g. left behind by a previous run of the same process.
+    // Returns the number of rows released.
+    pub async fn release_worker_locks(pool: &PgPool, worker_id: &str) -> Result<u64> {
+        let res = sqlx::query(
+            "UPDATE crawl_queue SET locked_by = NULL, locked_at = NULL
+              WHERE locked_by = $1 OR LEFT(locked_by, LENGTH($1) + 1) = $1 || '/'",
+        )
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+
+    // Whether the domain still has queued (or leased) URLs.
+    pub async fn domain_has_pending(pool: &PgPool, domain_id: i64) -> Result<bool> {
+        let row = sqlx::query(
+            "SELECT EXISTS (SELECT 1 FROM crawl_queue WHERE domain_id = $1) AS pending",
+        )
+        .bind(domain_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(row.try_get("pending")?)
+    }
+}
+
+pub mod recrawl {
+    // recrawl_queue: periodic recrawls (kind 'recrawl') and deferred re-renders
+    // (kind 'render'), at most one of each per URL.
+    // - Every crawled URL has a 'recrawl' row holding its adaptive interval; when due it
+    //   is promoted into crawl_queue and rescheduled after the fetch.
+    // - Pages whose render timed out are indexed statically and get a 'render' row; a
+    //   later pass refetches and renders them with a larger budget. Leasing works like
+    //   crawl_queue (FOR UPDATE SKIP LOCKED, locked_by/locked_at).
+    // - urls.render_outcome tracks each URL: deferred -> rendered | gave_up. A URL that
+    //   gave up keeps its static version in the index.
+    use super::*;
+    use sqlx::Row;
+
+    use super::queue::{LeasedUrl, NackOutcome};
+
+    // Queue a re-render of `canonical_url`, first available after `delay_secs`. A URL
+    // that is already queued keeps its row (and attempts). Returns false if the URL is
+    // unknown or already queued.
+    pub async fn schedule_render(
+        pool: &PgPool,
+        canonical_url: &str,
+        reason: &str,
+        delay_secs: i64,
+        max_attempts: i32,
+    ) -> Result<bool> {
+        let row = sqlx::query(
+            "WITH u AS (
+                UPDATE urls
+                   SET render_outcome = 'deferred', updated_at = CURRENT_TIMESTAMP
+                 WHERE canonical_url = $1
+                RETURNING id, domain_id
+             )
+             INSERT INTO recrawl_queue
+               (url_id, domain_id, kind, available_at, reason, max_attempts)
+             SELECT id, domain_id, 'render',
+                    CURRENT_TIMESTAMP + $3::BIGINT * INTERVAL '1 second', $2, $4
+               FROM u
+             ON CONFLICT (url_id, kind) DO NOTHING
+             RETURNING id",
+        )
+        .bind(canonical_url)
+        .bind(reason)
+        .bind(delay_secs.max(0))
+        .bind(max_attempts.max(1))
+        .fetch_optional(pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    // Lease up to `limit` due re-renders, highest priority and oldest first.
+    pub async fn lease_renders(
+        pool: &PgPool,
+        worker_id: &str,
+        limit: i64,
+    ) -> Result<Vec<LeasedUrl>> {
+        let rows = sqlx::query(
+            "WITH next AS (
+                SELECT id
+                  FROM recrawl_queue
+                 WHERE kind = 'render' AND locked_by IS NULL
+                   AND available_at <= CURRENT_TIMESTAMP
+                 ORDER BY priority DESC, available_at ASC, id ASC
+                 LIMIT $2
+                 FOR UPDATE SKIP LOCKED
+             )
+             UPDATE recrawl_queue q
+                SET locked_by = $1, locked_at = CURRENT_TIMESTAMP
+               FROM next, urls u, domains d
+              WHERE q.id = next.id AND u.id = q.url_id AND d.id = q.domain_id
+             RETURNING q.id, q.url_id, q.domain_id, q.attempts, u.canonical_url, d.name,
+                       u.robots_blocked",
+        )
+        .bind(worker_id)
+        .bind(limit.max(1))
+        .fetch_all(pool)
+        .await?;
+        rows.into_iter()
+            .map(|r| {
+                Ok(LeasedUrl {
+                    queue_id: r.try_get("id")?,
+                    url_id: r.try_get("url_id")?,
+                    domain_id: r.try_get("domain_id")?,
+                    domain: r.try_get("name")?,
+                    url: r.try_get("canonical_url")?,
+                    depth: 0,
+                    attempts: r.try_get("attempts")?,
+                    robots_blocked: r.try_get("robots_blocked")?,
+                    // A re-render needs the body, so it never fetches conditionally.
+                    etag: None,
+                    last_modified: None,
+                    content_hash: None,
+                })
+            })
+            .collect()
+    }
+
+    // The page rendered within budget: drop the row and mark the URL rendered.
+    pub async fn mark_rendered(pool: &PgPool, queue_id: i64, worker_id: &str) -> Result<bool> {
+        let row = sqlx::query(
+            "WITH done AS (
+                DELETE FROM recrawl_queue WHERE id = $1 AND locked_by = $2
+                RETURNING url_id, attempts
+             )
+             UPDATE urls u
+                SET render_outcome = 'rendered', render_attempts = done.attempts + 1,
+                    rendered_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+               FROM done
+              WHERE u.id = done.url_id
+             RETURNING u.id",
+        )
+        .bind(queue_id)
+        .bind(worker_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    // The re-render failed or timed out again: retry after base_backoff_secs * 2^attempts
+    // (capped at one day). Once max_attempts is reached the row is dropped and the URL
+    // marked gave_up; its static version stays indexed.
+    pub async fn retry_render(
+        pool: &PgPool,
+        queue_id: i64,
+        worker_id: &str,
+        error: &str,
+        base_backoff_secs: i64,
+    ) -> Result<NackOutcome> {
+        let row = sqlx::query(
+            "UPDATE recrawl_queue
+                SET attempts = attempts + 1,
+                    locked_by = NULL,
+                    locked_at = NULL,
+                    last_error = $3,
+                    available_at = CURRENT_TIMESTAMP
+                        + LEAST($4::BIGINT * POWER(2, LEAST(attempts, 20))::BIGINT, 86400)
+                          * INTERVAL '1 second'
+              WHERE id = $1 AND locked_by = $2
+             RETURNING url_id, attempts, max_attempts",
+        )
+        .bind(queue_id)
+        .bind(worker_id)
+        .bind(error)
+        .bind(base_backoff_secs.max(1))
+        .fetch_optional(pool)
+        .await?;
+        let Some(r) = row else {
+            return Ok(NackOutcome::Retrying);
+        };
+        let url_id: i64 = r.try_get("url_id")?;
+        let attempts: i32 = r.try_get("attempts")?;
+        let max_attempts: i32 = r.try_get("max_attempts")?;
+        let gave_up = attempts >= max_attempts;
+        if gave_up {
+            sqlx::query("DELETE FROM recrawl_queue WHERE id = $1")
+                .bind(queue_id)
+                .execute(pool)
+                .await?;
+        }
+        sqlx::query(
+            "UPDATE urls
+                SET render_attempts = $2,
+                    render_outcome = CASE WHEN $3 THEN 'gave_up' ELSE render_outcome END,
+                    updated_at = CURRENT_TIMESTAMP
+              WHERE id = $1",
+        )
+        .bind(url_id)
+        .bind(attempts)
+        .bind(gave_up)
+        .execute(pool)
+        .await?;
+        Ok(if gave_up {
+            NackOutcome::GaveUp
+        } else {
+            NackOutcome::Retrying
+        })
+    }
+
+    // Same lease recovery as queue::clear_stale_locks / queue::release_worker_locks.
+    pub async fn clear_stale_locks(pool: &PgPool, older_than_seconds: i64) -> Result<u64> {
+        let res = sqlx::query(
+            "UPDATE recrawl_queue
+                SET locked_by = NULL, locked_at = NULL
+              WHERE locked_at IS NOT NULL
+                AND locked_at < CURRENT_TIMESTAMP - $1::BIGINT * INTERVAL '1 second'",
+        )
+        .bind(older_than_seconds.max(0))
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+
+    pub async fn release_worker_locks(pool: &PgPool, worker_id: &str) -> Result<u64> {
+        let res = sqlx::query(
+            "UPDATE recrawl_queue SET locked_by = NULL, locked_at = NULL
+              WHERE locked_by = $1 OR LEFT(locked_by, LENGTH($1) + 1) = $1 || '/'",
+        )
+        .bind(worker_id)
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+    // Set a URL's starting recrawl interval (e.g. from sitemap hints) unless it has one.
+    pub async fn seed_interval(
+        pool: &PgPool,
+        url_id: i64,
+        domain_id: i64,
+        interval_secs: i32,
+    ) -> Result<bool> {
+        let res = sqlx::query(
+            "INSERT INTO recrawl_queue
+               (url_id, domain_id, kind, available_at, recrawl_interval_seconds)
+             VALUES ($1, $2, 'recrawl', CURRENT_TIMESTAMP + $3::BIGINT * INTERVAL '1 second', $3)
+             ON CONFLICT (url_id, kind) DO NOTHING",
+        )
+        .bind(url_id)
+        .bind(domain_id)
+        .bind(interval_secs.max(1))
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    // Current recrawl interval of a URL, falling back to its domain's
+    // crawl_interval_seconds. None when neither is set.
+    pub async fn recrawl_interval(pool: &PgPool, url_id: i64) -> Result<Option<i32>> {
+        let row = sqlx::query(
+            "SELECT COALESCE(r.recrawl_interval_seconds, d.crawl_interval_seconds) AS secs
+               FROM urls u
+               JOIN domains d ON d.id = u.domain_id
+               LEFT JOIN recrawl_queue r ON r.url_id = u.id AND r.kind = 'recrawl'
+              WHERE u.id = $1",
+        )
+        .bind(url_id)
+        .fetch_optional(pool)
+        .await?;
+        match row {
+            Some(r) => Ok(r.try_get::<Option<i32>, _>("secs")?.filter(|s| *s > 0)),
+            None => Ok(None),
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FetchSample {
+        // Unix seconds.
+        pub fetched_at: i64,
+        pub status_code: Option<i32>,
+        pub content_hash: Option<Vec<u8>>,
+    }
+
+    // The last `limit` successful fetches of a URL, oldest first.
+    pub async fn fetch_samples(pool: &PgPool, url_id: i64, limit: i64) -> Result<Vec<FetchSample>> {
+        let rows = sqlx::query(
+            "SELECT EXTRACT(EPOCH FROM fetched_at)::BIGINT AS fetched_at, status_code,
+                    content_hash
+               FROM fetch_history
+              WHERE url_id = $1 AND outcome = 'success'
+              ORDER BY fetched_at DESC, id DESC
+              LIMIT $2",
+        )
+        .bind(url_id)
+        .bind(limit.max(1))
+        .fetch_all(pool)
+        .await?;
+        let mut samples = rows
+            .into_iter()
+            .map(|r| {
+                Ok(FetchSample {
+                    fetched_at: r.try_get("fetched_at")?,
+                    status_code: r.try_get("status_code")?,
+                    content_hash: r.try_get("content_hash")?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        samples.reverse();
+        Ok(samples)
+    }
+
+    // Next recrawl of a URL in `interval_secs`, remembering the interval and the depth
+    // the URL was crawled at.
+    pub async fn schedule_recrawl(
+        pool: &PgPool,
+        url_id: i64,
+        domain_id: i64,
+        depth: i32,
+        interval_secs: i32,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO recrawl_queue
+               (url_id, domain_id, kind, available_at, recrawl_interval_seconds, depth)
+             VALUES ($1, $2, 'recrawl', CURRENT_TIMESTAMP + $3::BIGINT * INTERVAL '1 second',
+                     $3, $4)
+             ON CONFLICT (url_id, kind) DO UPDATE
+                SET available_at = EXCLUDED.available_at,
+                    recrawl_interval_seconds = EXCLUDED.recrawl_interval_seconds,
+                    depth = EXCLUDED.depth,
+                    attempts = 0",
+        )
+        .bind(url_id)
+        .bind(domain_id)
+        .bind(interval_secs.max(1))
+        .bind(depth.max(0))
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // The search index was rebuilt empty: forget the validators of every URL, so the
+    // next fetch is unconditional and indexes the page, and make every recrawl due now.
+    // Returns the number of recrawls made due.
+    pub async fn reindex_all(pool: &PgPool) -> Result<u64> {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "UPDATE urls SET etag = NULL, last_modified = NULL, last_content_hash = NULL
+              WHERE etag IS NOT NULL OR last_modified IS NOT NULL
+                 OR last_content_hash IS NOT NULL",
+        )
+        .execute(&mut *tx)
+        .await?;
+        let res = sqlx::query(
+            "UPDATE recrawl_queue SET available_at = CURRENT_TIMESTAMP
+              WHERE kind = 'recrawl' AND available_at > CURRENT_TIMESTAMP",
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(res.rows_affected())
+    }
+
+    // Move up to `limit` due recrawls into crawl_queue for the fetch workers, skipping
+    // blocked domains and robots-blocked URLs. A promoted row is pushed one interval
+    // ahead, so it is not promoted again if the fetch never reschedules it (e.g. the
+    // fetch keeps failing). Returns the number of URLs queued.
+    pub async fn promote_due(pool: &PgPool, limit: i64) -> Result<u64> {
+        let res = sqlx::query(
+            "WITH due AS (
+                SELECT r.id
+                  FROM recrawl_queue r
+                  JOIN urls u ON u.id = r.url_id
+                  JOIN domains d ON d.id = r.domain_id
+                 WHERE r.kind = 'recrawl' AND r.locked_by IS NULL
+                   AND r.available_at <= CURRENT_TIMESTAMP
+                   AND d.status <> 'blocked' AND NOT u.robots_blocked
+                 ORDER BY r.priority DESC, r.available_at ASC
+                 LIMIT $1
+                 FOR UPDATE OF r SKIP LOCKED
+             ), moved AS (
+                UPDATE recrawl_queue r
+                   SET available_at = CURRENT_TIMESTAMP
+                       + COALESCE(r.recrawl_interval_seconds, 86400) * INTERVAL '1 second'
+                  FROM due
+                 WHERE r.id = due.id
+                RETURNING r.url_id, r.domain_id, r.priority, r.depth
+             )
+             INSERT INTO crawl_queue (url_id, domain_id, priority, depth)
+             SELECT m.url_id, m.domain_id, GREATEST(m.priority, u.fetch_priority), m.depth
+               FROM moved m
+               JOIN urls u ON u.id = m.url_id
+             ON CONFLICT (url_id) DO NOTHING",
+        )
+        .bind(limit.max(1))
+        .execute(pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+}
+
+pub mod cert_pins {
+    // Trust-on-first-use pins: the SHA-256 of the first leaf certificate seen per host.
+    use super::*;
+    use sqlx::Row;
+
+    // Pin `sha256` for `host` unless a pin exists; returns the pinned fingerprint.
+    // - last_seen_at is bumped only when the presented certificate matches the pin.
+    pub async fn pin_first_use(pool: &PgPool, host: &str, sha256: &str) -> Result<String> {
+        let row = sqlx::query(
+            "INSERT INTO cert_pins (host, sha256)
+             VALUES (LOWER($1), $2)
+             ON CONFLICT (host)
+             DO UPDATE SET
+               last_seen_at = CASE WHEN cert_pins.sha256 = EXCLUDED.sha256
+                                   THEN CURRENT_TIMESTAMP ELSE cert_pins.last_seen_at END
+             RETURNING sha256",
+        )
+        .bind(host)
+        .bind(sha256)
+        .fetch_one(pool)
+        .await?;
+        Ok(row.try_get("sha256")?)
+    }
+
+    // Note a certificate that did not match the pin; the pin itself is left alone.
+    pub async fn record_mismatch(pool: &PgPool, host: &str, sha256: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE cert_pins
+                SET mismatch_sha256 = $2, mismatch_count = mismatch_count + 1,
+                    last_mismatch_at = CURRENT_TIMESTAMP
+              WHERE host = LOWER($1)",
+        )
+        .bind(host)
+        .bind(sha256)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -41,7 +41,7 @@ fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
         return Err(anyhow!("no certificates found in {path}"));
     }
     // Log basic info and fingerprint of first cert for debugging
-    if let Some(first) = certs.get(0) {
+    if let Some(first) = certs.first() {
         let mut hasher = Sha256::new();
         hasher.update(first.as_ref());
         let digest = hasher.finalize();
@@ -1,21 +1,15 @@
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use gurtd::crawler::client::{ClientError, DynStream, GurtClient};
+use gurtd::crawler::client::{ClientError, ClientRequest, ConnectorFn, DynStream, GurtClient};
 
 #[tokio::test]
 async fn client_parses_success_response() {
     let (mut server, client_side) = tokio::io::duplex(1 << 16);
     let shared = Arc::new(Mutex::new(Some(client_side)));
     // connector that returns the client side of the duplex stream once
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = {
+    let connector: Arc<ConnectorFn> = {
         let shared = shared.clone();
         Arc::new(move |_host: &str, _port: u16| {
             let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
@@ -55,11 +49,7 @@ async fn client_parses_success_response() {
 async fn client_errors_on_oversize_body() {
     let (mut server, client_side) = tokio::io::duplex(1 << 16);
     let shared = Arc::new(Mutex::new(Some(client_side)));
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = {
+    let connector: Arc<ConnectorFn> = {
         let shared = shared.clone();
         Arc::new(move |_host: &str, _port: u16| {
             let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
@@ -99,11 +89,7 @@ async fn client_retries_on_timeout() {
     static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
 
     // connector that never produces a stream (simulates a hang until timeout)
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = Arc::new(|_host: &str, _port: u16| {
+    let connector: Arc<ConnectorFn> = Arc::new(|_host: &str, _port: u16| {
         ATTEMPTS.fetch_add(1, Ordering::Relaxed);
         Box::pin(async move {
             tokio::time::sleep(Duration::from_millis(100)).await;
@@ -122,3 +108,127 @@ async fn client_retries_on_timeout() {
         matches!(res, Err(ClientError::Timeout)) || matches!(res, Err(ClientError::Connection))
     );
 }
+
+#[tokio::test]
+async fn client_follows_relative_redirect() {
+    // One duplex stream per request of the chain.
+    let (mut first, first_cli) = tokio::io::duplex(1 << 16);
+    let (mut second, second_cli) = tokio::io::duplex(1 << 16);
+    let streams = Arc::new(Mutex::new(vec![second_cli, first_cli]));
+    let connector: Arc<ConnectorFn> = {
+        let streams = streams.clone();
+        Arc::new(move |_host: &str, _port: u16| {
+            let cli = streams.lock().unwrap().pop().ok_or(ClientError::Connection);
+            Box::pin(async move { cli.map(|s| Box::pin(s) as DynStream) })
+        })
+    };
+    let client = GurtClient::new_test(connector);
+    let fut = client.fetch_with_retries("gurt://example.real/", 0);
+
+    let srv = async move {
+        let hs = b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n";
+        first.write_all(hs).await.unwrap();
+        assert!(read_get(&mut first).await.starts_with("GET / "));
+        first
+            .write_all(b"GURT/1.0.0 301 MOVED_PERMANENTLY\r\nlocation: /home\r\n\r\n")
+            .await
+            .unwrap();
+
+        second.write_all(hs).await.unwrap();
+        assert!(read_get(&mut second).await.starts_with("GET /home "));
+        second
+            .write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 4\r\n\r\nhome")
+            .await
+            .unwrap();
+    };
+
+    let (res, _) = tokio::join!(fut, srv);
+    let resp = res.expect("client ok");
+    assert_eq!(resp.code, 200);
+    assert_eq!(resp.body, b"home");
+}
+
+/// Read past the handshake up to the end of the GET request; returns the request.
+async fn read_get(stream: &mut tokio::io::DuplexStream) -> String {
+    let mut text = String::new();
+    let mut buf = [0u8; 256];
+    loop {
+        if let Some(at) = text.find("GET ") {
+            if text[at..].ends_with("\r\n\r\n") {
+                return text[at..].to_string();
+            }
+        }
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0, "client closed before sending GET");
+        text.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+}
+
+#[tokio::test]
+async fn client_sends_post_body_and_headers() {
+    let (mut server, client_side) = tokio::io::duplex(1 << 16);
+    let shared = Arc::new(Mutex::new(Some(client_side)));
+    let connector: Arc<ConnectorFn> = {
+        let shared = shared.clone();
+        Arc::new(move |_host: &str, _port: u16| {
+            let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
+            Box::pin(async move { cli.map(|s| Box::pin(s) as DynStream) })
+        })
+    };
+    let client = GurtClient::new_test(connector);
+    let req = ClientRequest::post(
+        "gurt://example.real:5000/resolve?x=1",
+        b"{\"a\":1}".to_vec(),
+    )
+    .header("content-type", "application/json");
+    let fut = client.execute(req, 0);
+
+    let srv = async move {
+        server
+            .write_all(b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n")
+            .await
+            .unwrap();
+        let mut text = String::new();
+        let mut buf = [0u8; 256];
+        while !text.ends_with("{\"a\":1}") {
+            let n = server.read(&mut buf).await.unwrap();
+            assert!(n > 0, "client closed before sending the body");
+            text.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        server
+            .write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+        text
+    };
+
+    let (res, text) = tokio::join!(fut, srv);
+    assert_eq!(res.expect("client ok").body, b"ok");
+    let request = &text[text.find("POST ").expect("POST sent")..];
+    assert!(request.starts_with("POST /resolve?x=1 GURT/1.0.0\r\n"));
+    assert!(request.contains("host: example.real:5000\r\n"));
+    assert!(request.contains("content-type: application/json\r\n"));
+    assert!(request.contains("content-length: 7\r\n"));
+}
+
+#[tokio::test]
+async fn tcp_connector_fails_over_to_the_next_address() {
+    use gurtd::crawler::client::tcp_connector;
+    use gurtd::crawler::resolver::{Resolver, StaticResolver};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // Nothing listens on 127.0.0.2 for this port, so the first connect is refused.
+    let hosts = StaticResolver::new()
+        .with_host("example.real", "127.0.0.2".parse().unwrap())
+        .with_host("example.real", "127.0.0.1".parse().unwrap());
+    let connector = tcp_connector(Some(Arc::new(hosts) as Arc<dyn Resolver>));
+    let (stream, accepted) = tokio::join!(connector("example.real", port), listener.accept());
+    assert!(stream.is_ok());
+    assert!(accepted.is_ok());
+
+    assert!(matches!(
+        connector("unknown.real", port).await,
+        Err(ClientError::Connection)
+    ));
+}
@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use gurtd::crawler::client::{ClientError, ConnectorFn, DynStream, GurtClient, UpgradeFn};
+use gurtd::crawler::tls::{
+    load_roots, tofu_tls_upgrade, verifying_tls_upgrade, PinMismatch, PinStore, TlsMode,
+    TlsSettings,
+};
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Connector serving each connection with the next certificate of `certs` (`leaf` or
+/// `other`): HANDSHAKE, TLS, then a 200 for the request.
+fn serving(certs: &[&str]) -> Arc<ConnectorFn> {
+    let certs = Arc::new(Mutex::new(
+        certs
+            .iter()
+            .rev()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>(),
+    ));
+    Arc::new(move |_host: &str, _port: u16| {
+        let cert = certs.lock().unwrap().pop();
+        Box::pin(async move {
+            let cert = cert.ok_or(ClientError::Connection)?;
+            let acceptor = gurtd::tls::TlsConfig::load(
+                &fixture(&format!("{cert}.pem")),
+                &fixture(&format!("{cert}-key.pem")),
+            )
+            .unwrap()
+            .into_acceptor();
+            let (mut server, client) = tokio::io::duplex(1 << 16);
+            tokio::spawn(async move {
+                read_head(&mut server).await;
+                server
+                    .write_all(b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n")
+                    .await
+                    .unwrap();
+                let Ok(mut tls) = acceptor.accept(server).await else {
+                    return;
+                };
+                read_head(&mut tls).await;
+                tls.write_all(b"GURT/1.0.0 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+                tls.shutdown().await.ok();
+            });
+            Ok(Box::pin(client) as DynStream)
+        })
+    })
+}
+
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).await.unwrap_or(0) == 0 {
+            return;
+        }
+        head.push(byte[0]);
+    }
+}
+
+async fn fetch(client: &GurtClient) -> Result<Vec<u8>, ClientError> {
+    client
+        .fetch_with_retries("gurt://example.real/", 0)
+        .await
+        .map(|r| r.body)
+}
+
+fn client(certs: &[&str], upgrade: Arc<UpgradeFn>) -> GurtClient {
+    GurtClient::new_test(serving(certs)).with_upgrade(upgrade)
+}
+
+#[tokio::test]
+async fn verify_mode_requires_a_chain_to_the_configured_roots() {
+    let roots = load_roots(&fixture("ca.pem")).expect("ca loads");
+    let c = client(&["leaf", "other"], verifying_tls_upgrade(roots));
+    assert_eq!(fetch(&c).await, Ok(b"ok".to_vec()));
+    // Self-signed, not issued by the test CA.
+    assert_eq!(fetch(&c).await, Err(ClientError::Certificate));
+}
+
+#[test]
+fn verify_mode_settings_need_a_loadable_ca_file() {
+    let settings = |mode, ca_file: Option<String>| TlsSettings {
+        mode,
+        ca_file,
+        on_mismatch: PinMismatch::Refuse,
+    };
+    assert!(settings(TlsMode::Verify, Some(fixture("ca.pem")))
+        .check()
+        .is_ok());
+    assert!(settings(TlsMode::Verify, None).check().is_err());
+    assert!(settings(TlsMode::Verify, Some(fixture("missing.pem")))
+        .check()
+        .is_err());
+    assert!(settings(TlsMode::Tofu, None).check().is_ok());
+}
+
+#[tokio::test]
+async fn tofu_mode_refuses_a_changed_certificate() {
+    let pins = Arc::new(PinStore::new(PinMismatch::Refuse));
+    let c = client(&["other", "other", "leaf"], tofu_tls_upgrade(pins));
+    assert_eq!(fetch(&c).await, Ok(b"ok".to_vec()));
+    assert_eq!(fetch(&c).await, Ok(b"ok".to_vec()));
+    assert_eq!(fetch(&c).await, Err(ClientError::Certificate));
+}
+
+#[tokio::test]
+async fn tofu_mode_can_flag_instead_of_refusing() {
+    let pins = Arc::new(PinStore::new(PinMismatch::Flag));
+    let c = client(&["leaf", "other"], tofu_tls_upgrade(pins));
+    assert_eq!(fetch(&c).await, Ok(b"ok".to_vec()));
+    assert_eq!(fetch(&c).await, Ok(b"ok".to_vec()));
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use gurtd::crawler::client::{ConnectorFn, DynStream, GurtClient};
+use gurtd::crawler::keepalive::ConnPool;
+use gurtd::crawler::resolver::{ResolveError, Resolver};
+use gurtd::indexing::dns::GurtDnsResolver;
+
+/// Mock DNS service: `answers` maps a domain to (status, body). Counts connections and
+/// requests; every connection serves requests until the client closes it.
+struct MockDns {
+    connections: Arc<AtomicUsize>,
+    requests: Arc<AtomicUsize>,
+    resolver: GurtDnsResolver,
+}
+
+fn mock_dns(answers: &[(&str, u16, &str)]) -> MockDns {
+    let answers: Arc<HashMap<String, (u16, String)>> = Arc::new(
+        answers
+            .iter()
+            .map(|(d, code, body)| (d.to_string(), (*code, body.to_string())))
+            .collect(),
+    );
+    let connections = Arc::new(AtomicUsize::new(0));
+    let requests = Arc::new(AtomicUsize::new(0));
+    let connector: Arc<ConnectorFn> = {
+        let connections = connections.clone();
+        let requests = requests.clone();
+        Arc::new(move |_host: &str, _port: u16| {
+            connections.fetch_add(1, Ordering::SeqCst);
+            let (server, client) = tokio::io::duplex(1 << 16);
+            tokio::spawn(serve(server, answers.clone(), requests.clone()));
+            Box::pin(async move { Ok(Box::pin(client) as DynStream) })
+        })
+    };
+    let client = GurtClient::new_test(connector)
+        .with_keepalive(Arc::new(ConnPool::new(Duration::from_secs(5), 1)));
+    MockDns {
+        connections,
+        requests,
+        resolver: GurtDnsResolver::new(client, "dns.real", 4878),
+    }
+}
+
+async fn serve(
+    mut stream: DuplexStream,
+    answers: Arc<HashMap<String, (u16, String)>>,
+    requests: Arc<AtomicUsize>,
+) {
+    if read_message(&mut stream).await.is_none() {
+        return;
+    }
+    let hs = b"GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n";
+    stream.write_all(hs).await.unwrap();
+    while let Some(body) = read_message(&mut stream).await {
+        requests.fetch_add(1, Ordering::SeqCst);
+        let req: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let domain = req["domain"].as_str().unwrap();
+        let (code, body) = answers
+            .get(domain)
+            .cloned()
+            .unwrap_or((404, "{}".to_string()));
+        let resp = format!(
+            "GURT/1.0.0 {} X\r\ncontent-length: {}\r\n\r\n{}",
+            code,
+            body.len(),
+            body
+        );
+        stream.write_all(resp.as_bytes()).await.unwrap();
+    }
+}
+
+/// Head plus content-length body of the next message; None at end of stream.
+async fn read_message(stream: &mut DuplexStream) -> Option<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).await.ok()? == 0 {
+            return None;
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+    let len = head
+        .lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await.ok()?;
+    Some(body)
+}
+
+fn v4(d: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, d))
+}
+
+#[tokio::test]
+async fn follows_cname_chain_over_one_connection_and_caches_the_answer() {
+    let dns = mock_dns(&[
+        (
+            "www.example.real",
+            200,
+            r#"{"records":[{"type":"CNAME","value":"example.real.","ttl":300}]}"#,
+        ),
+        (
+            "example.real",
+            200,
+            r#"{"records":[{"type":"A","value":"10.0.0.1","ttl":300},{"type":"A","value":"10.0.0.2","ttl":300}]}"#,
+        ),
+    ]);
+    assert_eq!(
+        dns.resolver.resolve("WWW.example.real.").await,
+        Ok(vec![v4(1), v4(2)])
+    );
+    assert_eq!(dns.requests.load(Ordering::SeqCst), 2);
+    assert_eq!(dns.connections.load(Ordering::SeqCst), 1);
+
+    assert_eq!(
+        dns.resolver.resolve("www.example.real").await,
+        Ok(vec![v4(1), v4(2)])
+    );
+    assert_eq!(dns.requests.load(Ordering::SeqCst), 2, "served from cache");
+}
+
+#[tokio::test]
+async fn caches_missing_names_and_failures() {
+    let dns = mock_dns(&[("broken.real", 500, "{}")]);
+    assert_eq!(
+        dns.resolver.resolve("missing.real").await,
+        Err(ResolveError::NotFound)
+    );
+    assert_eq!(
+        dns.resolver.resolve("missing.real").await,
+        Err(ResolveError::NotFound)
+    );
+    assert_eq!(
+        dns.resolver.resolve("broken.real").await,
+        Err(ResolveError::Failed)
+    );
+    assert_eq!(
+        dns.resolver.resolve("broken.real").await,
+        Err(ResolveError::Failed)
+    );
+    assert_eq!(dns.requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn rejects_cname_loops() {
+    let dns = mock_dns(&[
+        (
+            "a.real",
+            200,
+            r#"{"records":[{"type":"CNAME","value":"b.real","ttl":300}]}"#,
+        ),
+        (
+            "b.real",
+            200,
+            r#"{"records":[{"type":"CNAME","value":"a.real","ttl":300}]}"#,
+        ),
+    ]);
+    assert_eq!(
+        dns.resolver.resolve("a.real").await,
+        Err(ResolveError::NotFound)
+    );
+}
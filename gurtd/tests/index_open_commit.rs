@@ -33,6 +33,7 @@ fn open_create_commit_refresh_updates_searcher_docs() {
         fetch_time: 1_700_000_000,
         language: "en".into(),
         render_mode: "static".into(),
+        ..Default::default()
     };
     engine.add(doc).expect("add doc");
     engine.commit().expect("commit");
@@ -40,3 +41,117 @@ fn open_create_commit_refresh_updates_searcher_docs() {
 
     assert_eq!(engine.num_docs(), 1);
 }
+
+#[test]
+fn re_adding_same_url_replaces_document() {
+    let engine = TantivyIndexEngine::open_or_create_in_dir(tempdir()).expect("open/create index");
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/".into(),
+            domain: "a.real".into(),
+            content: "first".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine.commit().unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/".into(),
+            domain: "a.real".into(),
+            content: "second".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/".into(),
+            domain: "a.real".into(),
+            content: "third".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine.commit().unwrap();
+    engine.refresh().unwrap();
+    assert_eq!(engine.num_docs(), 1);
+}
+
+#[test]
+fn delete_url_and_domain_remove_documents() {
+    let engine = TantivyIndexEngine::open_or_create_in_dir(tempdir()).expect("open/create index");
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/".into(),
+            domain: "a.real".into(),
+            content: "x".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/gone".into(),
+            domain: "a.real".into(),
+            content: "x".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://b.real/".into(),
+            domain: "b.real".into(),
+            content: "x".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://b.real/more".into(),
+            domain: "b.real".into(),
+            content: "x".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine.commit().unwrap();
+
+    engine.delete_url("gurt://a.real/gone").unwrap();
+    engine.commit().unwrap();
+    engine.refresh().unwrap();
+    assert_eq!(engine.num_docs(), 3);
+
+    engine.delete_domain("B.real").unwrap();
+    engine.commit().unwrap();
+    engine.refresh().unwrap();
+    assert_eq!(engine.num_docs(), 1);
+}
+
+#[test]
+fn index_of_an_older_schema_is_rebuilt() {
+    let dir = tempdir();
+    drop(TantivyIndexEngine::open_or_create_in_dir(&dir).expect("create index"));
+    // Make it look like an index built before `filetype` existed.
+    let meta_path = dir.join("meta.json");
+    let mut meta: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+    meta["schema"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|field| field["name"] != "filetype");
+    std::fs::write(&meta_path, meta.to_string()).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not part of the index").unwrap();
+
+    let engine = TantivyIndexEngine::open_or_create_in_dir(&dir).expect("rebuild index");
+    assert!(engine.rebuilt());
+    assert!(dir.join("notes.txt").exists());
+    engine
+        .add(IndexDocument {
+            url: "gurt://a.real/".into(),
+            domain: "a.real".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine.commit().unwrap();
+    drop(engine);
+
+    let engine = TantivyIndexEngine::open_or_create_in_dir(&dir).expect("reopen index");
+    assert!(!engine.rebuilt());
+    assert_eq!(engine.num_docs(), 1);
+}
@@ -12,6 +12,7 @@ fn tantivy_schema_contains_required_fields() {
     let fetch_time = schema.get_field_name(fields.fetch_time);
     let language = schema.get_field_name(fields.language);
     let render_mode = schema.get_field_name(fields.render_mode);
+    let filetype = schema.get_field_name(fields.filetype);
 
     assert_eq!(url, "url");
     assert_eq!(domain, "domain");
@@ -20,6 +21,7 @@ fn tantivy_schema_contains_required_fields() {
     assert_eq!(fetch_time, "fetch_time");
     assert_eq!(language, "language");
     assert_eq!(render_mode, "render_mode");
+    assert_eq!(filetype, "filetype");
 }
 
 #[test]
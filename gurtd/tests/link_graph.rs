@@ -11,8 +11,11 @@ fn extract_links_from_html() {
         <a href=/relative>rel</a>
       </body></html>
     "#;
-    let links = extract_links(html);
-    assert_eq!(links, vec!["gurt://one/", "gurt://two/a"]);
+    let links = extract_links("gurt://one/page", html);
+    assert_eq!(
+        links,
+        vec!["gurt://one/", "gurt://two/a", "gurt://one/relative"]
+    );
 }
 
 #[test]
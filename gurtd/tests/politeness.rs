@@ -48,3 +48,34 @@ async fn acquire_polite_fast_when_no_delay() {
     // Should complete quickly (<40ms) since no enforced delay
     assert!(elapsed.as_millis() < 40, "elapsed too large: {:?}", elapsed);
 }
+
+#[tokio::test]
+async fn acquire_polite_serializes_crawl_delay_hosts() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // The per-host limit of 3 does not apply under a crawl-delay.
+    let sched = HostScheduler::new(10, 3);
+    let inflight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+    for _ in 0..3 {
+        let s = sched.clone();
+        let inflight = inflight.clone();
+        let peak = peak.clone();
+        handles.push(tokio::spawn(async move {
+            let (_g, _h) = s
+                .acquire_polite("slow.test", Some(Duration::from_millis(5)))
+                .await;
+            let now = inflight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // a fetch that outlasts the crawl-delay
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            inflight.fetch_sub(1, Ordering::SeqCst);
+        }));
+    }
+    for h in handles {
+        h.await.unwrap();
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
@@ -31,7 +31,7 @@ async fn oversized_request_emits_413_response() {
     req.extend_from_slice(b"GET /search HTTP/1.1\r\n");
     // Large header line without terminating CRLFCRLF until after we exceed the cap
     req.extend_from_slice(b"x-fill: ");
-    req.extend(std::iter::repeat(b'a').take(MAX_MESSAGE_BYTES + 1));
+    req.extend(std::iter::repeat_n(b'a', MAX_MESSAGE_BYTES + 1));
     req.extend_from_slice(b"\r\n\r\n");
 
     // Write the oversized request and then read the server's response
@@ -39,8 +39,7 @@ async fn oversized_request_emits_413_response() {
     client.flush().await.unwrap();
 
     // Read response
-    let mut buf = Vec::new();
-    buf.resize(1024, 0);
+    let mut buf = vec![0u8; 1024];
     let n = client.read(&mut buf).await.unwrap();
     let resp = String::from_utf8_lossy(&buf[..n]);
     assert!(
@@ -1,4 +1,4 @@
-use gurtd::query::{parse_query, ParsedQuery, QueryFilters};
+use gurtd::query::parse_query;
 
 #[test]
 fn parses_supported_filters_and_terms() {
@@ -41,3 +41,16 @@ fn empty_or_missing_filter_values_are_ignored() {
     assert_eq!(pq.filters.filetype, None);
     assert_eq!(pq.terms, vec!["rust"]);
 }
+
+#[test]
+fn wildcard_site_enables_subdomain_matching() {
+    let pq = parse_query("docs site:*.Example.Real filetype:.HTML");
+    assert_eq!(pq.filters.site.as_deref(), Some("example.real"));
+    assert!(pq.filters.site_subdomains);
+    assert_eq!(pq.filters.filetype.as_deref(), Some("html"));
+
+    let pq = parse_query("site:example.real");
+    assert_eq!(pq.filters.site.as_deref(), Some("example.real"));
+    assert!(!pq.filters.site_subdomains);
+    assert!(pq.terms.is_empty());
+}
@@ -36,6 +36,7 @@ fn disk_index_persists_across_reopen() {
                 fetch_time: 1_700_000_123,
                 language: "en".into(),
                 render_mode: "static".into(),
+                ..Default::default()
             })
             .expect("add");
         engine.commit().expect("commit");
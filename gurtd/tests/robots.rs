@@ -1,20 +1,14 @@
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use gurtd::crawler::client::{ClientError, DynStream, GurtClient};
+use gurtd::crawler::client::{ClientError, ConnectorFn, DynStream, GurtClient};
 use gurtd::crawler::robots::{is_allowed_with_robots, RobotsTxt};
 
 #[tokio::test]
 async fn robots_fetch_and_allow_deny() {
     let (mut server, client_side) = tokio::io::duplex(1 << 16);
     let shared = Arc::new(Mutex::new(Some(client_side)));
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = {
+    let connector: Arc<ConnectorFn> = {
         let shared = shared.clone();
         Arc::new(move |_host: &str, _port: u16| {
             let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
@@ -57,11 +51,7 @@ async fn robots_fetch_and_allow_deny() {
 async fn robots_absent_defaults_to_allow() {
     let (mut server, client_side) = tokio::io::duplex(1 << 16);
     let shared = Arc::new(Mutex::new(Some(client_side)));
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = {
+    let connector: Arc<ConnectorFn> = {
         let shared = shared.clone();
         Arc::new(move |_host: &str, _port: u16| {
             let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
@@ -57,6 +57,21 @@ fn search_with_query_returns_200_and_placeholder_json() {
     assert!(v["results"].is_array());
 }
 
+#[test]
+fn search_echoes_clamped_page_and_size() {
+    let _g = TEST_MUTEX.lock().unwrap();
+    let resp = handle(make_get("/api/search?q=rust&page=3&size=500")).expect("router ok");
+    assert_eq!(resp.code.as_u16(), 200);
+    let v: Value = serde_json::from_slice(&resp.body).expect("valid json");
+    assert_eq!(v["page"], 3);
+    assert_eq!(v["size"], 50);
+
+    let resp = handle(make_get("/api/search?q=rust&page=0&size=abc")).expect("router ok");
+    let v: Value = serde_json::from_slice(&resp.body).expect("valid json");
+    assert_eq!(v["page"], 1);
+    assert_eq!(v["size"], 10);
+}
+
 #[test]
 fn search_returns_429_when_overloaded() {
     let _g = TEST_MUTEX.lock().unwrap();
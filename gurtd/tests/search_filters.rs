@@ -0,0 +1,143 @@
+use gurtd::index::tantivy::{filetype_from_url, TantivyIndexEngine};
+use gurtd::index::{IndexDocument, IndexEngine};
+use gurtd::query::parse_query;
+use std::path::PathBuf;
+
+fn tempdir() -> PathBuf {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let ns = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mut p = std::env::temp_dir();
+    p.push(format!("gurtd-filters-{}-{:x}", std::process::id(), ns));
+    p
+}
+
+fn doc(url: &str, domain: &str, content: &str) -> IndexDocument {
+    IndexDocument {
+        url: url.into(),
+        domain: domain.into(),
+        title: String::new(),
+        content: content.into(),
+        fetch_time: 1_700_000_000,
+        language: "en".into(),
+        render_mode: "static".into(),
+        ..Default::default()
+    }
+}
+
+fn seeded_engine() -> TantivyIndexEngine {
+    let engine = TantivyIndexEngine::open_or_create_in_dir(tempdir()).expect("open/create index");
+    for d in [
+        doc("gurt://example.real/", "example.real", "rust guide"),
+        doc(
+            "gurt://docs.example.real/manual.pdf",
+            "docs.example.real",
+            "rust manual",
+        ),
+        doc(
+            "gurt://notexample.real/",
+            "notexample.real",
+            "rust elsewhere",
+        ),
+        doc("gurt://other.real/page.htm", "other.real", "rust tutorial"),
+    ] {
+        engine.add(d).unwrap();
+    }
+    engine.commit().unwrap();
+    engine.refresh().unwrap();
+    engine
+}
+
+fn urls(engine: &TantivyIndexEngine, q: &str) -> Vec<String> {
+    let mut out: Vec<String> = engine
+        .search(&parse_query(q), 1, 10)
+        .expect("search ok")
+        .hits
+        .into_iter()
+        .map(|h| h.url)
+        .collect();
+    out.sort();
+    out
+}
+
+#[test]
+fn site_filter_matches_exact_domain() {
+    let engine = seeded_engine();
+    assert_eq!(
+        urls(&engine, "rust site:example.real"),
+        vec!["gurt://example.real/"]
+    );
+}
+
+#[test]
+fn wildcard_site_filter_includes_subdomains_only() {
+    let engine = seeded_engine();
+    assert_eq!(
+        urls(&engine, "rust site:*.example.real"),
+        vec![
+            "gurt://docs.example.real/manual.pdf",
+            "gurt://example.real/"
+        ]
+    );
+}
+
+#[test]
+fn filetype_filter_uses_url_extension() {
+    let engine = seeded_engine();
+    assert_eq!(
+        urls(&engine, "rust filetype:pdf"),
+        vec!["gurt://docs.example.real/manual.pdf"]
+    );
+    assert_eq!(
+        urls(&engine, "rust filetype:html site:other.real"),
+        vec!["gurt://other.real/page.htm"]
+    );
+}
+
+#[test]
+fn filter_only_query_lists_matching_documents() {
+    let engine = seeded_engine();
+    assert_eq!(
+        urls(&engine, "site:notexample.real"),
+        vec!["gurt://notexample.real/"]
+    );
+    assert!(urls(&engine, "").is_empty());
+}
+
+#[test]
+fn filetype_defaults_to_html_for_extensionless_paths() {
+    assert_eq!(filetype_from_url("gurt://a.real/"), "html");
+    assert_eq!(filetype_from_url("gurt://a.real/docs/intro"), "html");
+    assert_eq!(filetype_from_url("gurt://a.real/x/Report.PDF?v=1"), "pdf");
+    assert_eq!(filetype_from_url("gurt://a.real/.hidden"), "html");
+}
+
+#[test]
+fn total_counts_all_matches_across_pages() {
+    let engine = seeded_engine();
+    let first = engine.search(&parse_query("rust"), 1, 3).unwrap();
+    assert_eq!(first.total, 4);
+    assert_eq!(first.hits.len(), 3);
+    let second = engine.search(&parse_query("rust"), 2, 3).unwrap();
+    assert_eq!(second.total, 4);
+    assert_eq!(second.hits.len(), 1);
+}
+
+#[test]
+fn hits_carry_snippet_with_highlighted_terms() {
+    let engine = seeded_engine();
+    let res = engine.search(&parse_query("manual"), 1, 10).unwrap();
+    assert_eq!(res.hits.len(), 1);
+    let hit = &res.hits[0];
+    assert_eq!(hit.snippet, "rust manual");
+    assert_eq!(hit.snippet_highlights, vec![5..11]);
+
+    // Filter-only queries have nothing to highlight but still show an excerpt.
+    let res = engine
+        .search(&parse_query("site:other.real"), 1, 10)
+        .unwrap();
+    assert_eq!(res.hits[0].snippet, "rust tutorial");
+    assert!(res.hits[0].snippet_highlights.is_empty());
+}
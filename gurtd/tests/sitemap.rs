@@ -1,20 +1,14 @@
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use gurtd::crawler::client::{ClientError, DynStream, GurtClient};
-use gurtd::crawler::sitemap::{fetch_sitemap_urls, parse_sitemap_xml};
+use gurtd::crawler::client::{ClientError, ConnectorFn, DynStream, GurtClient};
+use gurtd::crawler::sitemap::{fetch_sitemap_entries, fetch_sitemap_urls, parse_sitemap_xml};
 
 #[tokio::test]
 async fn sitemap_fetch_and_parse_urls() {
     let (mut server, client_side) = tokio::io::duplex(1 << 16);
     let shared = Arc::new(Mutex::new(Some(client_side)));
-    let connector: Arc<
-        dyn Fn(&str, u16) -> Pin<Box<dyn Future<Output = Result<DynStream, ClientError>> + Send>>
-            + Send
-            + Sync,
-    > = {
+    let connector: Arc<ConnectorFn> = {
         let shared = shared.clone();
         Arc::new(move |_host: &str, _port: u16| {
             let cli = shared.lock().unwrap().take().ok_or(ClientError::Connection);
@@ -65,3 +59,60 @@ fn sitemap_parse_empty_when_no_loc() {
     let urls = parse_sitemap_xml(xml);
     assert!(urls.is_empty());
 }
+
+#[tokio::test]
+async fn sitemap_index_leads_to_gzip_sitemap() {
+    use std::io::Write;
+
+    let index = br#"<sitemapindex>
+  <sitemap><loc>/pages.xml.gz</loc></sitemap>
+  <sitemap><loc>gurt://other.real/sitemap.xml</loc></sitemap>
+</sitemapindex>"#
+        .to_vec();
+    let xml = "<urlset><url><loc>gurt://example.real/a?x=1&amp;y=2</loc>\
+               <lastmod>2024-01-02</lastmod></url></urlset>";
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(xml.as_bytes()).unwrap();
+    let pages = gz.finish().unwrap();
+
+    // One connection per sitemap, answered in order: the index, then the gzip sitemap.
+    let mut servers = Vec::new();
+    let mut clients = Vec::new();
+    for body in [index, pages] {
+        let (mut server, client) = tokio::io::duplex(1 << 16);
+        let head = format!(
+            "GURT/1.0.0 101 SWITCHING_PROTOCOLS\r\n\r\n\
+             GURT/1.0.0 200 OK\r\ncontent-length: {}\r\n\r\n",
+            body.len()
+        );
+        server.write_all(head.as_bytes()).await.unwrap();
+        server.write_all(&body).await.unwrap();
+        servers.push(server);
+        clients.push(client);
+    }
+    clients.reverse();
+    let streams = Arc::new(Mutex::new(clients));
+    let hosts = Arc::new(Mutex::new(Vec::new()));
+    let connector: Arc<ConnectorFn> = {
+        let (streams, hosts) = (streams.clone(), hosts.clone());
+        Arc::new(move |host: &str, _port: u16| {
+            hosts.lock().unwrap().push(host.to_string());
+            let cli = streams.lock().unwrap().pop().ok_or(ClientError::Connection);
+            Box::pin(async move { cli.map(|s| Box::pin(s) as DynStream) })
+        })
+    };
+    let mut client = GurtClient::new_test(connector);
+    client.header_read_chunk = 1;
+
+    let fetch = |url: String| {
+        let client = &client;
+        async move { client.fetch_with_retries(&url, 1).await.ok() }
+    };
+    let entries = fetch_sitemap_entries(fetch, "example.real", &[], 10).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].loc, "gurt://example.real/a?x=1&y=2");
+    assert_eq!(entries[0].hint.lastmod, Some(1_704_153_600));
+    // Both sitemaps came from the domain; the off-domain one was never requested.
+    assert_eq!(*hosts.lock().unwrap(), vec!["example.real", "example.real"]);
+    drop(servers);
+}
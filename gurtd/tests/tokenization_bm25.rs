@@ -34,6 +34,7 @@ fn stopwords_are_removed_in_query() {
             fetch_time: 1_700_000_000,
             language: "en".into(),
             render_mode: "static".into(),
+            ..Default::default()
         })
         .unwrap();
     engine.commit().unwrap();
@@ -44,7 +45,7 @@ fn stopwords_are_removed_in_query() {
         terms: vec!["THE".into(), "and".into(), "RUST".into(), "of".into()],
         filters: QueryFilters::default(),
     };
-    let hits = engine.search(&pq, 1, 10).expect("search ok");
+    let hits = engine.search(&pq, 1, 10).expect("search ok").hits;
     assert!(!hits.is_empty(), "should match after removing stopwords");
 }
 
@@ -63,6 +64,7 @@ fn bm25_prefers_higher_tf() {
             fetch_time: 1_700_000_001,
             language: "en".into(),
             render_mode: "static".into(),
+            ..Default::default()
         })
         .unwrap();
 
@@ -76,6 +78,7 @@ fn bm25_prefers_higher_tf() {
             fetch_time: 1_700_000_002,
             language: "en".into(),
             render_mode: "static".into(),
+            ..Default::default()
         })
         .unwrap();
 
@@ -86,7 +89,7 @@ fn bm25_prefers_higher_tf() {
         terms: vec!["rust".into()],
         filters: QueryFilters::default(),
     };
-    let hits = engine.search(&pq, 1, 10).expect("search ok");
+    let hits = engine.search(&pq, 1, 10).expect("search ok").hits;
     assert!(hits.len() >= 2);
     // Expect first score >= second due to higher term frequency
     assert!(
@@ -95,3 +98,36 @@ fn bm25_prefers_higher_tf() {
         hits
     );
 }
+
+#[test]
+fn heading_match_outranks_body_match() {
+    let dir = tempdir();
+    let engine = TantivyIndexEngine::open_or_create_in_dir(&dir).expect("open/create index");
+    engine
+        .add(IndexDocument {
+            url: "gurt://example.real/body".into(),
+            domain: "example.real".into(),
+            content: "notes about crabs and other things".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine
+        .add(IndexDocument {
+            url: "gurt://example.real/heading".into(),
+            domain: "example.real".into(),
+            content: "crabs notes about other things".into(),
+            headings: "crabs".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    engine.commit().unwrap();
+    engine.refresh().unwrap();
+
+    let pq = ParsedQuery {
+        terms: vec!["crabs".into()],
+        filters: QueryFilters::default(),
+    };
+    let hits = engine.search(&pq, 1, 10).expect("search ok").hits;
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].url, "gurt://example.real/heading");
+}
@@ -39,7 +39,7 @@ fn assets_route_serves_lua_file() {
         .unwrap_or("");
     assert_eq!(ct, "text/lua");
     let body = String::from_utf8_lossy(&resp.body);
-    assert!(body.contains("network.fetch('/api/search?q="));
+    assert!(body.contains("'/search?q='"));
 }
 
 // No external CSS route is required; inline utility styles are embedded.
@@ -72,3 +72,13 @@ fn domains_route_serves_form() {
     assert!(body.contains("id=\"domain-form\""));
     assert!(body.contains("/assets/domains.lua"));
 }
+
+#[test]
+fn search_ssr_renders_pager() {
+    let resp = handle(get("/search?q=nothing-matches-this&page=2")).expect("ok");
+    assert_eq!(resp.code.as_u16(), 200);
+    let body = String::from_utf8_lossy(&resp.body);
+    assert!(body.contains("id=\"pager\""));
+    // Past the first page there is always a way back.
+    assert!(body.contains("/search?q=nothing%2Dmatches%2Dthis&page=1&size=10"));
+}
@@ -19,6 +19,13 @@ local function render(items)
             style = 'text-sm text-[#9ca3af] mt-1',
             text = url
         }))
+        local snippet = tostring(item.snippet or '')
+        if snippet ~= '' then
+            -- snippet is escaped server-side; <b> marks the matched terms
+            local p = gurt.create('p', { style = 'text-sm text-[#d9d9d9] mt-1' })
+            p.innerHTML = snippet
+            li:append(p)
+        end
         list:append(li)
     end
 end